@@ -0,0 +1,178 @@
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::prelude::*;
+use bevy::render::{Extract, ExtractSchedule, RenderApp};
+use openxr as xr;
+
+use crate::graphics::XrPreferdBlendMode;
+use crate::resources::{XrEnvironmentBlendMode, XrInstance, XrViewConfigurationType};
+use crate::xr_init::{xr_only, XrSessionSetup};
+use crate::xr_input::xr_camera::XrCameraType;
+
+/// How the rendered frame is combined with the real world. Change it with [`XrBlendMode::set`],
+/// the new mode is used from the next submitted frame on.
+#[derive(Resource, Clone, Debug)]
+pub struct XrBlendMode {
+    supported: Vec<XrPreferdBlendMode>,
+    current: XrPreferdBlendMode,
+    pub(crate) requested: XrPreferdBlendMode,
+    requested_unavailable: bool,
+    /// Sets the clear color and tonemapping of the XR cameras to match the mode, so the real
+    /// world shows through the background: black for additive and transparent for alpha blended
+    /// displays. Going back to opaque restores the defaults.
+    pub configure_cameras: bool,
+}
+
+impl Default for XrBlendMode {
+    fn default() -> Self {
+        Self {
+            supported: Vec::new(),
+            current: XrPreferdBlendMode::Opaque,
+            requested: XrPreferdBlendMode::Opaque,
+            requested_unavailable: false,
+            configure_cameras: true,
+        }
+    }
+}
+
+impl XrBlendMode {
+    /// The modes the system supports for the primary view configuration.
+    pub fn supported(&self) -> &[XrPreferdBlendMode] {
+        &self.supported
+    }
+
+    pub fn current(&self) -> XrPreferdBlendMode {
+        self.current
+    }
+
+    /// The mode the session was requested with, see `DefaultXrPlugins::prefered_blend_mode`.
+    pub fn requested(&self) -> XrPreferdBlendMode {
+        self.requested
+    }
+
+    /// Whether the system doesn't support [`XrBlendMode::requested`] and the session started with
+    /// a fallback instead, which is then the [`XrBlendMode::current`] mode.
+    pub fn requested_unavailable(&self) -> bool {
+        self.requested_unavailable
+    }
+
+    /// Switches to `mode`, fails with `ERROR_ENVIRONMENT_BLEND_MODE_UNSUPPORTED` when the system
+    /// doesn't support it.
+    pub fn set(&mut self, mode: XrPreferdBlendMode) -> xr::Result<()> {
+        if !self.supported.contains(&mode) {
+            return Err(xr::sys::Result::ERROR_ENVIRONMENT_BLEND_MODE_UNSUPPORTED);
+        }
+        self.current = mode;
+        Ok(())
+    }
+}
+
+pub struct XrBlendModePlugin;
+
+impl Plugin for XrBlendModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrBlendMode>();
+        app.add_systems(XrSessionSetup, setup_blend_mode);
+        app.add_systems(
+            PostUpdate,
+            (apply_blend_mode, configure_xr_cameras)
+                .chain()
+                .run_if(xr_only()),
+        );
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.add_systems(ExtractSchedule, extract_blend_mode);
+    }
+}
+
+fn setup_blend_mode(
+    mut blend_mode: ResMut<XrBlendMode>,
+    instance: Res<XrInstance>,
+    view_type: Res<XrViewConfigurationType>,
+    environment_blend_mode: Res<XrEnvironmentBlendMode>,
+) {
+    let supported = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .and_then(|system| instance.enumerate_environment_blend_modes(system, **view_type));
+    blend_mode.supported = match supported {
+        Ok(supported) => supported
+            .into_iter()
+            .filter_map(XrPreferdBlendMode::from_environment_blend_mode)
+            .collect(),
+        Err(err) => {
+            warn!("Failed to enumerate environment blend modes: {err}");
+            Vec::new()
+        }
+    };
+    // A recreated session keeps the mode the app switched to
+    if let Some(current) = XrPreferdBlendMode::from_environment_blend_mode(**environment_blend_mode)
+    {
+        blend_mode.current = current;
+    }
+    let requested = blend_mode.requested;
+    blend_mode.requested_unavailable = match blend_mode.supported.is_empty() {
+        true => blend_mode.current != requested,
+        false => !blend_mode.supported.contains(&requested),
+    };
+}
+
+/// Hands a changed mode to the frame loop.
+fn apply_blend_mode(
+    mut commands: Commands,
+    blend_mode: Res<XrBlendMode>,
+    environment_blend_mode: Res<XrEnvironmentBlendMode>,
+) {
+    let mode = blend_mode.current.environment_blend_mode();
+    if **environment_blend_mode != mode {
+        info!(
+            "Switching to the {:?} environment blend mode",
+            blend_mode.current
+        );
+        commands.insert_resource(XrEnvironmentBlendMode(mode));
+    }
+}
+
+fn configure_xr_cameras(
+    blend_mode: Res<XrBlendMode>,
+    mut applied: Local<Option<XrPreferdBlendMode>>,
+    mut cameras: Query<(&mut Camera, &mut Tonemapping, Ref<XrCameraType>)>,
+) {
+    if !blend_mode.configure_cameras {
+        return;
+    }
+    let current = blend_mode.current;
+    // Cameras are left alone in opaque mode unless another mode has to be undone
+    let switched =
+        *applied != Some(current) && (current != XrPreferdBlendMode::Opaque || applied.is_some());
+    *applied = Some(current);
+    let (clear_color, tonemapping) = match current {
+        XrPreferdBlendMode::Opaque => (ClearColorConfig::Default, Tonemapping::default()),
+        // Anything not black shows up as light on top of the real world
+        XrPreferdBlendMode::Additive => (ClearColorConfig::Custom(Color::BLACK), Tonemapping::None),
+        XrPreferdBlendMode::AlphaBlend => {
+            (ClearColorConfig::Custom(Color::NONE), Tonemapping::None)
+        }
+    };
+    for (mut camera, mut camera_tonemapping, camera_type) in &mut cameras {
+        if !matches!(*camera_type, XrCameraType::Xr(_)) {
+            continue;
+        }
+        let new_camera = camera_type.is_added() && current != XrPreferdBlendMode::Opaque;
+        if !switched && !new_camera {
+            continue;
+        }
+        camera.clear_color = clear_color.clone();
+        *camera_tonemapping = tonemapping;
+    }
+}
+
+fn extract_blend_mode(
+    mut commands: Commands,
+    environment_blend_mode: Extract<Option<Res<XrEnvironmentBlendMode>>>,
+) {
+    if let Some(mode) = environment_blend_mode.as_ref() {
+        if mode.is_changed() {
+            commands.insert_resource(XrEnvironmentBlendMode(***mode));
+        }
+    }
+}
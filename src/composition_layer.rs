@@ -0,0 +1,170 @@
+use std::ffi::c_void;
+use std::ptr;
+
+use bevy::prelude::*;
+use bevy::render::extract_resource::ExtractResource;
+use openxr as xr;
+use xr::sys;
+
+/// Per-layer composition settings, extracted to the render world and applied when the frame ends.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, ExtractResource)]
+pub struct XrLayerSettings {
+    /// The projection layer the eye cameras render to.
+    pub projection: XrCompositionLayerSettings,
+    /// The `XR_FB_passthrough` layer, if passthrough is running.
+    pub passthrough: XrCompositionLayerSettings,
+    /// The projection layer of the first person observer view, if one is active.
+    pub secondary: XrCompositionLayerSettings,
+}
+
+/// How the compositor blends a single layer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct XrCompositionLayerSettings {
+    /// Overrides the flags of the layer. `None` keeps the defaults, which blend with the source
+    /// alpha whenever there is a layer below.
+    pub flags: Option<xr::CompositionLayerFlags>,
+    /// Needs `XR_KHR_composition_layer_color_scale_bias`, ignored without it. Enable it with
+    /// [`XrExtensions::enable_color_scale_bias`].
+    ///
+    /// [`XrExtensions::enable_color_scale_bias`]: crate::graphics::extensions::XrExtensions::enable_color_scale_bias
+    pub color_scale_bias: Option<XrColorScaleBias>,
+    /// Needs `XR_FB_composition_layer_alpha_blend`, ignored without it.
+    pub alpha_blend: Option<XrAlphaBlend>,
+}
+
+/// Scales and offsets the color of a layer in the compositor, `color * scale + bias`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XrColorScaleBias {
+    /// Linear RGBA.
+    pub scale: Vec4,
+    /// Linear RGBA.
+    pub bias: Vec4,
+}
+
+impl Default for XrColorScaleBias {
+    fn default() -> Self {
+        Self {
+            scale: Vec4::ONE,
+            bias: Vec4::ZERO,
+        }
+    }
+}
+
+impl XrColorScaleBias {
+    /// Fades the layer towards `color`, from untouched at `0.0` to only `color` at `1.0`. The
+    /// compositor keeps doing this while the app is busy, e.g. fading to black for a loading screen.
+    pub fn fade(color: Color, amount: f32) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        Self {
+            scale: Vec4::splat(1.0 - amount),
+            bias: Vec4::from(color.as_linear_rgba_f32()) * amount,
+        }
+    }
+}
+
+/// Custom blend factors of a layer with the layers below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XrAlphaBlend {
+    pub src_factor_color: sys::BlendFactorFB,
+    pub dst_factor_color: sys::BlendFactorFB,
+    pub src_factor_alpha: sys::BlendFactorFB,
+    pub dst_factor_alpha: sys::BlendFactorFB,
+}
+
+impl Default for XrAlphaBlend {
+    /// Premultiplied alpha blending.
+    fn default() -> Self {
+        Self::new(
+            sys::BlendFactorFB::ONE,
+            sys::BlendFactorFB::ONE_MINUS_SRC_ALPHA,
+        )
+    }
+}
+
+impl XrAlphaBlend {
+    /// Uses the same factors for color and alpha.
+    pub fn new(src_factor: sys::BlendFactorFB, dst_factor: sys::BlendFactorFB) -> Self {
+        Self {
+            src_factor_color: src_factor,
+            dst_factor_color: dst_factor,
+            src_factor_alpha: src_factor,
+            dst_factor_alpha: dst_factor,
+        }
+    }
+}
+
+impl XrLayerSettings {
+    /// Drops the settings the enabled extensions can't apply.
+    pub(crate) fn supported(mut self, exts: &xr::InstanceExtensions) -> Self {
+        for layer in [
+            &mut self.projection,
+            &mut self.passthrough,
+            &mut self.secondary,
+        ] {
+            if exts.khr_composition_layer_color_scale_bias.is_none() {
+                layer.color_scale_bias = None;
+            }
+            if exts.fb_composition_layer_alpha_blend.is_none() {
+                layer.alpha_blend = None;
+            }
+        }
+        self
+    }
+}
+
+impl XrCompositionLayerSettings {
+    pub(crate) fn chain(&self) -> LayerChain {
+        LayerChain {
+            color_scale_bias: self.color_scale_bias.map(|settings| {
+                sys::CompositionLayerColorScaleBiasKHR {
+                    ty: sys::CompositionLayerColorScaleBiasKHR::TYPE,
+                    next: ptr::null(),
+                    color_scale: color4f(settings.scale),
+                    color_bias: color4f(settings.bias),
+                }
+            }),
+            alpha_blend: self
+                .alpha_blend
+                .map(|settings| sys::CompositionLayerAlphaBlendFB {
+                    ty: sys::CompositionLayerAlphaBlendFB::TYPE,
+                    next: ptr::null_mut(),
+                    src_factor_color: settings.src_factor_color,
+                    dst_factor_color: settings.dst_factor_color,
+                    src_factor_alpha: settings.src_factor_alpha,
+                    dst_factor_alpha: settings.dst_factor_alpha,
+                }),
+        }
+    }
+}
+
+/// The extension structs chained to a layer, they have to stay in place until the frame ended.
+pub(crate) struct LayerChain {
+    color_scale_bias: Option<sys::CompositionLayerColorScaleBiasKHR>,
+    alpha_blend: Option<sys::CompositionLayerAlphaBlendFB>,
+}
+
+impl LayerChain {
+    /// Links the structs and returns the `next` pointer of the layer. The chain must not move
+    /// after this.
+    pub(crate) fn head(&mut self) -> *const c_void {
+        let mut next: *const c_void = ptr::null();
+        if let Some(color_scale_bias) = &mut self.color_scale_bias {
+            color_scale_bias.next = next;
+            next = color_scale_bias as *const _ as *const c_void;
+        }
+        if let Some(alpha_blend) = &mut self.alpha_blend {
+            alpha_blend.next = next as *mut c_void;
+            next = alpha_blend as *const _ as *const c_void;
+        }
+        next
+    }
+}
+
+fn color4f(color: Vec4) -> sys::Color4f {
+    sys::Color4f {
+        r: color.x,
+        g: color.y,
+        b: color.z,
+        a: color.w,
+    }
+}
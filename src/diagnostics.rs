@@ -0,0 +1,186 @@
+use std::ptr;
+
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, RegisterDiagnostic,
+};
+use bevy::prelude::*;
+use openxr as xr;
+use xr::sys;
+
+use crate::resources::{XrFrameState, XrFrameTimings, XrInstance, XrSession};
+use crate::xr_init::{xr_only, XrSessionSetup};
+
+/// Adds XR frame timings to the [`DiagnosticsStore`], so they show up in `LogDiagnosticsPlugin`.
+pub struct XrDiagnosticsPlugin;
+
+impl XrDiagnosticsPlugin {
+    /// Display period predicted by `xrWaitFrame`, in milliseconds.
+    pub const PREDICTED_DISPLAY_PERIOD: DiagnosticPath =
+        DiagnosticPath::const_new("xr/predicted_display_period");
+    /// Time spent blocked in `xrWaitFrame`, in milliseconds.
+    pub const WAIT_FRAME: DiagnosticPath = DiagnosticPath::const_new("xr/wait_frame");
+    /// 1 for frames the runtime asked us not to render, averaging to the ratio of skipped frames.
+    pub const SKIPPED_FRAMES: DiagnosticPath = DiagnosticPath::const_new("xr/skipped_frames");
+    /// Time from releasing the swapchain image to `xrEndFrame` returning, in milliseconds.
+    pub const END_FRAME: DiagnosticPath = DiagnosticPath::const_new("xr/end_frame");
+}
+
+impl Plugin for XrDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(Self::PREDICTED_DISPLAY_PERIOD).with_suffix("ms"));
+        app.register_diagnostic(Diagnostic::new(Self::WAIT_FRAME).with_suffix("ms"));
+        app.register_diagnostic(Diagnostic::new(Self::SKIPPED_FRAMES));
+        app.register_diagnostic(Diagnostic::new(Self::END_FRAME).with_suffix("ms"));
+        app.add_systems(XrSessionSetup, setup_performance_metrics);
+        app.add_systems(
+            Update,
+            (
+                frame_timing_diagnostics,
+                performance_metrics_diagnostics
+                    .run_if(resource_exists::<XrPerformanceMetricsCounters>),
+            )
+                .run_if(xr_only()),
+        );
+    }
+}
+
+/// Adds the timings measured since the last run. They are taken out of [`XrFrameTimings`], so a
+/// frame that wasn't waited on or ended doesn't count the previous one again.
+fn frame_timing_diagnostics(
+    mut diagnostics: Diagnostics,
+    frame_state: Res<XrFrameState>,
+    frame_timings: Res<XrFrameTimings>,
+) {
+    let (wait_frame, end_frame, skipped) = {
+        let mut timings = frame_timings.lock().unwrap();
+        (
+            timings.wait_frame.take(),
+            timings.end_frame.take(),
+            timings.skipped,
+        )
+    };
+    if let Some(wait_frame) = wait_frame {
+        let period = frame_state
+            .lock()
+            .unwrap()
+            .predicted_display_period
+            .as_nanos();
+        diagnostics.add_measurement(&XrDiagnosticsPlugin::PREDICTED_DISPLAY_PERIOD, || {
+            period as f64 / 1_000_000.0
+        });
+        diagnostics.add_measurement(&XrDiagnosticsPlugin::WAIT_FRAME, || {
+            wait_frame.as_secs_f64() * 1000.0
+        });
+        diagnostics.add_measurement(&XrDiagnosticsPlugin::SKIPPED_FRAMES, || {
+            if skipped {
+                1.0
+            } else {
+                0.0
+            }
+        });
+    }
+    if let Some(end_frame) = end_frame {
+        diagnostics.add_measurement(&XrDiagnosticsPlugin::END_FRAME, || {
+            end_frame.as_secs_f64() * 1000.0
+        });
+    }
+}
+
+/// Compositor counters exposed by `XR_META_performance_metrics`, each registered as a diagnostic
+/// under `xr/perfmetrics_meta/...`.
+#[derive(Resource)]
+struct XrPerformanceMetricsCounters(Vec<(DiagnosticPath, sys::Path)>);
+
+fn setup_performance_metrics(
+    mut commands: Commands,
+    mut store: ResMut<DiagnosticsStore>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    let Some(metrics) = instance.exts().meta_performance_metrics else {
+        return;
+    };
+    let state = sys::PerformanceMetricsStateMETA {
+        ty: sys::PerformanceMetricsStateMETA::TYPE,
+        next: ptr::null(),
+        enabled: true.into(),
+    };
+    let result = unsafe { (metrics.set_performance_metrics_state)(session.as_raw(), &state) };
+    if result.into_raw() < 0 {
+        warn!("Failed to enable performance metrics: {result}");
+        return;
+    }
+    let mut count = 0;
+    let result = unsafe {
+        (metrics.enumerate_performance_metrics_counter_paths)(
+            instance.as_raw(),
+            0,
+            &mut count,
+            ptr::null_mut(),
+        )
+    };
+    if result.into_raw() < 0 {
+        warn!("Failed to enumerate performance metrics counters: {result}");
+        return;
+    }
+    let mut paths = vec![sys::Path::NULL; count as usize];
+    let result = unsafe {
+        (metrics.enumerate_performance_metrics_counter_paths)(
+            instance.as_raw(),
+            count,
+            &mut count,
+            paths.as_mut_ptr(),
+        )
+    };
+    if result.into_raw() < 0 {
+        warn!("Failed to enumerate performance metrics counters: {result}");
+        return;
+    }
+    paths.truncate(count as usize);
+
+    let mut counters = Vec::with_capacity(paths.len());
+    for path in paths {
+        let Ok(name) = instance.path_to_string(path) else {
+            continue;
+        };
+        // Counter paths start with a slash, e.g. "/perfmetrics_meta/compositor/frametime"
+        let diagnostic_path = DiagnosticPath::new(format!("xr{name}"));
+        store.add(Diagnostic::new(diagnostic_path.clone()));
+        counters.push((diagnostic_path, path));
+    }
+    info!("Tracking {} performance metrics counters", counters.len());
+    commands.insert_resource(XrPerformanceMetricsCounters(counters));
+}
+
+fn performance_metrics_diagnostics(
+    mut diagnostics: Diagnostics,
+    counters: Res<XrPerformanceMetricsCounters>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    let Some(metrics) = instance.exts().meta_performance_metrics else {
+        return;
+    };
+    for (diagnostic_path, path) in &counters.0 {
+        let mut counter = sys::PerformanceMetricsCounterMETA {
+            ty: sys::PerformanceMetricsCounterMETA::TYPE,
+            next: ptr::null_mut(),
+            counter_flags: xr::PerformanceMetricsCounterFlagsMETA::EMPTY,
+            counter_unit: xr::PerformanceMetricsCounterUnitMETA::GENERIC,
+            uint_value: 0,
+            float_value: 0.0,
+        };
+        let result = unsafe {
+            (metrics.query_performance_metrics_counter)(session.as_raw(), *path, &mut counter)
+        };
+        if result.into_raw() < 0 {
+            continue;
+        }
+        let flags = counter.counter_flags;
+        if flags.contains(xr::PerformanceMetricsCounterFlagsMETA::FLOAT_VALUE_VALID) {
+            diagnostics.add_measurement(diagnostic_path, || counter.float_value as f64);
+        } else if flags.contains(xr::PerformanceMetricsCounterFlagsMETA::UINT_VALUE_VALID) {
+            diagnostics.add_measurement(diagnostic_path, || counter.uint_value as f64);
+        }
+    }
+}
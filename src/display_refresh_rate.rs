@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup};
+
+/// How far, in Hz, a requested rate may be from a supported one.
+const REFRESH_RATE_EPSILON: f32 = 0.1;
+
+/// Sent when the runtime switches the display refresh rate, either because the app asked for it
+/// through [`XrDisplayRefreshRate::request`] or on its own.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct XrDisplayRefreshRateChanged {
+    pub from: f32,
+    pub to: f32,
+}
+
+/// Refresh rates of the headset display, in Hz, as reported by `XR_FB_display_refresh_rate`.
+/// The extension is opt-in, enable it with `XrExtensions::enable_fb_display_refresh_rate`.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct XrDisplayRefreshRate {
+    supported: Vec<f32>,
+    current: Option<f32>,
+    requested: Option<f32>,
+    predicted_display_period: Duration,
+}
+
+impl XrDisplayRefreshRate {
+    /// The rates the runtime can switch to, empty when the extension is not available.
+    pub fn supported(&self) -> &[f32] {
+        &self.supported
+    }
+
+    /// The active refresh rate, if the runtime reports it.
+    pub fn current(&self) -> Option<f32> {
+        self.current
+    }
+
+    /// Asks the runtime to switch to `rate`, e.g. 72.0, 90.0 or 120.0. The change is not
+    /// immediate, [`XrDisplayRefreshRateChanged`] is sent once the runtime applied it. A recreated
+    /// session is asked for the same rate again.
+    pub fn request(&mut self, rate: f32) {
+        self.requested = Some(rate);
+    }
+
+    /// Time between two displayed frames as predicted by the last `xrWaitFrame`. This follows the
+    /// refresh rate even when the extension is missing.
+    pub fn predicted_display_period(&self) -> Duration {
+        self.predicted_display_period
+    }
+}
+
+pub struct XrDisplayRefreshRatePlugin;
+
+impl Plugin for XrDisplayRefreshRatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrDisplayRefreshRate>();
+        app.add_systems(XrSessionSetup, setup_display_refresh_rate);
+        app.add_systems(
+            PreUpdate,
+            update_display_refresh_rate
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+        app.add_systems(
+            PostUpdate,
+            request_display_refresh_rate
+                .run_if(xr_only())
+                .run_if(resource_changed::<XrDisplayRefreshRate>),
+        );
+    }
+}
+
+fn setup_display_refresh_rate(
+    mut refresh_rate: ResMut<XrDisplayRefreshRate>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    if instance.exts().fb_display_refresh_rate.is_none() {
+        return;
+    }
+    // Changes the resource, so the requested rate is sent to a recreated session again
+    refresh_rate.supported = match session.enumerate_display_refresh_rates() {
+        Ok(rates) => rates,
+        Err(err) => {
+            warn!("Failed to enumerate display refresh rates: {err}");
+            Vec::new()
+        }
+    };
+    refresh_rate.current = session.get_display_refresh_rate().ok();
+    info!(
+        "Display refresh rate {:?}, supported {:?}",
+        refresh_rate.current, refresh_rate.supported
+    );
+}
+
+fn update_display_refresh_rate(
+    mut refresh_rate: ResMut<XrDisplayRefreshRate>,
+    mut changed: EventReader<XrDisplayRefreshRateChanged>,
+    frame_state: Res<XrFrameState>,
+) {
+    let period = frame_state
+        .lock()
+        .unwrap()
+        .predicted_display_period
+        .as_nanos();
+    let period = Duration::from_nanos(period.max(0) as u64);
+    // Only touch the resource when something changed so requests are not re-sent every frame
+    if refresh_rate.predicted_display_period != period {
+        refresh_rate
+            .bypass_change_detection()
+            .predicted_display_period = period;
+    }
+    if let Some(event) = changed.read().last() {
+        refresh_rate.bypass_change_detection().current = Some(event.to);
+    }
+}
+
+fn request_display_refresh_rate(
+    refresh_rate: Res<XrDisplayRefreshRate>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    let Some(rate) = refresh_rate.requested else {
+        return;
+    };
+    if instance.exts().fb_display_refresh_rate.is_none() {
+        warn!("Can't request a {rate}Hz refresh rate, XR_FB_display_refresh_rate is not enabled");
+        return;
+    }
+    let Some(rate) = match_refresh_rate(&refresh_rate.supported, rate) else {
+        warn!(
+            "Refresh rate {rate}Hz is not supported, expected one of {:?}",
+            refresh_rate.supported
+        );
+        return;
+    };
+    if let Err(err) = session.request_display_refresh_rate(rate) {
+        warn!("Failed to request a {rate}Hz refresh rate: {err}");
+    }
+}
+
+/// The supported rate closest to `rate`, if it is within [`REFRESH_RATE_EPSILON`]. Runtimes
+/// report rates like 89.99999, so the exact value can't be compared.
+fn match_refresh_rate(supported: &[f32], rate: f32) -> Option<f32> {
+    supported
+        .iter()
+        .copied()
+        .filter(|supported| (supported - rate).abs() < REFRESH_RATE_EPSILON)
+        .min_by(|a, b| (a - rate).abs().total_cmp(&(b - rate).abs()))
+}
+
+/// Keeps the [`Time<Fixed>`] timestep equal to the headset's display period, so physics steps
+/// once per displayed frame. Add it to `PreUpdate` after [`xr_begin_frame`] to opt in.
+pub fn sync_fixed_timestep(
+    refresh_rate: Res<XrDisplayRefreshRate>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    let period = refresh_rate.predicted_display_period();
+    if !period.is_zero() && fixed_time.timestep() != period {
+        fixed_time.set_timestep(period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: [f32; 4] = [72.0, 80.0, 89.99999, 120.00001];
+
+    #[test]
+    fn matches_rates_within_epsilon() {
+        assert_eq!(match_refresh_rate(&SUPPORTED, 72.0), Some(72.0));
+        assert_eq!(match_refresh_rate(&SUPPORTED, 90.0), Some(89.99999));
+        assert_eq!(match_refresh_rate(&SUPPORTED, 120.0), Some(120.00001));
+        assert_eq!(match_refresh_rate(&SUPPORTED, 80.05), Some(80.0));
+        assert_eq!(match_refresh_rate(&SUPPORTED, 79.95), Some(80.0));
+    }
+
+    #[test]
+    fn rejects_rates_outside_epsilon() {
+        assert_eq!(match_refresh_rate(&SUPPORTED, 60.0), None);
+        assert_eq!(match_refresh_rate(&SUPPORTED, 80.2), None);
+        assert_eq!(match_refresh_rate(&SUPPORTED, 90.5), None);
+        assert_eq!(match_refresh_rate(&[], 90.0), None);
+    }
+
+    #[test]
+    fn picks_the_closest_rate() {
+        assert_eq!(match_refresh_rate(&[90.08, 90.02], 90.0), Some(90.02));
+        assert_eq!(match_refresh_rate(&[90.02, 90.08], 90.0), Some(90.02));
+    }
+}
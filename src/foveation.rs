@@ -0,0 +1,324 @@
+use std::{mem, ptr};
+
+use ash::vk;
+use bevy::prelude::*;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use openxr as xr;
+use xr::sys;
+
+use crate::resources::{XrInstance, XrSession, XrSwapchain, XrViewConfigurationType};
+use crate::xr_init::{xr_only, XrSessionSetup};
+
+/// Foveation strength, from no foveation to the most aggressive fixed profile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XrFoveationLevel {
+    #[default]
+    Off,
+    Low,
+    Medium,
+    High,
+    /// [`XrFoveationLevel::High`] with the foveation center moved up, which hides the reduced
+    /// resolution in the lower part of the lenses where users rarely look.
+    HighTop,
+}
+
+/// The foveation the app asks for. Change it at any time, the closest supported mode is applied
+/// to the swapchain and reported through [`XrFoveationMode`].
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrFoveation {
+    pub level: XrFoveationLevel,
+    /// Let the runtime lower the level when the app has GPU headroom.
+    pub dynamic: bool,
+    /// Follow the user's gaze instead of the lens center. Only supported through
+    /// `XR_VARJO_foveated_rendering`, which needs the session to use
+    /// [`XrViewConfiguration::QuadVarjo`]. The view configuration can't change after startup, so
+    /// with any other one this falls back to the fixed profile with a warning.
+    ///
+    /// [`XrViewConfiguration::QuadVarjo`]: crate::graphics::XrViewConfiguration::QuadVarjo
+    pub eye_tracked: bool,
+}
+
+/// The foveation that actually took effect.
+#[derive(Resource, ExtractResource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrFoveationMode {
+    Off,
+    /// An `XR_FB_foveation` level profile is written into the fragment density map of each
+    /// swapchain image, but no render pass attaches it, so everything is still rendered at full
+    /// resolution. wgpu can't attach a fragment density map to Bevy's passes.
+    FbDensityMapUnused {
+        level: XrFoveationLevel,
+        dynamic: bool,
+    },
+    /// Gaze driven foveation through the Varjo quad view configuration, the focus views follow
+    /// the gaze. They are rendered into the same swapchain as the context views, which is sized
+    /// for the largest view, so the context views are rendered at that resolution too.
+    VarjoQuadViews,
+    /// Foveation was requested but the runtime exposes none of the needed extensions.
+    Unsupported,
+}
+
+impl XrFoveationMode {
+    /// Whether an `XR_FB_foveation` profile is applied to the swapchain.
+    pub fn has_fb_profile(&self) -> bool {
+        matches!(self, XrFoveationMode::FbDensityMapUnused { .. })
+    }
+}
+
+/// The fragment density map `XR_FB_foveation_vulkan` attached to a swapchain image.
+#[derive(Clone, Copy, Debug)]
+pub struct XrFoveationDensityMap {
+    pub image: vk::Image,
+    pub size: UVec2,
+}
+
+/// Applies [`XrFoveation`] and reports the outcome as [`XrFoveationMode`].
+///
+/// `XR_FB_foveation` has no effect on Vulkan in this release: the profile only reaches the
+/// fragment density maps, which none of the render passes attach. Gaze driven foveation through
+/// [`XrFoveationMode::VarjoQuadViews`] is the only mode that changes what gets rendered.
+pub struct XrFoveationPlugin;
+
+impl Plugin for XrFoveationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrFoveation>();
+        app.insert_resource(XrFoveationMode::Off);
+        app.add_plugins(ExtractResourcePlugin::<XrFoveationMode>::default());
+        app.add_systems(XrSessionSetup, reapply_foveation);
+        app.add_systems(
+            PostUpdate,
+            apply_foveation
+                .run_if(xr_only())
+                .run_if(resource_changed::<XrFoveation>),
+        );
+    }
+}
+
+/// A new session comes with a new swapchain that has no profile applied yet.
+fn reapply_foveation(mut foveation: ResMut<XrFoveation>, mut mode: ResMut<XrFoveationMode>) {
+    *mode = XrFoveationMode::Off;
+    foveation.set_changed();
+}
+
+fn resolve_mode(
+    instance: &XrInstance,
+    view_type: xr::ViewConfigurationType,
+    swapchain: &XrSwapchain,
+    foveation: &XrFoveation,
+) -> XrFoveationMode {
+    let exts = instance.exts();
+    if foveation.eye_tracked
+        && view_type == xr::ViewConfigurationType::PRIMARY_QUAD_VARJO
+        && supports_varjo_foveated_rendering(instance)
+    {
+        return XrFoveationMode::VarjoQuadViews;
+    }
+    if foveation.level == XrFoveationLevel::Off {
+        return XrFoveationMode::Off;
+    }
+    match (
+        &exts.fb_foveation,
+        &exts.fb_foveation_configuration,
+        &exts.fb_swapchain_update_state,
+    ) {
+        // The profile has nothing to act on without the density maps
+        (Some(_), Some(_), Some(_)) if !swapchain.foveation_density_maps().is_empty() => {
+            XrFoveationMode::FbDensityMapUnused {
+                level: foveation.level,
+                dynamic: foveation.dynamic,
+            }
+        }
+        _ => XrFoveationMode::Unsupported,
+    }
+}
+
+/// Whether `XR_VARJO_foveated_rendering` is enabled and the system can move the focus views with
+/// the gaze.
+fn supports_varjo_foveated_rendering(instance: &XrInstance) -> bool {
+    if instance.exts().varjo_foveated_rendering.is_none() {
+        return false;
+    }
+    let Ok(system) = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY) else {
+        return false;
+    };
+    let mut properties = sys::SystemFoveatedRenderingPropertiesVARJO {
+        ty: sys::SystemFoveatedRenderingPropertiesVARJO::TYPE,
+        next: ptr::null_mut(),
+        supports_foveated_rendering: false.into(),
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties as *mut _ as *mut _;
+    let result = unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    };
+    result.into_raw() >= 0 && properties.supports_foveated_rendering.into()
+}
+
+/// `xrLocateViews`, with the focus views following the gaze while Varjo foveated rendering is the
+/// active [`XrFoveationMode`].
+pub(crate) fn locate_views(
+    session: &XrSession,
+    view_type: xr::ViewConfigurationType,
+    time: xr::Time,
+    space: &xr::Space,
+    mode: Option<&XrFoveationMode>,
+) -> xr::Result<(xr::ViewStateFlags, Vec<xr::View>)> {
+    if mode != Some(&XrFoveationMode::VarjoQuadViews) {
+        return session.locate_views(view_type, time, space);
+    }
+    let foveated_info = sys::ViewLocateFoveatedRenderingVARJO {
+        ty: sys::ViewLocateFoveatedRenderingVARJO::TYPE,
+        next: ptr::null(),
+        foveated_rendering_active: true.into(),
+    };
+    let info = sys::ViewLocateInfo {
+        ty: sys::ViewLocateInfo::TYPE,
+        next: &foveated_info as *const _ as *const _,
+        view_configuration_type: view_type,
+        display_time: time,
+        space: space.as_raw(),
+    };
+    let mut state = sys::ViewState {
+        ty: sys::ViewState::TYPE,
+        next: ptr::null_mut(),
+        view_state_flags: xr::ViewStateFlags::EMPTY,
+    };
+    let mut views = [sys::View {
+        ty: sys::View::TYPE,
+        next: ptr::null_mut(),
+        pose: xr::Posef::IDENTITY,
+        fov: xr::Fovf {
+            angle_left: 0.0,
+            angle_right: 0.0,
+            angle_up: 0.0,
+            angle_down: 0.0,
+        },
+    }; 4];
+    let mut count = 0;
+    let result = unsafe {
+        (session.instance().fp().locate_views)(
+            session.as_raw(),
+            &info,
+            &mut state,
+            views.len() as u32,
+            &mut count,
+            views.as_mut_ptr(),
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let views = views[..count as usize]
+        .iter()
+        .map(|view| xr::View {
+            pose: view.pose,
+            fov: view.fov,
+        })
+        .collect();
+    Ok((state.view_state_flags, views))
+}
+
+pub fn apply_foveation(
+    foveation: Res<XrFoveation>,
+    mut mode: ResMut<XrFoveationMode>,
+    instance: Res<XrInstance>,
+    view_type: Res<XrViewConfigurationType>,
+    session: Res<XrSession>,
+    swapchain: Res<XrSwapchain>,
+) {
+    let new_mode = resolve_mode(&instance, **view_type, &swapchain, &foveation);
+    if foveation.eye_tracked && new_mode != XrFoveationMode::VarjoQuadViews {
+        if **view_type != xr::ViewConfigurationType::PRIMARY_QUAD_VARJO {
+            warn!(
+                "Eye tracked foveation needs the QuadVarjo view configuration, the session uses {:?}, falling back to {new_mode:?}",
+                **view_type
+            );
+        } else {
+            warn!("Eye tracked foveation is not available, falling back to {new_mode:?}");
+        }
+    }
+    let level = match new_mode {
+        XrFoveationMode::FbDensityMapUnused { level, .. } => level,
+        // Reset a previously applied profile
+        XrFoveationMode::Off if mode.has_fb_profile() => XrFoveationLevel::Off,
+        _ => {
+            *mode = new_mode;
+            return;
+        }
+    };
+    match apply_fb_profile(&instance, &session, &swapchain, level, foveation.dynamic) {
+        Ok(()) => {
+            if new_mode.has_fb_profile() && !mode.has_fb_profile() {
+                warn!("Foveation profile applied to the density maps, but no pass attaches them so nothing is foveated");
+            }
+            *mode = new_mode;
+        }
+        Err(err) => {
+            warn!("Failed to apply foveation profile: {err}");
+            *mode = XrFoveationMode::Unsupported;
+        }
+    }
+}
+
+fn apply_fb_profile(
+    instance: &XrInstance,
+    session: &XrSession,
+    swapchain: &XrSwapchain,
+    level: XrFoveationLevel,
+    dynamic: bool,
+) -> xr::Result<()> {
+    let exts = instance.exts();
+    let (Some(foveation_fb), Some(update_state_fb)) =
+        (exts.fb_foveation, exts.fb_swapchain_update_state)
+    else {
+        return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
+    };
+    let (fb_level, vertical_offset) = match level {
+        XrFoveationLevel::Off => (xr::FoveationLevelFB::NONE, 0.0),
+        XrFoveationLevel::Low => (xr::FoveationLevelFB::LOW, 0.0),
+        XrFoveationLevel::Medium => (xr::FoveationLevelFB::MEDIUM, 0.0),
+        XrFoveationLevel::High => (xr::FoveationLevelFB::HIGH, 0.0),
+        // Degrees, matching the legacy "high top" fixed foveation level
+        XrFoveationLevel::HighTop => (xr::FoveationLevelFB::HIGH, 10.0),
+    };
+    let level_info = sys::FoveationLevelProfileCreateInfoFB {
+        ty: sys::FoveationLevelProfileCreateInfoFB::TYPE,
+        next: ptr::null_mut(),
+        level: fb_level,
+        vertical_offset,
+        dynamic: if dynamic {
+            xr::FoveationDynamicFB::LEVEL_ENABLED
+        } else {
+            xr::FoveationDynamicFB::DISABLED
+        },
+    };
+    let create_info = sys::FoveationProfileCreateInfoFB {
+        ty: sys::FoveationProfileCreateInfoFB::TYPE,
+        next: &level_info as *const _ as *mut _,
+    };
+    let mut profile = sys::FoveationProfileFB::NULL;
+    let result = unsafe {
+        (foveation_fb.create_foveation_profile)(session.as_raw(), &create_info, &mut profile)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let state = sys::SwapchainStateFoveationFB {
+        ty: sys::SwapchainStateFoveationFB::TYPE,
+        next: ptr::null_mut(),
+        flags: xr::SwapchainStateFoveationFlagsFB::EMPTY,
+        profile,
+    };
+    let result = unsafe {
+        (update_state_fb.update_swapchain)(
+            swapchain.as_raw(),
+            &state as *const _ as *const sys::SwapchainStateBaseHeaderFB,
+        )
+    };
+    // The swapchain keeps its own copy of the profile
+    unsafe { (foveation_fb.destroy_foveation_profile)(profile) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
+use crate::xr_input::body_tracking::FB_BODY_TRACKING_EXTENSION;
+use crate::xr_input::vive_trackers::HTCX_VIVE_TRACKER_EXTENSION;
 use openxr::ExtensionSet;
+use std::ffi::c_char;
 use std::ops;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -18,6 +21,15 @@ impl XrExtensions {
         self.0.fb_passthrough = false;
         self
     }
+    pub fn enable_fb_projected_passthrough(&mut self) -> &mut Self {
+        self.0.fb_passthrough = true;
+        self.0.fb_triangle_mesh = true;
+        self
+    }
+    pub fn disable_fb_projected_passthrough(&mut self) -> &mut Self {
+        self.0.fb_triangle_mesh = false;
+        self
+    }
     pub fn enable_hand_tracking(&mut self) -> &mut Self {
         self.0.ext_hand_tracking = true;
         self
@@ -26,6 +38,105 @@ impl XrExtensions {
         self.0.ext_hand_tracking = false;
         self
     }
+    pub fn enable_fb_hand_tracking_aim(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_aim = true;
+        self
+    }
+    pub fn disable_fb_hand_tracking_aim(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_aim = false;
+        self
+    }
+    pub fn enable_fb_hand_tracking_mesh(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_mesh = true;
+        self
+    }
+    pub fn disable_fb_hand_tracking_mesh(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_mesh = false;
+        self
+    }
+    pub fn enable_fb_hand_tracking_capsules(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_capsules = true;
+        self
+    }
+    pub fn disable_fb_hand_tracking_capsules(&mut self) -> &mut Self {
+        self.0.fb_hand_tracking_capsules = false;
+        self
+    }
+    pub fn enable_msft_hand_tracking_mesh(&mut self) -> &mut Self {
+        self.0.msft_hand_tracking_mesh = true;
+        self
+    }
+    pub fn disable_msft_hand_tracking_mesh(&mut self) -> &mut Self {
+        self.0.msft_hand_tracking_mesh = false;
+        self
+    }
+    pub fn enable_fb_foveation(&mut self) -> &mut Self {
+        self.0.fb_foveation = true;
+        self.0.fb_foveation_configuration = true;
+        self.0.fb_foveation_vulkan = true;
+        self.0.fb_swapchain_update_state = true;
+        self
+    }
+    pub fn disable_fb_foveation(&mut self) -> &mut Self {
+        self.0.fb_foveation = false;
+        self.0.fb_foveation_configuration = false;
+        self.0.fb_foveation_vulkan = false;
+        self.0.fb_swapchain_update_state = false;
+        self
+    }
+    pub fn enable_varjo_foveated_rendering(&mut self) -> &mut Self {
+        self.0.varjo_quad_views = true;
+        self.0.varjo_foveated_rendering = true;
+        self
+    }
+    pub fn disable_varjo_foveated_rendering(&mut self) -> &mut Self {
+        self.0.varjo_foveated_rendering = false;
+        self
+    }
+    pub fn enable_fb_display_refresh_rate(&mut self) -> &mut Self {
+        self.0.fb_display_refresh_rate = true;
+        self
+    }
+    pub fn disable_fb_display_refresh_rate(&mut self) -> &mut Self {
+        self.0.fb_display_refresh_rate = false;
+        self
+    }
+    pub fn enable_performance_settings(&mut self) -> &mut Self {
+        self.0.ext_performance_settings = true;
+        self.0.ext_thermal_query = true;
+        self
+    }
+    pub fn disable_performance_settings(&mut self) -> &mut Self {
+        self.0.ext_performance_settings = false;
+        self.0.ext_thermal_query = false;
+        self
+    }
+    pub fn enable_meta_performance_metrics(&mut self) -> &mut Self {
+        self.0.meta_performance_metrics = true;
+        self
+    }
+    pub fn disable_meta_performance_metrics(&mut self) -> &mut Self {
+        self.0.meta_performance_metrics = false;
+        self
+    }
+    pub fn enable_msft_first_person_observer(&mut self) -> &mut Self {
+        self.0.msft_secondary_view_configuration = true;
+        self.0.msft_first_person_observer = true;
+        self
+    }
+    pub fn disable_msft_first_person_observer(&mut self) -> &mut Self {
+        self.0.msft_secondary_view_configuration = false;
+        self.0.msft_first_person_observer = false;
+        self
+    }
+    pub fn enable_visibility_mask(&mut self) -> &mut Self {
+        self.0.khr_visibility_mask = true;
+        self
+    }
+    pub fn disable_visibility_mask(&mut self) -> &mut Self {
+        self.0.khr_visibility_mask = false;
+        self
+    }
     pub fn enable_local_floor(&mut self) -> &mut Self {
         self.0.ext_local_floor = true;
         self
@@ -34,6 +145,104 @@ impl XrExtensions {
         self.0.ext_local_floor = false;
         self
     }
+    pub fn enable_color_scale_bias(&mut self) -> &mut Self {
+        self.0.khr_composition_layer_color_scale_bias = true;
+        self
+    }
+    pub fn disable_color_scale_bias(&mut self) -> &mut Self {
+        self.0.khr_composition_layer_color_scale_bias = false;
+        self
+    }
+    pub fn enable_fb_alpha_blend(&mut self) -> &mut Self {
+        self.0.fb_composition_layer_alpha_blend = true;
+        self
+    }
+    pub fn disable_fb_alpha_blend(&mut self) -> &mut Self {
+        self.0.fb_composition_layer_alpha_blend = false;
+        self
+    }
+    pub fn enable_fb_spatial_anchors(&mut self) -> &mut Self {
+        self.0.fb_spatial_entity = true;
+        self.0.fb_spatial_entity_storage = true;
+        self.0.fb_spatial_entity_query = true;
+        self
+    }
+    pub fn disable_fb_spatial_anchors(&mut self) -> &mut Self {
+        self.0.fb_spatial_entity = false;
+        self.0.fb_spatial_entity_storage = false;
+        self.0.fb_spatial_entity_query = false;
+        self
+    }
+    pub fn enable_msft_spatial_anchors(&mut self) -> &mut Self {
+        self.0.msft_spatial_anchor = true;
+        self.0.msft_spatial_anchor_persistence = true;
+        self
+    }
+    pub fn disable_msft_spatial_anchors(&mut self) -> &mut Self {
+        self.0.msft_spatial_anchor = false;
+        self.0.msft_spatial_anchor_persistence = false;
+        self
+    }
+    pub fn enable_fb_scene(&mut self) -> &mut Self {
+        self.0.fb_spatial_entity = true;
+        self.0.fb_spatial_entity_query = true;
+        self.0.fb_scene = true;
+        self.0.fb_spatial_entity_container = true;
+        self
+    }
+    pub fn disable_fb_scene(&mut self) -> &mut Self {
+        self.0.fb_scene = false;
+        self.0.fb_spatial_entity_container = false;
+        self
+    }
+    pub fn enable_eye_gaze_interaction(&mut self) -> &mut Self {
+        self.0.ext_eye_gaze_interaction = true;
+        self
+    }
+    pub fn disable_eye_gaze_interaction(&mut self) -> &mut Self {
+        self.0.ext_eye_gaze_interaction = false;
+        self
+    }
+    pub fn enable_htc_facial_tracking(&mut self) -> &mut Self {
+        self.0.htc_facial_tracking = true;
+        self
+    }
+    pub fn disable_htc_facial_tracking(&mut self) -> &mut Self {
+        self.0.htc_facial_tracking = false;
+        self
+    }
+    pub fn enable_fb_body_tracking(&mut self) -> &mut Self {
+        let name = FB_BODY_TRACKING_EXTENSION.to_string();
+        if !self.0.other.contains(&name) {
+            self.0.other.push(name);
+        }
+        self
+    }
+    pub fn disable_fb_body_tracking(&mut self) -> &mut Self {
+        self.0.other.retain(|ext| ext != FB_BODY_TRACKING_EXTENSION);
+        self
+    }
+    pub fn enable_vive_trackers(&mut self) -> &mut Self {
+        let name = HTCX_VIVE_TRACKER_EXTENSION.to_string();
+        if !self.0.other.contains(&name) {
+            self.0.other.push(name);
+        }
+        self
+    }
+    pub fn disable_vive_trackers(&mut self) -> &mut Self {
+        self.0
+            .other
+            .retain(|ext| ext != HTCX_VIVE_TRACKER_EXTENSION);
+        self
+    }
+    pub fn enable_wrist_trackers(&mut self) -> &mut Self {
+        self.0.htc_vive_wrist_tracker_interaction = true;
+        self
+    }
+    pub fn disable_wrist_trackers(&mut self) -> &mut Self {
+        self.0.htc_vive_wrist_tracker_interaction = false;
+        self
+    }
 }
 impl From<ExtensionSet> for XrExtensions {
     fn from(value: ExtensionSet) -> Self {
@@ -132,6 +341,7 @@ impl ops::BitAnd for XrExtensions {
         out.htc_hand_interaction = self.0.htc_hand_interaction && rhs.0.htc_hand_interaction;
         out.htc_vive_wrist_tracker_interaction =
             self.0.htc_vive_wrist_tracker_interaction && rhs.0.htc_vive_wrist_tracker_interaction;
+        // enabled through `other`, see `enable_vive_trackers`
         // out.htcx_vive_tracker_interaction =
         //     self.0.htcx_vive_tracker_interaction && rhs.0.htcx_vive_tracker_interaction;
         out.huawei_controller_interaction =
@@ -250,3 +460,21 @@ fn and_windows_only_exts(lhs: &XrExtensions, rhs: &XrExtensions, out: &mut Exten
     out.msft_holographic_window_attachment =
         lhs.0.msft_holographic_window_attachment && rhs.0.msft_holographic_window_attachment;
 }
+
+/// Loads an extension function missing from the generated bindings by its nul terminated name,
+/// fails with `ERROR_FUNCTION_UNSUPPORTED` when its extension is not enabled.
+pub(crate) unsafe fn load_function(
+    instance: &openxr::Instance,
+    name: &[u8],
+) -> openxr::Result<unsafe extern "system" fn()> {
+    let mut function = None;
+    let result = (instance.fp().get_instance_proc_addr)(
+        instance.as_raw(),
+        name.as_ptr() as *const c_char,
+        &mut function,
+    );
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    function.ok_or(openxr::sys::Result::ERROR_FUNCTION_UNSUPPORTED)
+}
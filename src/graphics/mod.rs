@@ -1,6 +1,7 @@
 pub mod extensions;
 mod vulkan;
 
+use bevy::prelude::Resource;
 use bevy::render::renderer::{RenderAdapter, RenderAdapterInfo, RenderDevice, RenderQueue};
 use bevy::window::RawHandleWrapper;
 use wgpu::Instance;
@@ -26,6 +27,50 @@ impl Default for XrPreferdBlendMode {
         Self::Opaque
     }
 }
+impl XrPreferdBlendMode {
+    pub fn environment_blend_mode(self) -> xr::EnvironmentBlendMode {
+        match self {
+            XrPreferdBlendMode::Opaque => xr::EnvironmentBlendMode::OPAQUE,
+            XrPreferdBlendMode::Additive => xr::EnvironmentBlendMode::ADDITIVE,
+            XrPreferdBlendMode::AlphaBlend => xr::EnvironmentBlendMode::ALPHA_BLEND,
+        }
+    }
+
+    pub fn from_environment_blend_mode(mode: xr::EnvironmentBlendMode) -> Option<Self> {
+        match mode {
+            xr::EnvironmentBlendMode::OPAQUE => Some(XrPreferdBlendMode::Opaque),
+            xr::EnvironmentBlendMode::ADDITIVE => Some(XrPreferdBlendMode::Additive),
+            xr::EnvironmentBlendMode::ALPHA_BLEND => Some(XrPreferdBlendMode::AlphaBlend),
+            _ => None,
+        }
+    }
+}
+
+/// The primary view configuration the session is begun with. Falls back to
+/// [`XrViewConfiguration::Stereo`] when the system doesn't support the requested one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrViewConfiguration {
+    /// A single view, e.g. for handheld AR or CAVE like displays.
+    Mono,
+    /// One view per eye.
+    Stereo,
+    /// A wide context view and a high resolution focus view per eye, needs `XR_VARJO_quad_views`.
+    QuadVarjo,
+}
+impl Default for XrViewConfiguration {
+    fn default() -> Self {
+        Self::Stereo
+    }
+}
+impl XrViewConfiguration {
+    pub fn view_configuration_type(self) -> xr::ViewConfigurationType {
+        match self {
+            XrViewConfiguration::Mono => xr::ViewConfigurationType::PRIMARY_MONO,
+            XrViewConfiguration::Stereo => xr::ViewConfigurationType::PRIMARY_STEREO,
+            XrViewConfiguration::QuadVarjo => xr::ViewConfigurationType::PRIMARY_QUAD_VARJO,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct XrAppInfo {
@@ -43,6 +88,7 @@ pub fn initialize_xr_graphics(
     window: Option<RawHandleWrapper>,
     reqeusted_extensions: XrExtensions,
     prefered_blend_mode: XrPreferdBlendMode,
+    prefered_view_configuration: XrViewConfiguration,
     app_info: XrAppInfo,
 ) -> anyhow::Result<(
     RenderDevice,
@@ -61,8 +107,54 @@ pub fn initialize_xr_graphics(
     XrInput,
     XrViews,
     XrFrameState,
+    XrSessionCreateInfo,
 )> {
-    vulkan::initialize_xr_graphics(window, reqeusted_extensions, prefered_blend_mode, app_info)
+    vulkan::initialize_xr_graphics(
+        window,
+        reqeusted_extensions,
+        prefered_blend_mode,
+        prefered_view_configuration,
+        app_info,
+    )
+}
+
+/// What a new session needs to be created on the same instance and device, used to recover from
+/// session loss.
+#[derive(Resource, Clone, Copy)]
+pub struct XrSessionCreateInfo(pub(crate) SessionCreateInfo);
+
+impl XrSessionCreateInfo {
+    /// The primary view configuration the session was created for.
+    pub fn view_configuration_type(&self) -> xr::ViewConfigurationType {
+        match &self.0 {
+            SessionCreateInfo::Vulkan(info) => info.view_type,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum SessionCreateInfo {
+    Vulkan(vulkan::VulkanSessionCreateInfo),
+}
+
+/// Creates a new session and swapchain. The previous session has to be destroyed first, most
+/// runtimes only allow one session per instance.
+pub fn create_session(
+    info: &XrSessionCreateInfo,
+    instance: &XrInstance,
+    device: &RenderDevice,
+) -> anyhow::Result<(
+    XrSession,
+    XrSessionRunning,
+    XrFrameWaiter,
+    XrSwapchain,
+    XrInput,
+)> {
+    match &info.0 {
+        SessionCreateInfo::Vulkan(info) => {
+            vulkan::create_session(info, instance, device.wgpu_device())
+        }
+    }
 }
 
 pub fn xr_entry() -> anyhow::Result<xr::Entry> {
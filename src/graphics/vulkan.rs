@@ -1,4 +1,5 @@
-use std::ffi::{c_void, CString};
+use std::ffi::{c_void, CStr, CString};
+use std::ptr;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
@@ -12,20 +13,24 @@ use openxr as xr;
 use wgpu::Instance;
 use xr::EnvironmentBlendMode;
 
+use crate::foveation::XrFoveationDensityMap;
 use crate::graphics::extensions::XrExtensions;
 use crate::input::XrInput;
 use crate::resources::{
-    Swapchain, SwapchainInner, XrEnvironmentBlendMode, XrFormat, XrFrameState, XrFrameWaiter,
-    XrInstance, XrResolution, XrSession, XrSessionRunning, XrSwapchain, XrViews,
+    SecondaryViewInfo, SecondaryViewSwapchain, Swapchain, SwapchainInner, XrEnvironmentBlendMode,
+    XrFormat, XrFrameState, XrFrameWaiter, XrInstance, XrResolution, XrSession, XrSessionRunning,
+    XrSwapchain, XrViews,
 };
-use crate::VIEW_TYPE;
 
-use super::{XrAppInfo, XrPreferdBlendMode};
+use super::{
+    SessionCreateInfo, XrAppInfo, XrPreferdBlendMode, XrSessionCreateInfo, XrViewConfiguration,
+};
 
 pub fn initialize_xr_graphics(
     window: Option<RawHandleWrapper>,
     reqeusted_extensions: XrExtensions,
     prefered_blend_mode: XrPreferdBlendMode,
+    prefered_view_configuration: XrViewConfiguration,
     app_info: XrAppInfo,
 ) -> anyhow::Result<(
     RenderDevice,
@@ -44,6 +49,7 @@ pub fn initialize_xr_graphics(
     XrInput,
     XrViews,
     XrFrameState,
+    XrSessionCreateInfo,
 )> {
     use wgpu_hal::{api::Vulkan as V, Api};
 
@@ -58,6 +64,9 @@ pub fn initialize_xr_graphics(
 
     let mut enabled_extensions: xr::ExtensionSet = reqeusted_extensions.into();
     enabled_extensions.khr_vulkan_enable2 = true;
+    if prefered_view_configuration == XrViewConfiguration::QuadVarjo {
+        enabled_extensions.varjo_quad_views = available_extensions.raw().varjo_quad_views;
+    }
     #[cfg(target_os = "android")]
     {
         enabled_extensions.khr_android_create_instance = true;
@@ -91,20 +100,37 @@ pub fn initialize_xr_graphics(
         }
     );
 
-    let blend_modes = xr_instance.enumerate_environment_blend_modes(xr_system_id, VIEW_TYPE)?;
-    let blend_mode: EnvironmentBlendMode = match prefered_blend_mode {
-        XrPreferdBlendMode::Opaque if blend_modes.contains(&EnvironmentBlendMode::OPAQUE) => {
-            EnvironmentBlendMode::OPAQUE
-        }
-        XrPreferdBlendMode::Additive if blend_modes.contains(&EnvironmentBlendMode::ADDITIVE) => {
-            EnvironmentBlendMode::ADDITIVE
-        }
-        XrPreferdBlendMode::AlphaBlend
-            if blend_modes.contains(&EnvironmentBlendMode::ALPHA_BLEND) =>
-        {
-            EnvironmentBlendMode::ALPHA_BLEND
+    let view_configurations = xr_instance.enumerate_view_configurations(xr_system_id)?;
+    let view_type = prefered_view_configuration.view_configuration_type();
+    let view_type = if view_configurations.contains(&view_type) {
+        view_type
+    } else {
+        warn!(
+            "{:?} view configuration is not supported, falling back to stereo",
+            prefered_view_configuration
+        );
+        xr::ViewConfigurationType::PRIMARY_STEREO
+    };
+    info!("using view configuration {:?}", view_type);
+
+    let blend_modes = xr_instance.enumerate_environment_blend_modes(xr_system_id, view_type)?;
+    let blend_mode = match prefered_blend_mode.environment_blend_mode() {
+        blend_mode if blend_modes.contains(&blend_mode) => blend_mode,
+        _ => {
+            let fallback = if blend_modes.contains(&EnvironmentBlendMode::OPAQUE) {
+                EnvironmentBlendMode::OPAQUE
+            } else {
+                blend_modes
+                    .first()
+                    .copied()
+                    .unwrap_or(EnvironmentBlendMode::OPAQUE)
+            };
+            error!(
+                "{:?} environment blend mode is not supported, falling back to {:?}, supported are {:?}",
+                prefered_blend_mode, fallback, blend_modes
+            );
+            fallback
         }
-        _ => EnvironmentBlendMode::OPAQUE,
     };
 
     #[cfg(not(target_os = "android"))]
@@ -131,7 +157,7 @@ pub fn initialize_xr_graphics(
     let vk_entry = unsafe { ash::Entry::load() }?;
     let flags = wgpu::InstanceFlags::from_build_config();
     let extensions = <V as Api>::Instance::desired_extensions(&vk_entry, vk_target_version, flags)?;
-    let device_extensions = vec![
+    let mut device_extensions = vec![
         ash::extensions::khr::Swapchain::name(),
         ash::extensions::khr::DrawIndirectCount::name(),
         #[cfg(target_os = "android")]
@@ -182,6 +208,17 @@ pub fn initialize_xr_graphics(
     });
     let vk_physical_device_ptr = vk_physical_device.as_raw() as *const c_void;
 
+    // Swapchains created for XR_FB_foveation_vulkan come with fragment density map images
+    let fragment_density_map = xr_instance.exts().fb_foveation_vulkan.is_some()
+        && supports_device_extension(
+            &vk_instance,
+            vk_physical_device,
+            vk::ExtFragmentDensityMapFn::name(),
+        );
+    if fragment_density_map {
+        device_extensions.push(vk::ExtFragmentDensityMapFn::name());
+    }
+
     let vk_device_properties =
         unsafe { vk_instance.get_physical_device_properties(vk_physical_device) };
     if vk_device_properties.api_version < vk_target_version {
@@ -228,15 +265,22 @@ pub fn initialize_xr_graphics(
             .queue_priorities(&[1.0])
             .build();
         let family_infos = [family_info];
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures {
+            multiview: vk::TRUE,
+            ..Default::default()
+        };
+        let mut density_map_features = vk::PhysicalDeviceFragmentDensityMapFeaturesEXT {
+            fragment_density_map: vk::TRUE,
+            ..Default::default()
+        };
+        let mut builder = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&family_infos)
+            .push_next(&mut multiview_features);
+        if fragment_density_map {
+            builder = builder.push_next(&mut density_map_features);
+        }
         let info = enabled_phd_features
-            .add_to_device_create_builder(
-                vk::DeviceCreateInfo::builder()
-                    .queue_create_infos(&family_infos)
-                    .push_next(&mut vk::PhysicalDeviceMultiviewFeatures {
-                        multiview: vk::TRUE,
-                        ..Default::default()
-                    }),
-            )
+            .add_to_device_create_builder(builder)
             .enabled_extension_names(&extensions_cchar)
             .build();
         let vk_device = unsafe {
@@ -295,20 +339,8 @@ pub fn initialize_xr_graphics(
         )
     }?;
 
-    let (session, frame_wait, frame_stream) = unsafe {
-        xr_instance.create_session::<xr::Vulkan>(
-            xr_system_id,
-            &xr::vulkan::SessionCreateInfo {
-                instance: vk_instance_ptr,
-                physical_device: vk_physical_device_ptr,
-                device: vk_device_ptr,
-                queue_family_index,
-                queue_index: 0,
-            },
-        )
-    }?;
-
-    let views = xr_instance.enumerate_view_configuration_views(xr_system_id, VIEW_TYPE)?;
+    let views = xr_instance.enumerate_view_configuration_views(xr_system_id, view_type)?;
+    let secondary = secondary_view_info(&xr_instance, xr_system_id, &view_configurations)?;
 
     let surface = window.map(|wrapper| unsafe {
         // SAFETY: Plugins should be set up on the main thread.
@@ -322,13 +354,139 @@ pub fn initialize_xr_graphics(
         .map(|surface| surface.get_capabilities(&wgpu_adapter).formats[0])
         .unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb);
 
-    let resolution = uvec2(
-        views[0].recommended_image_rect_width,
-        views[0].recommended_image_rect_height,
-    );
+    // Every view renders into a layer of the same swapchain, so it is sized for the largest one.
+    // The focus views of the Varjo quad views recommend a different size than the context views
+    let resolution = views.iter().fold(UVec2::ZERO, |resolution, view| {
+        resolution.max(uvec2(
+            view.recommended_image_rect_width,
+            view.recommended_image_rect_height,
+        ))
+    });
 
-    let handle = session
-        .create_swapchain(&xr::SwapchainCreateInfo {
+    let session_create_info = VulkanSessionCreateInfo {
+        system: xr_system_id,
+        instance: vk_instance_ptr as usize,
+        physical_device: vk_physical_device_ptr as usize,
+        device: vk_device_ptr as usize,
+        queue_family_index,
+        format: swapchain_format,
+        resolution,
+        view_type,
+        view_count: views.len() as u32,
+        secondary,
+        fragment_density_map,
+    };
+    let (session, session_running, frame_waiter, swapchain, input) =
+        create_session(&session_create_info, &xr_instance, &wgpu_device)?;
+
+    Ok((
+        wgpu_device.into(),
+        RenderQueue(Arc::new(wgpu_queue)),
+        RenderAdapterInfo(wgpu_adapter.get_info()),
+        RenderAdapter(Arc::new(wgpu_adapter)),
+        wgpu_instance,
+        xr_instance.clone().into(),
+        session,
+        blend_mode.into(),
+        resolution.into(),
+        swapchain_format.into(),
+        session_running,
+        frame_waiter,
+        swapchain,
+        input,
+        Mutex::default().into(),
+        Mutex::new(xr::FrameState {
+            predicted_display_time: xr::Time::from_nanos(1),
+            predicted_display_period: xr::Duration::from_nanos(1),
+            should_render: true,
+        })
+        .into(),
+        XrSessionCreateInfo(SessionCreateInfo::Vulkan(session_create_info)),
+    ))
+}
+
+/// Vulkan handles and swapchain settings a session is created from, kept around so a lost session
+/// can be replaced by a new one on the same device.
+#[derive(Clone, Copy)]
+pub(crate) struct VulkanSessionCreateInfo {
+    system: xr::SystemId,
+    instance: usize,
+    physical_device: usize,
+    device: usize,
+    queue_family_index: u32,
+    format: wgpu::TextureFormat,
+    resolution: UVec2,
+    pub(crate) view_type: xr::ViewConfigurationType,
+    view_count: u32,
+    secondary: Option<SecondaryViewInfo>,
+    /// `VK_EXT_fragment_density_map` is enabled on the device, so the swapchain can be created
+    /// with the density maps of `XR_FB_foveation_vulkan`.
+    fragment_density_map: bool,
+}
+
+/// Picks the first person observer as secondary view configuration if it was enabled and the
+/// system supports it.
+fn secondary_view_info(
+    xr_instance: &xr::Instance,
+    system: xr::SystemId,
+    view_configurations: &[xr::ViewConfigurationType],
+) -> anyhow::Result<Option<SecondaryViewInfo>> {
+    let view_type = xr::ViewConfigurationType::SECONDARY_MONO_FIRST_PERSON_OBSERVER_MSFT;
+    if xr_instance.exts().msft_first_person_observer.is_none()
+        || !view_configurations.contains(&view_type)
+    {
+        return Ok(None);
+    }
+    let views = xr_instance.enumerate_view_configuration_views(system, view_type)?;
+    let Some(view) = views.first() else {
+        return Ok(None);
+    };
+    let blend_modes = xr_instance.enumerate_environment_blend_modes(system, view_type)?;
+    info!("using secondary view configuration {:?}", view_type);
+    Ok(Some(SecondaryViewInfo {
+        view_type,
+        resolution: uvec2(
+            view.recommended_image_rect_width,
+            view.recommended_image_rect_height,
+        ),
+        // Mixed reality capture composites the view over the camera image
+        blend_mode: if blend_modes.contains(&EnvironmentBlendMode::ALPHA_BLEND) {
+            EnvironmentBlendMode::ALPHA_BLEND
+        } else {
+            EnvironmentBlendMode::OPAQUE
+        },
+    }))
+}
+
+pub(crate) fn create_session(
+    info: &VulkanSessionCreateInfo,
+    xr_instance: &xr::Instance,
+    wgpu_device: &wgpu::Device,
+) -> anyhow::Result<(
+    XrSession,
+    XrSessionRunning,
+    XrFrameWaiter,
+    XrSwapchain,
+    XrInput,
+)> {
+    let (session, frame_wait, frame_stream) = unsafe {
+        xr_instance.create_session::<xr::Vulkan>(
+            info.system,
+            &xr::vulkan::SessionCreateInfo {
+                instance: info.instance as *const c_void,
+                physical_device: info.physical_device as *const c_void,
+                device: info.device as *const c_void,
+                queue_family_index: info.queue_family_index,
+                queue_index: 0,
+            },
+        )
+    }?;
+
+    let swapchain_format = info.format;
+    let resolution = info.resolution;
+    let handle = create_swapchain(
+        &session,
+        &xr::SwapchainCreateInfo {
             create_flags: xr::SwapchainCreateFlags::EMPTY,
             usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
                 | xr::SwapchainUsageFlags::SAMPLED,
@@ -340,13 +498,92 @@ pub fn initialize_xr_graphics(
             width: resolution.x,
             height: resolution.y,
             face_count: 1,
-            array_size: 2,
+            array_size: info.view_count,
             mip_count: 1,
+        },
+        foveation_flags(xr_instance, info.fragment_density_map),
+    )?;
+    let foveation_density_maps = match info.fragment_density_map {
+        true => enumerate_density_maps(xr_instance, &handle).unwrap_or_else(|err| {
+            warn!("Failed to get the foveation density maps: {err}");
+            Vec::new()
+        }),
+        false => Vec::new(),
+    };
+    let buffers = create_textures(
+        &handle,
+        wgpu_device,
+        swapchain_format,
+        resolution,
+        info.view_count,
+    )?;
+
+    let secondary = match info.secondary {
+        Some(secondary) => {
+            let handle = session.create_swapchain(&xr::SwapchainCreateInfo {
+                create_flags: xr::SwapchainCreateFlags::EMPTY,
+                usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+                    | xr::SwapchainUsageFlags::SAMPLED,
+                format: wgpu_to_vulkan(swapchain_format).as_raw() as _,
+                sample_count: 1,
+                width: secondary.resolution.x,
+                height: secondary.resolution.y,
+                face_count: 1,
+                array_size: 1,
+                mip_count: 1,
+            })?;
+            let buffers = create_textures(
+                &handle,
+                wgpu_device,
+                swapchain_format,
+                secondary.resolution,
+                1,
+            )?;
+            Some(SecondaryViewSwapchain {
+                info: secondary,
+                session: session.clone(),
+                handle: Mutex::new(handle),
+                buffers,
+                image_index: Mutex::new(0),
+                image_acquired: AtomicBool::new(false),
+                image_ready: AtomicBool::new(false),
+            })
+        }
+        None => None,
+    };
+
+    Ok((
+        session.clone().into_any_graphics().into(),
+        AtomicBool::new(false).into(),
+        Mutex::new(frame_wait).into(),
+        Swapchain::Vulkan(SwapchainInner {
+            stream: Mutex::new(frame_stream),
+            handle: Mutex::new(handle),
+            buffers,
+            image_index: Mutex::new(0),
+            frame_begun: AtomicBool::new(false),
+            image_acquired: AtomicBool::new(false),
+            image_ready: AtomicBool::new(false),
+            secondary,
+            foveation_density_maps,
         })
-        .unwrap();
-    let images = handle.enumerate_images().unwrap();
+        .into(),
+        XrInput::new(xr_instance.clone(), session.into_any_graphics())?,
+    ))
+}
 
-    let buffers = images
+/// Wraps the images of a swapchain in wgpu textures with one layer per view.
+fn create_textures(
+    handle: &xr::Swapchain<xr::Vulkan>,
+    wgpu_device: &wgpu::Device,
+    swapchain_format: wgpu::TextureFormat,
+    resolution: UVec2,
+    view_count: u32,
+) -> anyhow::Result<Vec<wgpu::Texture>> {
+    use wgpu_hal::{api::Vulkan as V, Api};
+
+    let images = handle.enumerate_images()?;
+    Ok(images
         .into_iter()
         .map(|color_image| {
             let color_image = vk::Image::from_raw(color_image);
@@ -358,21 +595,22 @@ pub fn initialize_xr_graphics(
                         size: wgpu::Extent3d {
                             width: resolution.x,
                             height: resolution.y,
-                            depth_or_array_layers: 2,
+                            depth_or_array_layers: view_count,
                         },
                         mip_level_count: 1,
                         sample_count: 1,
                         dimension: wgpu::TextureDimension::D2,
                         format: swapchain_format,
                         usage: wgpu_hal::TextureUses::COLOR_TARGET
-                            | wgpu_hal::TextureUses::COPY_DST,
+                            | wgpu_hal::TextureUses::COPY_DST
+                            | wgpu_hal::TextureUses::RESOURCE,
                         memory_flags: wgpu_hal::MemoryFlags::empty(),
                         view_formats: vec![],
                     },
                     None,
                 )
             };
-            let texture = unsafe {
+            unsafe {
                 wgpu_device.create_texture_from_hal::<V>(
                     wgpu_hal_texture,
                     &wgpu::TextureDescriptor {
@@ -380,51 +618,140 @@ pub fn initialize_xr_graphics(
                         size: wgpu::Extent3d {
                             width: resolution.x,
                             height: resolution.y,
-                            depth_or_array_layers: 2,
+                            depth_or_array_layers: view_count,
                         },
                         mip_level_count: 1,
                         sample_count: 1,
                         dimension: wgpu::TextureDimension::D2,
                         format: swapchain_format,
                         usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                            | wgpu::TextureUsages::COPY_DST,
+                            | wgpu::TextureUsages::COPY_DST
+                            | wgpu::TextureUsages::TEXTURE_BINDING,
                         view_formats: &[],
                     },
                 )
-            };
-            texture
+            }
         })
-        .collect();
+        .collect())
+}
 
-    Ok((
-        wgpu_device.into(),
-        RenderQueue(Arc::new(wgpu_queue)),
-        RenderAdapterInfo(wgpu_adapter.get_info()),
-        RenderAdapter(Arc::new(wgpu_adapter)),
-        wgpu_instance,
-        xr_instance.clone().into(),
-        session.clone().into_any_graphics().into(),
-        blend_mode.into(),
-        resolution.into(),
-        swapchain_format.into(),
-        AtomicBool::new(false).into(),
-        Mutex::new(frame_wait).into(),
-        Swapchain::Vulkan(SwapchainInner {
-            stream: Mutex::new(frame_stream),
-            handle: Mutex::new(handle),
-            buffers,
-            image_index: Mutex::new(0),
+/// The flags to create the swapchain with for `XR_FB_foveation`, `None` when it is not enabled. On
+/// Vulkan the runtime can only foveate through fragment density maps, which need
+/// `VK_EXT_fragment_density_map` on the device.
+fn foveation_flags(
+    xr_instance: &xr::Instance,
+    fragment_density_map: bool,
+) -> Option<xr::SwapchainCreateFoveationFlagsFB> {
+    xr_instance.exts().fb_foveation?;
+    Some(match fragment_density_map {
+        true => xr::SwapchainCreateFoveationFlagsFB::FRAGMENT_DENSITY_MAP,
+        false => xr::SwapchainCreateFoveationFlagsFB::EMPTY,
+    })
+}
+
+fn supports_device_extension(
+    vk_instance: &ash::Instance,
+    vk_physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> bool {
+    let Ok(properties) =
+        (unsafe { vk_instance.enumerate_device_extension_properties(vk_physical_device) })
+    else {
+        return false;
+    };
+    properties
+        .iter()
+        .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+/// Creates the swapchain, chaining `XrSwapchainCreateInfoFoveationFB` when foveation is enabled so
+/// a foveation profile can later be applied to it with `xrUpdateSwapchainFB`.
+fn create_swapchain(
+    session: &xr::Session<xr::Vulkan>,
+    info: &xr::SwapchainCreateInfo<xr::Vulkan>,
+    foveation: Option<xr::SwapchainCreateFoveationFlagsFB>,
+) -> xr::Result<xr::Swapchain<xr::Vulkan>> {
+    let foveation_info = xr::sys::SwapchainCreateInfoFoveationFB {
+        ty: xr::sys::SwapchainCreateInfoFoveationFB::TYPE,
+        next: ptr::null_mut(),
+        flags: foveation.unwrap_or(xr::SwapchainCreateFoveationFlagsFB::EMPTY),
+    };
+    let raw_info = xr::sys::SwapchainCreateInfo {
+        ty: xr::sys::SwapchainCreateInfo::TYPE,
+        next: if foveation.is_some() {
+            &foveation_info as *const _ as *const _
+        } else {
+            ptr::null()
+        },
+        create_flags: info.create_flags,
+        usage_flags: info.usage_flags,
+        format: info.format as _,
+        sample_count: info.sample_count,
+        width: info.width,
+        height: info.height,
+        face_count: info.face_count,
+        array_size: info.array_size,
+        mip_count: info.mip_count,
+    };
+    let mut handle = xr::sys::Swapchain::NULL;
+    let result = unsafe {
+        (session.instance().fp().create_swapchain)(session.as_raw(), &raw_info, &mut handle)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(unsafe { xr::Swapchain::from_raw(session.clone(), handle) })
+}
+
+/// Gets the fragment density map of every swapchain image by chaining
+/// `XrSwapchainImageFoveationVulkanFB` into the enumerated images.
+fn enumerate_density_maps(
+    xr_instance: &xr::Instance,
+    handle: &xr::Swapchain<xr::Vulkan>,
+) -> xr::Result<Vec<XrFoveationDensityMap>> {
+    let fp = xr_instance.fp();
+    let mut count = 0;
+    let result =
+        unsafe { (fp.enumerate_swapchain_images)(handle.as_raw(), 0, &mut count, ptr::null_mut()) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let mut density_maps = vec![
+        xr::sys::SwapchainImageFoveationVulkanFB {
+            ty: xr::sys::SwapchainImageFoveationVulkanFB::TYPE,
+            next: ptr::null_mut(),
+            image: 0,
+            width: 0,
+            height: 0,
+        };
+        count as usize
+    ];
+    let mut images = density_maps
+        .iter_mut()
+        .map(|density_map| xr::sys::SwapchainImageVulkanKHR {
+            ty: xr::sys::SwapchainImageVulkanKHR::TYPE,
+            next: density_map as *mut _ as *mut _,
+            image: 0,
         })
-        .into(),
-        XrInput::new(xr_instance, session.into_any_graphics())?,
-        Mutex::default().into(),
-        Mutex::new(xr::FrameState {
-            predicted_display_time: xr::Time::from_nanos(1),
-            predicted_display_period: xr::Duration::from_nanos(1),
-            should_render: true,
+        .collect::<Vec<_>>();
+    let result = unsafe {
+        (fp.enumerate_swapchain_images)(
+            handle.as_raw(),
+            count,
+            &mut count,
+            images.as_mut_ptr() as *mut xr::sys::SwapchainImageBaseHeader,
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(density_maps
+        .iter()
+        .map(|density_map| XrFoveationDensityMap {
+            image: vk::Image::from_raw(density_map.image),
+            size: uvec2(density_map.width, density_map.height),
         })
-        .into(),
-    ))
+        .collect())
 }
 
 fn wgpu_to_vulkan(format: wgpu::TextureFormat) -> vk::Format {
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use bevy::render::camera::{ExtractedCamera, NormalizedRenderTarget};
+use bevy::render::view::ExtractedView;
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::transform::TransformSystem;
+use openxr as xr;
+
+use crate::foveation::{self, XrFoveationMode};
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrFrameWaited, XrSession, XrViewConfigurationType, XrViews};
+use crate::xr_init::xr_only;
+use crate::xr_input::hands::hand_tracking::{
+    update_hand_bones, DisableHandTracking, HandTrackingData,
+};
+use crate::xr_input::oculus_touch::OculusController;
+use crate::xr_input::trackers::update_open_xr_controllers;
+use crate::xr_input::vive_trackers::{late_latch_trackers, TrackerSpaces};
+use crate::xr_input::{QuatConv, Vec3Conv};
+use crate::{begin_frame, XR_TEXTURE_HANDLES};
+
+/// Opt-in late latching of the head and tracked poses.
+///
+/// Controllers, hand joints and trackers are located again at the end of `PostUpdate`, right
+/// before transform propagation, so they are extracted with the runtime's latest prediction
+/// instead of the one from `PreUpdate`. Changes to their transforms made in `Update` are
+/// overwritten.
+///
+/// The views are located again in the render world right before the view uniforms are prepared,
+/// and the extracted XR cameras are moved by the difference to the poses the frame was simulated
+/// with. The same poses are then submitted in the projection layer, so the compositor only has to
+/// reproject over the time it takes to render. Culling still uses the simulated head pose.
+pub struct XrLateLatchingPlugin;
+
+impl Plugin for XrLateLatchingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (
+                update_open_xr_controllers.run_if(resource_exists::<OculusController>),
+                update_hand_bones
+                    .run_if(resource_exists::<HandTrackingData>)
+                    .run_if(|dh: Option<Res<DisableHandTracking>>| {
+                        !dh.is_some_and(|v| *v == DisableHandTracking::Both)
+                    }),
+                late_latch_trackers.run_if(resource_exists::<TrackerSpaces>),
+            )
+                .run_if(xr_only())
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.add_systems(
+            Render,
+            late_latch_views
+                .run_if(xr_only())
+                .after(begin_frame)
+                .before(RenderSet::ManageViews),
+        );
+    }
+}
+
+fn late_latch_views(
+    session: Res<XrSession>,
+    input: Res<XrInput>,
+    view_type: Res<XrViewConfigurationType>,
+    frame_state: Res<XrFrameState>,
+    frame_waited: Res<XrFrameWaited>,
+    views: Res<XrViews>,
+    foveation_mode: Option<Res<XrFoveationMode>>,
+    mut cameras: Query<(&ExtractedCamera, &mut ExtractedView)>,
+) {
+    if !frame_waited.0 {
+        return;
+    }
+    let _span = info_span!("xr_late_latch_views").entered();
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    let located = foveation::locate_views(
+        &session,
+        **view_type,
+        time,
+        &input.stage,
+        foveation_mode.as_deref(),
+    );
+    let (flags, mut fresh) = match located {
+        Ok(located) => located,
+        Err(e) => {
+            warn!("error: {}", e);
+            return;
+        }
+    };
+    // Keep the simulated poses while tracking is lost
+    if !flags.contains(xr::ViewStateFlags::ORIENTATION_VALID | xr::ViewStateFlags::POSITION_VALID) {
+        return;
+    }
+    let mut views = views.lock().unwrap();
+    if views.len() != fresh.len() {
+        return;
+    }
+    for (camera, mut view) in &mut cameras {
+        let Some(NormalizedRenderTarget::TextureView(target)) = camera.target else {
+            continue;
+        };
+        let Some(index) = XR_TEXTURE_HANDLES
+            .iter()
+            .position(|handle| *handle == target)
+        else {
+            continue;
+        };
+        let simulated = pose_affine(&views[index].pose);
+        let latched = pose_affine(&fresh[index].pose);
+        // The eye's parent transform, usually the tracking root, stays the same
+        let parent = view.transform.affine() * simulated.inverse();
+        view.transform = GlobalTransform::from(parent * latched);
+        if view.view_projection.is_some() {
+            view.view_projection =
+                Some(view.projection * view.transform.compute_matrix().inverse());
+        }
+    }
+    // The projection matrices were built from the simulated fov, submit that one too
+    for (fresh, simulated) in fresh.iter_mut().zip(views.iter()) {
+        fresh.fov = simulated.fov;
+    }
+    *views = fresh;
+}
+
+fn pose_affine(pose: &xr::Posef) -> bevy::math::Affine3A {
+    Transform::from_translation(pose.position.to_vec3())
+        .with_rotation(pose.orientation.to_quat())
+        .compute_affine()
+}
@@ -1,48 +1,90 @@
+pub mod blend_mode;
+pub mod composition_layer;
+pub mod diagnostics;
+pub mod display_refresh_rate;
+pub mod foveation;
 pub mod graphics;
 pub mod input;
+pub mod late_latching;
+pub mod mirror;
 pub mod passthrough;
+pub mod performance;
+pub mod recovery;
 pub mod resource_macros;
 pub mod resources;
+pub mod spatial;
+pub mod visibility_mask;
 pub mod xr_init;
 pub mod xr_input;
 
 use std::sync::{Arc, Mutex};
 
+use crate::blend_mode::{XrBlendMode, XrBlendModePlugin};
+use crate::composition_layer::XrLayerSettings;
+use crate::diagnostics::XrDiagnosticsPlugin;
+use crate::display_refresh_rate::{XrDisplayRefreshRateChanged, XrDisplayRefreshRatePlugin};
+use crate::foveation::{XrFoveationMode, XrFoveationPlugin};
+use crate::performance::{XrPerfNotificationLevel, XrPerformanceNotification, XrPerformancePlugin};
+use crate::recovery::{
+    extract_session_resources, forward_render_frame_errors, handle_frame_errors, recreate_session,
+    restart_lost_session, XrFrameCall, XrFrameError, XrRecoveryPolicy, XrRecoveryState,
+    XrRestartSession,
+};
+use crate::spatial::anchors::XrAnchorPlugin;
+use crate::spatial::scene::XrScenePlugin;
+use crate::spatial::XrSpaceEventFB;
+use crate::visibility_mask::{XrVisibilityMaskChanged, XrVisibilityMaskPlugin};
 use crate::xr_init::RenderRestartPlugin;
 use crate::xr_input::hands::hand_tracking::DisableHandTracking;
 use crate::xr_input::oculus_touch::ActionSets;
 use bevy::app::{AppExit, PluginGroupBuilder};
-use bevy::ecs::system::SystemState;
+use bevy::ecs::system::{SystemParam, SystemState};
 use bevy::prelude::*;
 use bevy::render::camera::{ManualTextureView, ManualTextureViewHandle, ManualTextureViews};
-use bevy::render::pipelined_rendering::PipelinedRenderingPlugin;
+use bevy::render::extract_resource::ExtractResourcePlugin;
 use bevy::render::renderer::{render_system, RenderInstance};
 use bevy::render::settings::RenderCreation;
-use bevy::render::{Render, RenderApp, RenderPlugin, RenderSet};
+use bevy::render::{Extract, ExtractSchedule, Render, RenderApp, RenderPlugin, RenderSet};
 use bevy::window::{PresentMode, PrimaryWindow, RawHandleWrapper, WindowMode};
 use graphics::extensions::XrExtensions;
-use graphics::{XrAppInfo, XrPreferdBlendMode};
+use graphics::{XrAppInfo, XrPreferdBlendMode, XrViewConfiguration};
 use input::XrInput;
 pub use openxr as xr;
-use passthrough::{start_passthrough, supports_passthrough};
+use passthrough::{setup_passthrough, update_projected_passthrough, XrPassthrough};
 use resources::*;
 use xr::FormFactor;
-use xr_init::{xr_only, XrEnableStatus, XrRenderData};
+use xr_init::{xr_only, xr_should_render, XrEnableStatus, XrRenderData, XrSessionSetup};
+use xr_input::body_tracking::XrBodyTrackingPlugin;
 use xr_input::controllers::XrControllerType;
+use xr_input::eye_gaze::XrEyeGazePlugin;
+use xr_input::face_tracking::XrFaceTrackingPlugin;
 use xr_input::hands::emulated::HandEmulationPlugin;
 use xr_input::hands::hand_tracking::{HandTrackingData, HandTrackingPlugin};
+use xr_input::vive_trackers::XrTrackerPlugin;
 use xr_input::OpenXrInput;
 
-const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
-
 pub const LEFT_XR_TEXTURE_HANDLE: ManualTextureViewHandle = ManualTextureViewHandle(1208214591);
 pub const RIGHT_XR_TEXTURE_HANDLE: ManualTextureViewHandle = ManualTextureViewHandle(3383858418);
+pub const LEFT_FOCUS_XR_TEXTURE_HANDLE: ManualTextureViewHandle =
+    ManualTextureViewHandle(2690476017);
+pub const RIGHT_FOCUS_XR_TEXTURE_HANDLE: ManualTextureViewHandle =
+    ManualTextureViewHandle(1588151329);
+pub const SECONDARY_XR_TEXTURE_HANDLE: ManualTextureViewHandle =
+    ManualTextureViewHandle(4045672187);
+/// The texture views of the primary views, indexed like the views the runtime locates.
+pub const XR_TEXTURE_HANDLES: [ManualTextureViewHandle; 4] = [
+    LEFT_XR_TEXTURE_HANDLE,
+    RIGHT_XR_TEXTURE_HANDLE,
+    LEFT_FOCUS_XR_TEXTURE_HANDLE,
+    RIGHT_FOCUS_XR_TEXTURE_HANDLE,
+];
 
 /// Adds OpenXR support to an App
 #[derive(Default)]
 pub struct OpenXrPlugin {
     reqeusted_extensions: XrExtensions,
     prefered_blend_mode: XrPreferdBlendMode,
+    prefered_view_configuration: XrViewConfiguration,
     app_info: XrAppInfo,
 }
 
@@ -69,21 +111,50 @@ pub struct FutureXrResources(
 
 // #[derive(Resource)]
 pub struct XrEvents(pub Vec<Box<xr::EventDataBuffer>>);
-// fn mr_test(mut commands: Commands, passthrough_layer: Option<Res<XrPassthroughLayer>>) {
-//     commands.insert_resource(ClearColor(Color::rgba(0.0, 0.0, 0.0, 0.0)));
-// }
+
+/// Bevy events [`xr_begin_frame`] forwards from the OpenXR event queue.
+#[derive(SystemParam)]
+pub struct XrEventWriters<'w> {
+    pub display_refresh_rate_changed: EventWriter<'w, XrDisplayRefreshRateChanged>,
+    pub performance_notification: EventWriter<'w, XrPerformanceNotification>,
+    pub frame_errors: EventWriter<'w, XrFrameError>,
+    pub visibility_mask_changed: EventWriter<'w, XrVisibilityMaskChanged>,
+    pub spatial_entity: EventWriter<'w, XrSpaceEventFB>,
+}
 
 impl Plugin for OpenXrPlugin {
     fn build(&self, app: &mut App) {
         let mut system_state: SystemState<Query<&RawHandleWrapper, With<PrimaryWindow>>> =
             SystemState::new(&mut app.world);
+        app.add_event::<XrDisplayRefreshRateChanged>();
+        app.add_event::<XrPerformanceNotification>();
+        app.add_event::<XrFrameError>();
+        app.add_event::<XrRestartSession>();
+        app.add_event::<XrVisibilityMaskChanged>();
+        app.add_event::<XrSpaceEventFB>();
+        app.init_resource::<XrRecoveryPolicy>();
+        app.init_resource::<XrRecoveryState>();
+        app.init_resource::<XrLayerSettings>();
+        app.init_resource::<XrPassthrough>();
+        app.add_systems(XrSessionSetup, setup_passthrough);
+        app.add_systems(
+            PostUpdate,
+            update_projected_passthrough
+                .run_if(xr_only())
+                .after(TransformSystem::TransformPropagate),
+        );
         let primary_window = system_state.get(&app.world).get_single().ok().cloned();
+        // Whatever plugin adds it first, it has to know what was asked for
+        app.world
+            .get_resource_or_insert_with(XrBlendMode::default)
+            .requested = self.prefered_blend_mode;
 
         #[cfg(not(target_arch = "wasm32"))]
         match graphics::initialize_xr_graphics(
             primary_window.clone(),
             self.reqeusted_extensions.clone(),
             self.prefered_blend_mode,
+            self.prefered_view_configuration,
             self.app_info.clone(),
         ) {
             Ok((
@@ -103,6 +174,7 @@ impl Plugin for OpenXrPlugin {
                 input,
                 views,
                 frame_state,
+                session_create_info,
             )) => {
                 // std::thread::sleep(Duration::from_secs(5));
                 debug!("Configured wgpu adapter Limits: {:#?}", device.limits());
@@ -118,6 +190,11 @@ impl Plugin for OpenXrPlugin {
                 app.insert_resource(input.clone());
                 app.insert_resource(views.clone());
                 app.insert_resource(frame_state.clone());
+                app.insert_resource(XrViewConfigurationType(
+                    session_create_info.view_configuration_type(),
+                ));
+                app.insert_resource(XrSecondaryViewState::default());
+                app.insert_resource(session_create_info);
                 let xr_data = XrRenderData {
                     xr_instance,
                     xr_session: session,
@@ -154,12 +231,12 @@ impl Plugin for OpenXrPlugin {
                 app.insert_resource(XrEnableStatus::Disabled);
             }
         }
-        // app.add_systems(PreUpdate, mr_test);
         #[cfg(target_arch = "wasm32")]
         {
             app.add_plugins(RenderPlugin::default());
             app.insert_resource(XrEnableStatus::Disabled);
         }
+        app.add_plugins(ExtractResourcePlugin::<XrLayerSettings>::default());
     }
 
     fn ready(&self, app: &App) -> bool {
@@ -186,45 +263,39 @@ impl Plugin for OpenXrPlugin {
             } else {
                 app.insert_resource(DisableHandTracking::Both);
             }
-            let passthrough = data.xr_instance.exts().fb_passthrough.is_some()
-                && supports_passthrough(
-                    &data.xr_instance,
-                    data.xr_instance
-                        .system(FormFactor::HEAD_MOUNTED_DISPLAY)
-                        .unwrap(),
-                )
-                .is_ok_and(|v| v);
-
-            let (left, right) = data.xr_swapchain.get_render_views();
-            let left = ManualTextureView {
-                texture_view: left.into(),
-                size: *data.xr_resolution,
-                format: *data.xr_format,
-            };
-            let right = ManualTextureView {
-                texture_view: right.into(),
-                size: *data.xr_resolution,
-                format: *data.xr_format,
-            };
+            let passthrough = app.world.resource::<XrPassthrough>().clone();
+            let view_type = app.world.resource::<XrViewConfigurationType>().clone();
+            let frame_timings = XrFrameTimings::new(Mutex::default());
+            let frame_errors = XrFrameErrors::new(Mutex::default());
+            app.insert_resource(frame_timings.clone());
+            app.insert_resource(frame_errors.clone());
+            app.insert_resource(XrFrameWaited(false));
             app.insert_non_send_resource(XrEvents(Vec::new()));
-            app.add_systems(PreUpdate, xr_begin_frame.run_if(xr_only()));
+            app.add_systems(
+                PreUpdate,
+                (
+                    recreate_session
+                        .run_if(resource_exists_and_equals(XrRecoveryState::Recreating)),
+                    // After recreating, so the render world drops the old session first
+                    restart_lost_session.run_if(resource_exists_and_equals(XrRecoveryState::Lost)),
+                    xr_begin_frame.run_if(xr_only()),
+                    clear_frame_waited.run_if(not(xr_only())),
+                    (forward_render_frame_errors, handle_frame_errors)
+                        .chain()
+                        .run_if(xr_only()),
+                )
+                    .chain(),
+            );
             let mut manual_texture_views = app.world.resource_mut::<ManualTextureViews>();
-            manual_texture_views.insert(LEFT_XR_TEXTURE_HANDLE, left);
-            manual_texture_views.insert(RIGHT_XR_TEXTURE_HANDLE, right);
+            insert_texture_views(
+                &mut manual_texture_views,
+                &data.xr_swapchain,
+                *data.xr_resolution,
+                *data.xr_format,
+            );
             drop(manual_texture_views);
             let render_app = app.sub_app_mut(RenderApp);
 
-            if passthrough {
-                info!("Passthrough!");
-                let (pl, p) = start_passthrough(&data);
-                render_app.insert_resource(pl);
-                info!("Inserted XrPassthroughLayer resource!");
-                // app.insert_resource(p);
-                // if !app.world.contains_resource::<ClearColor>() {
-                // info!("ClearColor!");
-                // }
-            }
-
             render_app.insert_resource(data.xr_instance.clone());
             render_app.insert_resource(data.xr_session.clone());
             render_app.insert_resource(data.xr_blend_mode.clone());
@@ -234,14 +305,34 @@ impl Plugin for OpenXrPlugin {
             render_app.insert_resource(data.xr_frame_waiter.clone());
             render_app.insert_resource(data.xr_swapchain.clone());
             render_app.insert_resource(data.xr_input.clone());
-            render_app.insert_resource(data.xr_views.clone());
-            render_app.insert_resource(data.xr_frame_state.clone());
+            render_app.insert_resource(view_type);
+            render_app.insert_resource(passthrough);
+            // With pipelined rendering the main world already waits on the next frame while the
+            // render world submits this one, so the render world keeps its own frame state and
+            // views which are copied over during extraction
+            render_app.insert_resource(XrViews::new(Mutex::default()));
+            render_app.insert_resource(XrFrameState::new(Mutex::new(
+                *data.xr_frame_state.lock().unwrap(),
+            )));
+            render_app.insert_resource(XrFrameWaited(false));
+            render_app.insert_resource(XrSecondaryViewState::default());
+            render_app.insert_resource(frame_timings);
+            render_app.insert_resource(frame_errors);
             render_app.insert_resource(XrEnableStatus::Enabled);
+            render_app.add_systems(
+                ExtractSchedule,
+                (extract_session_resources, extract_frame_state),
+            );
             render_app.add_systems(
                 Render,
                 (
+                    begin_frame
+                        .run_if(xr_only())
+                        .before(post_frame)
+                        .after(RenderSet::ExtractCommands),
                     post_frame
                         .run_if(xr_only())
+                        .run_if(xr_should_render())
                         .before(render_system)
                         .after(RenderSet::ExtractCommands),
                     end_frame.run_if(xr_only()).after(render_system),
@@ -255,6 +346,7 @@ impl Plugin for OpenXrPlugin {
 pub struct DefaultXrPlugins {
     pub reqeusted_extensions: XrExtensions,
     pub prefered_blend_mode: XrPreferdBlendMode,
+    pub prefered_view_configuration: XrViewConfiguration,
     pub app_info: XrAppInfo,
 }
 
@@ -263,9 +355,9 @@ impl PluginGroup for DefaultXrPlugins {
         DefaultPlugins
             .build()
             .disable::<RenderPlugin>()
-            .disable::<PipelinedRenderingPlugin>()
             .add_before::<RenderPlugin, _>(OpenXrPlugin {
                 prefered_blend_mode: self.prefered_blend_mode,
+                prefered_view_configuration: self.prefered_view_configuration,
                 reqeusted_extensions: self.reqeusted_extensions,
                 app_info: self.app_info.clone(),
             })
@@ -273,6 +365,18 @@ impl PluginGroup for DefaultXrPlugins {
             .add_before::<OpenXrPlugin, _>(RenderRestartPlugin)
             .add(HandEmulationPlugin)
             .add(HandTrackingPlugin)
+            .add(XrFoveationPlugin)
+            .add(XrDisplayRefreshRatePlugin)
+            .add(XrPerformancePlugin)
+            .add(XrDiagnosticsPlugin)
+            .add(XrVisibilityMaskPlugin)
+            .add(XrBlendModePlugin)
+            .add(XrAnchorPlugin)
+            .add(XrScenePlugin)
+            .add(XrEyeGazePlugin)
+            .add(XrFaceTrackingPlugin)
+            .add(XrBodyTrackingPlugin)
+            .add(XrTrackerPlugin)
             .set(WindowPlugin {
                 #[cfg(not(target_os = "android"))]
                 primary_window: Some(Window {
@@ -300,20 +404,41 @@ pub fn xr_begin_frame(
     instance: Res<XrInstance>,
     session: Res<XrSession>,
     session_running: Res<XrSessionRunning>,
+    view_type: Res<XrViewConfigurationType>,
+    swapchain: Res<XrSwapchain>,
     frame_state: Res<XrFrameState>,
     frame_waiter: Res<XrFrameWaiter>,
-    swapchain: Res<XrSwapchain>,
+    mut frame_waited: ResMut<XrFrameWaited>,
     views: Res<XrViews>,
+    mut secondary_view: ResMut<XrSecondaryViewState>,
     input: Res<XrInput>,
+    frame_timings: Res<XrFrameTimings>,
+    foveation_mode: Option<Res<XrFoveationMode>>,
     mut events: NonSendMut<XrEvents>,
+    mut event_writers: XrEventWriters,
     mut app_exit: EventWriter<AppExit>,
 ) {
+    // Cleared before anything can return early, so a frame that wasn't waited on is never begun
+    frame_waited.0 = false;
+    secondary_view.active = false;
     {
         let _span = info_span!("xr_poll_events");
         let mut new_events = Vec::new();
+        // Set when the session can't be used anymore, the remaining events are still drained
+        let mut stop_frame = false;
         loop {
             let mut evt_buf = Box::new(xr::EventDataBuffer::default());
-            if let Some(event) = instance.poll_event(evt_buf.as_mut()).unwrap() {
+            let event = match instance.poll_event(evt_buf.as_mut()) {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("error: {}", e);
+                    event_writers
+                        .frame_errors
+                        .send(XrFrameError::new(XrFrameCall::PollEvent, e));
+                    break;
+                }
+            };
+            if let Some(event) = event {
                 use xr::Event::*;
                 match event {
                     SessionStateChanged(e) => {
@@ -322,26 +447,90 @@ pub fn xr_begin_frame(
                         info!("entered XR state {:?}", e.state());
                         match e.state() {
                             xr::SessionState::READY => {
-                                session.begin(VIEW_TYPE).unwrap();
-                                session_running.store(true, std::sync::atomic::Ordering::Relaxed);
+                                match swapchain.begin_session(&session, **view_type) {
+                                    Ok(_) => session_running
+                                        .store(true, std::sync::atomic::Ordering::Relaxed),
+                                    Err(e) => warn!("error: {}", e),
+                                }
                             }
                             xr::SessionState::STOPPING => {
-                                // session.end().unwrap();
-                                // session_running.store(false, std::sync::atomic::Ordering::Relaxed);
-                                // app_exit.send(AppExit);
+                                session_running.store(false, std::sync::atomic::Ordering::Relaxed);
+                                if let Err(e) = session.end() {
+                                    warn!("error: {}", e);
+                                }
                             }
-                            xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
+                            xr::SessionState::EXITING => {
                                 app_exit.send(AppExit);
-                                return;
+                                stop_frame = true;
+                            }
+                            xr::SessionState::LOSS_PENDING => {
+                                event_writers.frame_errors.send(XrFrameError::new(
+                                    XrFrameCall::PollEvent,
+                                    xr::sys::Result::SESSION_LOSS_PENDING,
+                                ));
+                                stop_frame = true;
                             }
                             _ => {}
                         }
                     }
-                    InstanceLossPending(_) => return,
+                    InstanceLossPending(_) => {
+                        event_writers.frame_errors.send(XrFrameError::new(
+                            XrFrameCall::PollEvent,
+                            xr::sys::Result::ERROR_INSTANCE_LOST,
+                        ));
+                        stop_frame = true;
+                    }
                     EventsLost(e) => {
                         warn!("lost {} XR events", e.lost_event_count());
                     }
-                    _ => {}
+                    DisplayRefreshRateChangedFB(e) => {
+                        info!(
+                            "display refresh rate changed from {}Hz to {}Hz",
+                            e.from_display_refresh_rate(),
+                            e.to_display_refresh_rate()
+                        );
+                        event_writers.display_refresh_rate_changed.send(
+                            XrDisplayRefreshRateChanged {
+                                from: e.from_display_refresh_rate(),
+                                to: e.to_display_refresh_rate(),
+                            },
+                        );
+                    }
+                    VisibilityMaskChangedKHR(e) => {
+                        if e.view_configuration_type() == **view_type {
+                            event_writers
+                                .visibility_mask_changed
+                                .send(XrVisibilityMaskChanged {
+                                    view_index: e.view_index(),
+                                });
+                        }
+                    }
+                    PerfSettingsEXT(e) => {
+                        if let Some(notification) = XrPerformanceNotification::from_xr(
+                            e.domain(),
+                            e.sub_domain(),
+                            e.from_level(),
+                            e.to_level(),
+                        ) {
+                            match notification.to_level {
+                                XrPerfNotificationLevel::Normal => {
+                                    info!("performance notification {:?}", notification)
+                                }
+                                XrPerfNotificationLevel::Warning => {
+                                    warn!("performance notification {:?}", notification)
+                                }
+                                XrPerfNotificationLevel::Impaired => {
+                                    error!("performance notification {:?}", notification)
+                                }
+                            }
+                            event_writers.performance_notification.send(notification);
+                        }
+                    }
+                    event => {
+                        if let Some(event) = XrSpaceEventFB::from_xr(&event) {
+                            event_writers.spatial_entity.send(event);
+                        }
+                    }
                 }
                 new_events.push(evt_buf);
             } else {
@@ -350,31 +539,114 @@ pub fn xr_begin_frame(
         }
 
         *events = XrEvents(new_events);
+        if stop_frame {
+            return;
+        }
+    }
+    if !session_running.load(std::sync::atomic::Ordering::Relaxed) {
+        // Waiting on frames is only allowed while the session is running
+        return;
     }
     {
         let _span = info_span!("xr_wait_frame").entered();
-        *frame_state.lock().unwrap() = match frame_waiter.lock().unwrap().wait() {
+        let start = std::time::Instant::now();
+        let (new_frame_state, secondary_active) = match swapchain.wait_frame(&frame_waiter) {
             Ok(a) => a,
             Err(e) => {
                 warn!("error: {}", e);
+                event_writers
+                    .frame_errors
+                    .send(XrFrameError::new(XrFrameCall::WaitFrame, e));
                 return;
             }
         };
-    }
-    {
-        let _span = info_span!("xr_begin_frame").entered();
-        swapchain.begin().unwrap()
+        *frame_state.lock().unwrap() = new_frame_state;
+        frame_waited.0 = true;
+        secondary_view.active = secondary_active;
+        let mut timings = frame_timings.lock().unwrap();
+        timings.wait_frame = Some(start.elapsed());
+        timings.skipped = !frame_state.lock().unwrap().should_render;
     }
     {
         let _span = info_span!("xr_locate_views").entered();
-        *views.lock().unwrap() = session
-            .locate_views(
-                VIEW_TYPE,
-                frame_state.lock().unwrap().predicted_display_time,
-                &input.stage,
-            )
-            .unwrap()
-            .1;
+        let time = frame_state.lock().unwrap().predicted_display_time;
+        match foveation::locate_views(
+            &session,
+            **view_type,
+            time,
+            &input.stage,
+            foveation_mode.as_deref(),
+        ) {
+            Ok((_, located)) => *views.lock().unwrap() = located,
+            Err(e) => {
+                // Keep rendering with the previous views
+                warn!("error: {}", e);
+                event_writers
+                    .frame_errors
+                    .send(XrFrameError::new(XrFrameCall::LocateViews, e));
+            }
+        }
+        if let (true, Some(secondary)) = (secondary_view.active, swapchain.secondary_view_info()) {
+            match session.locate_views(secondary.view_type, time, &input.stage) {
+                Ok((_, located)) => secondary_view.view = located.into_iter().next(),
+                Err(e) => {
+                    warn!("error: {}", e);
+                    secondary_view.active = false;
+                }
+            }
+        }
+    }
+}
+
+/// Clears the waited frame while [`xr_begin_frame`] doesn't run, e.g. after the session was lost,
+/// so the render world doesn't begin a frame that was never waited on.
+fn clear_frame_waited(
+    mut frame_waited: ResMut<XrFrameWaited>,
+    mut secondary_view: ResMut<XrSecondaryViewState>,
+) {
+    frame_waited.0 = false;
+    secondary_view.active = false;
+}
+
+/// Copies the state of the frame the main world just simulated into the render world.
+pub fn extract_frame_state(
+    main_frame_state: Extract<Option<Res<XrFrameState>>>,
+    main_views: Extract<Option<Res<XrViews>>>,
+    main_frame_waited: Extract<Option<Res<XrFrameWaited>>>,
+    main_secondary_view: Extract<Option<Res<XrSecondaryViewState>>>,
+    frame_state: Option<Res<XrFrameState>>,
+    views: Option<Res<XrViews>>,
+    mut frame_waited: Option<ResMut<XrFrameWaited>>,
+    mut secondary_view: Option<ResMut<XrSecondaryViewState>>,
+) {
+    if let (Some(main), Some(render)) = (main_frame_state.as_ref(), frame_state) {
+        *render.lock().unwrap() = *main.lock().unwrap();
+    }
+    if let (Some(main), Some(render)) = (main_views.as_ref(), views) {
+        render.lock().unwrap().clone_from(&main.lock().unwrap());
+    }
+    if let (Some(main), Some(render)) = (main_frame_waited.as_ref(), frame_waited.as_mut()) {
+        render.0 = main.0;
+    }
+    if let (Some(main), Some(render)) = (main_secondary_view.as_ref(), secondary_view.as_mut()) {
+        render.clone_from(main);
+    }
+}
+
+/// Begins the frame the main world waited on. This runs in the render world so the main world
+/// can wait on the next frame while this one is still being rendered.
+pub fn begin_frame(
+    swapchain: Res<XrSwapchain>,
+    frame_waited: Res<XrFrameWaited>,
+    frame_errors: Res<XrFrameErrors>,
+) {
+    if !frame_waited.0 {
+        return;
+    }
+    let _span = info_span!("xr_begin_frame").entered();
+    if let Err(e) = swapchain.begin() {
+        warn!("error: {}", e);
+        report_frame_error(&frame_errors, XrFrameCall::BeginFrame, e);
     }
 }
 
@@ -382,42 +654,88 @@ pub fn post_frame(
     resolution: Res<XrResolution>,
     format: Res<XrFormat>,
     swapchain: Res<XrSwapchain>,
+    secondary_view: Res<XrSecondaryViewState>,
+    frame_errors: Res<XrFrameErrors>,
     mut manual_texture_views: ResMut<ManualTextureViews>,
 ) {
+    if !swapchain.frame_begun() {
+        return;
+    }
     {
         let _span = info_span!("xr_acquire_image").entered();
-        swapchain.acquire_image().unwrap()
+        if let Err(e) = swapchain.acquire_image() {
+            warn!("error: {}", e);
+            report_frame_error(&frame_errors, XrFrameCall::AcquireImage, e);
+            return;
+        }
     }
     {
         let _span = info_span!("xr_wait_image").entered();
-        swapchain.wait_image().unwrap();
+        if let Err(e) = swapchain.wait_image() {
+            warn!("error: {}", e);
+            report_frame_error(&frame_errors, XrFrameCall::WaitImage, e);
+            return;
+        }
+    }
+    if secondary_view.active {
+        let _span = info_span!("xr_acquire_secondary_image").entered();
+        if let Err(e) = swapchain.acquire_secondary_image() {
+            // Only the secondary view is skipped, the primary views are fine
+            warn!("error: {}", e);
+        }
     }
     {
         let _span = info_span!("xr_update_manual_texture_views").entered();
-        let (left, right) = swapchain.get_render_views();
-        let left = ManualTextureView {
-            texture_view: left.into(),
-            size: **resolution,
-            format: **format,
+        insert_texture_views(
+            &mut manual_texture_views,
+            &swapchain,
+            **resolution,
+            **format,
+        );
+    }
+}
+
+/// Points the XR texture view handles at the currently acquired swapchain images.
+fn insert_texture_views(
+    manual_texture_views: &mut ManualTextureViews,
+    swapchain: &XrSwapchain,
+    resolution: UVec2,
+    format: wgpu::TextureFormat,
+) {
+    for (handle, view) in XR_TEXTURE_HANDLES.iter().zip(swapchain.get_render_views()) {
+        let view = ManualTextureView {
+            texture_view: view.into(),
+            size: resolution,
+            format,
         };
-        let right = ManualTextureView {
-            texture_view: right.into(),
-            size: **resolution,
-            format: **format,
+        manual_texture_views.insert(*handle, view);
+    }
+    if let (Some(info), Some(view)) = (
+        swapchain.secondary_view_info(),
+        swapchain.get_secondary_render_view(),
+    ) {
+        let view = ManualTextureView {
+            texture_view: view.into(),
+            size: info.resolution,
+            format,
         };
-        manual_texture_views.insert(LEFT_XR_TEXTURE_HANDLE, left);
-        manual_texture_views.insert(RIGHT_XR_TEXTURE_HANDLE, right);
+        manual_texture_views.insert(SECONDARY_XR_TEXTURE_HANDLE, view);
     }
 }
 
 pub fn end_frame(
+    instance: Res<XrInstance>,
     xr_frame_state: Res<XrFrameState>,
     views: Res<XrViews>,
+    secondary_view: Res<XrSecondaryViewState>,
     input: Res<XrInput>,
     swapchain: Res<XrSwapchain>,
     resolution: Res<XrResolution>,
     environment_blend_mode: Res<XrEnvironmentBlendMode>,
-    passthrough_layer: Option<Res<XrPassthroughLayer>>,
+    passthrough: Option<Res<XrPassthrough>>,
+    layer_settings: Res<XrLayerSettings>,
+    frame_timings: Res<XrFrameTimings>,
+    frame_errors: Res<XrFrameErrors>,
 ) {
     #[cfg(target_os = "android")]
     {
@@ -426,36 +744,87 @@ pub fn end_frame(
         let env = vm.attach_current_thread_as_daemon();
     }
 
+    let start = std::time::Instant::now();
+    if !swapchain.frame_begun() {
+        return;
+    }
+    let frame_state = *xr_frame_state.lock().unwrap();
+    if !frame_state.should_render || !swapchain.image_ready() {
+        // No image was acquired in `post_frame`, or the runtime doesn't want it displayed. A
+        // waited image is handed back and the frame still has to be ended
+        if let Err(e) = swapchain.release_image() {
+            warn!("error: {}", e);
+            report_frame_error(&frame_errors, XrFrameCall::ReleaseImage, e);
+        }
+        let _span = info_span!("xr_end_frame").entered();
+        if let Err(e) = swapchain
+            .end_without_layers(frame_state.predicted_display_time, **environment_blend_mode)
+        {
+            warn!("error: {}", e);
+            report_frame_error(&frame_errors, XrFrameCall::EndFrame, e);
+        }
+        frame_timings.lock().unwrap().end_frame = Some(start.elapsed());
+        return;
+    }
     {
         let _span = info_span!("xr_release_image").entered();
-        swapchain.release_image().unwrap();
+        if let Err(e) = swapchain.release_image() {
+            warn!("error: {}", e);
+            report_frame_error(&frame_errors, XrFrameCall::ReleaseImage, e);
+        }
     }
     {
         let _span = info_span!("xr_end_frame").entered();
+        // Held until the frame ended so the layer can't be destroyed while it is submitted
+        let passthrough = passthrough.as_ref().map(|passthrough| passthrough.lock());
+        let (passthrough_underlays, passthrough_overlays) = passthrough
+            .as_ref()
+            .map(|passthrough| passthrough.running_layers())
+            .unwrap_or_default();
         let result = swapchain.end(
-            xr_frame_state.lock().unwrap().predicted_display_time,
+            frame_state.predicted_display_time,
             &views.lock().unwrap(),
             &input.stage,
             **resolution,
             **environment_blend_mode,
-            passthrough_layer.map(|p| p.into_inner()),
+            &passthrough_underlays,
+            &passthrough_overlays,
+            secondary_view
+                .view
+                .as_ref()
+                .filter(|_| secondary_view.active),
+            &layer_settings.supported(instance.exts()),
         );
         match result {
             Ok(_) => {}
-            Err(e) => warn!("error: {}", e),
+            Err(e) => {
+                warn!("error: {}", e);
+                report_frame_error(&frame_errors, XrFrameCall::EndFrame, e);
+            }
         }
     }
+    frame_timings.lock().unwrap().end_frame = Some(start.elapsed());
+}
+
+/// Hands an error of the render world over to the main world, which turns it into an
+/// [`XrFrameError`] event.
+fn report_frame_error(frame_errors: &XrFrameErrors, call: XrFrameCall, error: xr::sys::Result) {
+    frame_errors
+        .lock()
+        .unwrap()
+        .push(XrFrameError::new(call, error));
 }
 
 pub fn locate_views(
     views: Res<XrViews>,
     input: Res<XrInput>,
     session: Res<XrSession>,
+    view_type: Res<XrViewConfigurationType>,
     xr_frame_state: Res<XrFrameState>,
 ) {
     let _span = info_span!("xr_locate_views").entered();
     *views.lock().unwrap() = match session.locate_views(
-        VIEW_TYPE,
+        **view_type,
         xr_frame_state.lock().unwrap().predicted_display_time,
         &input.stage,
     ) {
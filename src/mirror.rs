@@ -0,0 +1,407 @@
+use bevy::asset::load_internal_asset;
+use bevy::core_pipeline::core_2d::graph::{Core2d, Node2d};
+use bevy::core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::query::QueryItem;
+use bevy::prelude::*;
+use bevy::render::camera::ManualTextureViews;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::render_graph::{
+    NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+};
+use bevy::render::render_resource::binding_types::{sampler, texture_2d, uniform_buffer};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CachedRenderPipelineId,
+    ColorTargetState, ColorWrites, FragmentState, MultisampleState, PipelineCache, PrimitiveState,
+    RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderStages, ShaderType, SpecializedRenderPipeline, SpecializedRenderPipelines, TextureFormat,
+    TextureSampleType, UniformBuffer,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::BevyDefault;
+use bevy::render::view::{ExtractedView, ViewTarget};
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::utils::HashMap;
+
+use crate::resources::{XrResolution, XrSwapchain, XrViews};
+use crate::xr_init::xr_only;
+use crate::xr_input::xr_camera::XrCameraType;
+use crate::{post_frame, LEFT_XR_TEXTURE_HANDLE, RIGHT_XR_TEXTURE_HANDLE};
+
+const MIRROR_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x5f1d_9a3e_77c4_4b21_a0e2_c81f_3d6b_90a7);
+
+/// What the desktop window shows while XR is running.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub enum XrMirrorMode {
+    /// The full left eye image.
+    #[default]
+    LeftEye,
+    /// The full right eye image.
+    RightEye,
+    /// Both eye images next to each other.
+    SideBySide,
+    /// A crop of the left eye around its optical center, matching the window's aspect ratio, which
+    /// hides the stretched edges of the wide headset fov.
+    Cropped {
+        /// Fraction of the eye image's height that is shown.
+        height: f32,
+    },
+    /// A separate [`XrSpectatorCamera`] renders the scene again from its own point of view. Unlike
+    /// the other modes this costs a full extra render of the scene.
+    Spectator,
+}
+
+/// Marker for the desktop camera of [`XrMirrorMode::Spectator`], move it like any other camera.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrSpectatorCamera;
+
+/// The window camera that blits the eye images, mirrors the current [`XrMirrorMode`].
+#[derive(Component, Clone, Copy, Debug, ExtractComponent)]
+pub struct XrMirrorCamera {
+    pub mode: XrMirrorMode,
+}
+
+/// Shows the headset view in the primary window. The eye images are copied with a single
+/// fullscreen pass after the headset cameras rendered, so the headset doesn't pay for the mirror.
+/// On frames the compositor doesn't need, no eye image is acquired and the pass is skipped.
+pub struct XrMirrorPlugin;
+
+impl Plugin for XrMirrorPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, MIRROR_SHADER_HANDLE, "mirror.wgsl", Shader::from_wgsl);
+        app.init_resource::<XrMirrorMode>();
+        app.add_plugins(ExtractComponentPlugin::<XrMirrorCamera>::default());
+        app.add_systems(
+            PostUpdate,
+            update_mirror_cameras
+                .run_if(xr_only())
+                .run_if(resource_changed::<XrMirrorMode>),
+        );
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<XrMirrorPipeline>>()
+            .init_resource::<XrMirrorBindings>()
+            .add_systems(
+                Render,
+                (
+                    prepare_mirror_pipelines.in_set(RenderSet::Prepare),
+                    prepare_mirror_bind_groups
+                        .in_set(RenderSet::PrepareBindGroups)
+                        .after(post_frame),
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<XrMirrorNode>>(Core2d, XrMirrorLabel)
+            .add_render_graph_edges(
+                Core2d,
+                (Node2d::MainPass, XrMirrorLabel, Node2d::Tonemapping),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<XrMirrorPipeline>();
+    }
+}
+
+fn update_mirror_cameras(
+    mut commands: Commands,
+    mode: Res<XrMirrorMode>,
+    mut mirror_cameras: Query<(Entity, &mut XrMirrorCamera)>,
+    spectator_cameras: Query<Entity, With<XrSpectatorCamera>>,
+) {
+    if *mode == XrMirrorMode::Spectator {
+        for (entity, _) in &mirror_cameras {
+            commands.entity(entity).despawn_recursive();
+        }
+        if spectator_cameras.is_empty() {
+            commands.spawn((
+                Camera3dBundle {
+                    camera: Camera {
+                        order: 1,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 1.6, 2.5)
+                        .looking_at(Vec3::new(0.0, 1.0, 0.0), Vec3::Y),
+                    ..default()
+                },
+                XrCameraType::Flatscreen,
+                XrSpectatorCamera,
+            ));
+        }
+        return;
+    }
+    for entity in &spectator_cameras {
+        commands.entity(entity).despawn_recursive();
+    }
+    if mirror_cameras.is_empty() {
+        commands.spawn((
+            Camera2dBundle {
+                // After the headset cameras, which use -1
+                camera: Camera {
+                    order: 1,
+                    ..default()
+                },
+                ..default()
+            },
+            XrMirrorCamera { mode: *mode },
+        ));
+    }
+    for (_, mut camera) in &mut mirror_cameras {
+        camera.mode = *mode;
+    }
+}
+
+#[derive(Resource)]
+struct XrMirrorPipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl FromWorld for XrMirrorPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "xr_mirror_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                    uniform_buffer::<XrMirrorUniform>(false),
+                ),
+            ),
+        );
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("xr_mirror_sampler"),
+            mag_filter: bevy::render::render_resource::FilterMode::Linear,
+            min_filter: bevy::render::render_resource::FilterMode::Linear,
+            ..default()
+        });
+        Self { layout, sampler }
+    }
+}
+
+impl SpecializedRenderPipeline for XrMirrorPipeline {
+    type Key = TextureFormat;
+
+    fn specialize(&self, format: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("xr_mirror_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            push_constant_ranges: vec![],
+            vertex: fullscreen_shader_vertex_state(),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                shader: MIRROR_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+        }
+    }
+}
+
+#[derive(Component)]
+struct XrMirrorPipelineId(CachedRenderPipelineId);
+
+fn prepare_mirror_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<XrMirrorPipeline>>,
+    pipeline: Res<XrMirrorPipeline>,
+    views: Query<(Entity, &ExtractedView), With<XrMirrorCamera>>,
+) {
+    for (entity, view) in &views {
+        let format = if view.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+        let id = pipelines.specialize(&pipeline_cache, &pipeline, format);
+        commands.entity(entity).insert(XrMirrorPipelineId(id));
+    }
+}
+
+/// The uniform buffer and bind groups of every mirror camera, kept across frames.
+#[derive(Resource, Default)]
+struct XrMirrorBindings(EntityHashMap<XrMirrorViewBindings>);
+
+#[derive(Default)]
+struct XrMirrorViewBindings {
+    uniform: UniformBuffer<XrMirrorUniform>,
+    /// One per swapchain image, indexed like the images.
+    bind_groups: HashMap<usize, BindGroup>,
+}
+
+#[derive(Component)]
+struct XrMirrorBindGroup(BindGroup);
+
+/// Updates the mirror uniforms and picks the bind group of the acquired swapchain image. Nothing
+/// is bound while no image was acquired this frame, the texture views then still point at images
+/// owned by the runtime, so the mirror pass is skipped.
+#[allow(clippy::too_many_arguments)]
+fn prepare_mirror_bind_groups(
+    mut commands: Commands,
+    mut bindings: ResMut<XrMirrorBindings>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    pipeline: Res<XrMirrorPipeline>,
+    texture_views: Res<ManualTextureViews>,
+    swapchain: Option<Res<XrSwapchain>>,
+    xr_views: Option<Res<XrViews>>,
+    resolution: Option<Res<XrResolution>>,
+    views: Query<(Entity, &ExtractedView, &XrMirrorCamera)>,
+) {
+    bindings.0.retain(|entity, _| views.contains(*entity));
+    let (Some(swapchain), Some(xr_views), Some(resolution)) = (swapchain, xr_views, resolution)
+    else {
+        return;
+    };
+    if swapchain.is_changed() {
+        // A recreated session comes with new swapchain images
+        for view_bindings in bindings.0.values_mut() {
+            view_bindings.bind_groups.clear();
+        }
+    }
+    if !swapchain.image_ready() {
+        return;
+    }
+    let Some(left) = texture_views.get(&LEFT_XR_TEXTURE_HANDLE) else {
+        return;
+    };
+    // Mono view configurations only have the one view
+    let right = texture_views.get(&RIGHT_XR_TEXTURE_HANDLE).unwrap_or(left);
+    for (entity, view, camera) in &views {
+        let XrMirrorViewBindings {
+            uniform,
+            bind_groups,
+        } = bindings.0.entry(entity).or_default();
+        uniform.set(XrMirrorUniform::new(
+            camera.mode,
+            &xr_views,
+            **resolution,
+            view.viewport.zw(),
+        ));
+        uniform.write_buffer(&render_device, &render_queue);
+        let Some(binding) = uniform.binding() else {
+            continue;
+        };
+        let bind_group = bind_groups
+            .entry(swapchain.image_index())
+            .or_insert_with(|| {
+                render_device.create_bind_group(
+                    "xr_mirror_bind_group",
+                    &pipeline.layout,
+                    &BindGroupEntries::sequential((
+                        &left.texture_view,
+                        &right.texture_view,
+                        &pipeline.sampler,
+                        binding,
+                    )),
+                )
+            });
+        commands
+            .entity(entity)
+            .insert(XrMirrorBindGroup(bind_group.clone()));
+    }
+}
+
+#[derive(ShaderType, Clone, Copy, Default)]
+struct XrMirrorUniform {
+    uv_offset: Vec2,
+    uv_scale: Vec2,
+    mode: u32,
+}
+
+impl XrMirrorUniform {
+    fn new(mode: XrMirrorMode, views: &XrViews, resolution: UVec2, viewport: UVec2) -> Self {
+        let (mode, uv_offset, uv_scale) = match mode {
+            XrMirrorMode::RightEye => (1, Vec2::ZERO, Vec2::ONE),
+            XrMirrorMode::SideBySide => (2, Vec2::ZERO, Vec2::ONE),
+            XrMirrorMode::Cropped { height } => {
+                let (offset, scale) = crop_rect(views, height, resolution, viewport);
+                (0, offset, scale)
+            }
+            XrMirrorMode::LeftEye | XrMirrorMode::Spectator => (0, Vec2::ZERO, Vec2::ONE),
+        };
+        Self {
+            uv_offset,
+            uv_scale,
+            mode,
+        }
+    }
+}
+
+/// The part of the left eye image around its optical center with the window's aspect ratio.
+fn crop_rect(views: &XrViews, height: f32, resolution: UVec2, viewport: UVec2) -> (Vec2, Vec2) {
+    let views = views.lock().unwrap();
+    let Some(view) = views.first() else {
+        return (Vec2::ZERO, Vec2::ONE);
+    };
+    let (left, right) = (view.fov.angle_left.tan(), view.fov.angle_right.tan());
+    let (up, down) = (view.fov.angle_up.tan(), view.fov.angle_down.tan());
+    // The headset fov is asymmetric, the center of the lens is not the center of the image
+    let center = Vec2::new(-left / (right - left), up / (up - down));
+
+    let eye_aspect = resolution.x as f32 / resolution.y.max(1) as f32;
+    let window_aspect = viewport.x as f32 / viewport.y.max(1) as f32;
+    let mut scale = Vec2::new(height.clamp(0.0, 1.0) * window_aspect / eye_aspect, height);
+    if scale.x > 1.0 {
+        scale /= scale.x;
+    }
+    let offset = (center - scale / 2.0).clamp(Vec2::ZERO, Vec2::ONE - scale);
+    (offset, scale)
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct XrMirrorLabel;
+
+#[derive(Default)]
+struct XrMirrorNode;
+
+impl ViewNode for XrMirrorNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static XrMirrorPipelineId,
+        &'static XrMirrorBindGroup,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (target, pipeline_id, bind_group): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.0)
+        else {
+            return Ok(());
+        };
+
+        let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("xr_mirror_pass"),
+            color_attachments: &[Some(target.get_unsampled_color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_render_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group.0, &[]);
+        pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
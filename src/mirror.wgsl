@@ -0,0 +1,35 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct MirrorSettings {
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    // 0: left eye, 1: right eye, 2: side by side
+    mode: u32,
+}
+
+@group(0) @binding(0) var left_eye: texture_2d<f32>;
+@group(0) @binding(1) var right_eye: texture_2d<f32>;
+@group(0) @binding(2) var eye_sampler: sampler;
+@group(0) @binding(3) var<uniform> settings: MirrorSettings;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    var color: vec4<f32>;
+    if settings.mode == 2u {
+        // Sampling in non-uniform control flow needs an explicit level
+        if in.uv.x < 0.5 {
+            color = textureSampleLevel(left_eye, eye_sampler, vec2(in.uv.x * 2.0, in.uv.y), 0.0);
+        } else {
+            color = textureSampleLevel(right_eye, eye_sampler, vec2(in.uv.x * 2.0 - 1.0, in.uv.y), 0.0);
+        }
+    } else {
+        let uv = settings.uv_offset + in.uv * settings.uv_scale;
+        if settings.mode == 1u {
+            color = textureSampleLevel(right_eye, eye_sampler, uv, 0.0);
+        } else {
+            color = textureSampleLevel(left_eye, eye_sampler, uv, 0.0);
+        }
+    }
+    // The primary window is transparent for passthrough, the mirror should be opaque
+    return vec4(color.rgb, 1.0);
+}
@@ -1,74 +1,716 @@
-use std::ptr::null;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use openxr::SystemId;
-
-use crate::{
-    resources::{XrInstance, XrPassthroughLayer},
-    xr_init::XrRenderData,
-};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::utils::{HashMap, HashSet};
 use openxr as xr;
-use xr::{
-    sys::{
-        PassthroughCreateInfoFB, PassthroughFB, PassthroughLayerCreateInfoFB, PassthroughLayerFB,
+use xr::sys;
+use xr::SystemId;
+
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrPassthroughLayer, XrSession};
+use crate::xr_input::trackers::OpenXRTrackingRoot;
+
+/// What `XR_FB_passthrough` can do on this system.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrPassthroughCapabilities {
+    pub supported: bool,
+    /// The cameras deliver color instead of grayscale.
+    pub color: bool,
+    /// Passthrough layers can be depth tested against the projection layer.
+    pub layer_depth: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XrPassthroughState {
+    /// The extension is not enabled, the system has no passthrough or there is no session.
+    #[default]
+    Unavailable,
+    /// Nothing was created yet or it was destroyed.
+    Stopped,
+    /// Created, but the cameras are off and no layer is submitted.
+    Paused,
+    /// The passthrough layer is submitted below the projection layer every frame.
+    Running,
+}
+
+/// How the runtime maps the camera image before compositing it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum XrPassthroughColorMap {
+    #[default]
+    None,
+    /// Maps each grayscale input value to a grayscale output value.
+    MonoToMono(Box<[u8; 256]>),
+    /// Maps each grayscale input value to a color.
+    MonoToRgba(Box<[Color; 256]>),
+    /// Brightness in `-100.0..=100.0`, contrast and saturation are factors with `1.0` as neutral.
+    /// Needs version 3 of `XR_FB_passthrough`.
+    BrightnessContrastSaturation {
+        brightness: f32,
+        contrast: f32,
+        saturation: f32,
     },
-    PassthroughFlagsFB, PassthroughLayerPurposeFB,
-};
-
-pub fn start_passthrough(render_data: &XrRenderData) -> (XrPassthroughLayer, PassthroughFB) {
-    let instance = &render_data.xr_instance;
-    let entry = instance.entry();
-    let instance = instance.as_raw();
-    let session = render_data.xr_session.as_raw();
-
-    let passthrough_fb_vtable =
-        unsafe { openxr::raw::PassthroughFB::load(entry, instance) }.unwrap();
-
-    // Configuration for creating the passthrough feature
-    let passthrough_create_info = PassthroughCreateInfoFB {
-        ty: PassthroughCreateInfoFB::TYPE,
-        next: null(),
-        flags: PassthroughFlagsFB::IS_RUNNING_AT_CREATION,
-    };
+}
 
-    let mut passthrough_feature = openxr::sys::PassthroughFB::NULL;
-    let result = unsafe {
-        (passthrough_fb_vtable.create_passthrough)(
-            session,
-            &passthrough_create_info,
-            &mut passthrough_feature,
-        )
-    };
+#[derive(Clone, Debug, PartialEq)]
+pub struct XrPassthroughStyle {
+    /// Opacity of the camera image, `0.0` hides it.
+    pub opacity: f32,
+    /// Color of the edge filter, fully transparent turns it off.
+    pub edge_color: Color,
+    pub color_map: XrPassthroughColorMap,
+}
+
+impl Default for XrPassthroughStyle {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            edge_color: Color::NONE,
+            color_map: XrPassthroughColorMap::None,
+        }
+    }
+}
+
+/// Shows passthrough only where `mesh` is, as seen from the headset, e.g. a window cut into the
+/// virtual scene or the surface of a desk. The mesh follows the entity's [`GlobalTransform`] and
+/// needs `XR_FB_triangle_mesh`.
+#[derive(Component, Clone, Debug, Default)]
+pub struct XrProjectedPassthrough {
+    pub mesh: Handle<Mesh>,
+    /// Composite the passthrough on top of the rendered scene instead of below it. Below it, the
+    /// scene has to leave the area transparent to let it through.
+    pub overlay: bool,
+}
+
+/// `XR_FB_passthrough` control, shared with the render world which submits the layer while it
+/// is running. Passthrough is started with the session and restored to its last state when the
+/// session is recreated.
+#[derive(Resource, Clone, Default)]
+pub struct XrPassthrough(Arc<Mutex<PassthroughInner>>);
+
+pub(crate) struct PassthroughInner {
+    fp: Option<xr::raw::PassthroughFB>,
+    triangle_mesh_fp: Option<xr::raw::TriangleMeshFB>,
+    session: Option<xr::Session<xr::AnyGraphics>>,
+    capabilities: XrPassthroughCapabilities,
+    state: XrPassthroughState,
+    /// The state to return to once there is a session.
+    restore: XrPassthroughState,
+    style: Option<XrPassthroughStyle>,
+    pub(crate) passthrough: sys::PassthroughFB,
+    layer: sys::PassthroughLayerFB,
+    /// Whether the full view `RECONSTRUCTION` layer is shown.
+    reconstruction: bool,
+    projected: HashMap<Entity, ProjectedLayer>,
+}
+
+/// A `PROJECTED` layer with the geometry it is projected onto.
+struct ProjectedLayer {
+    layer: sys::PassthroughLayerFB,
+    mesh: sys::TriangleMeshFB,
+    geometry: sys::GeometryInstanceFB,
+    mesh_id: AssetId<Mesh>,
+    overlay: bool,
+}
+
+impl Default for PassthroughInner {
+    fn default() -> Self {
+        Self {
+            fp: None,
+            triangle_mesh_fp: None,
+            session: None,
+            capabilities: XrPassthroughCapabilities::default(),
+            state: XrPassthroughState::Unavailable,
+            restore: XrPassthroughState::Running,
+            style: None,
+            passthrough: sys::PassthroughFB::NULL,
+            layer: sys::PassthroughLayerFB::NULL,
+            reconstruction: true,
+            projected: HashMap::new(),
+        }
+    }
+}
+
+impl XrPassthrough {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, PassthroughInner> {
+        self.0.lock().unwrap()
+    }
+
+    pub fn state(&self) -> XrPassthroughState {
+        self.lock().state
+    }
+
+    pub fn capabilities(&self) -> XrPassthroughCapabilities {
+        self.lock().capabilities
+    }
+
+    /// Creates and starts passthrough, or resumes it when it is paused.
+    pub fn start(&self) -> xr::Result<()> {
+        self.lock().start()
+    }
+
+    /// Stops the cameras but keeps everything around for a quick [`XrPassthrough::resume`].
+    pub fn pause(&self) -> xr::Result<()> {
+        self.lock().pause()
+    }
+
+    pub fn resume(&self) -> xr::Result<()> {
+        self.lock().resume()
+    }
+
+    /// Destroys the passthrough objects, [`XrPassthrough::start`] creates them again.
+    pub fn destroy(&self) -> xr::Result<()> {
+        let mut inner = self.lock();
+        inner.restore = XrPassthroughState::Stopped;
+        inner.destroy()
+    }
+
+    /// Whether the passthrough layer covering the whole view is shown. Turn it off to only show
+    /// the [`XrProjectedPassthrough`] layers.
+    pub fn reconstruction_enabled(&self) -> bool {
+        self.lock().reconstruction
+    }
+
+    pub fn set_reconstruction_enabled(&self, enabled: bool) -> xr::Result<()> {
+        let mut inner = self.lock();
+        if inner.reconstruction == enabled {
+            return Ok(());
+        }
+        inner.reconstruction = enabled;
+        if inner.layer == sys::PassthroughLayerFB::NULL {
+            return Ok(());
+        }
+        let (fp, _) = inner.fp()?;
+        check(unsafe {
+            match enabled {
+                true => (fp.passthrough_layer_resume)(inner.layer),
+                false => (fp.passthrough_layer_pause)(inner.layer),
+            }
+        })
+    }
+
+    pub fn style(&self) -> Option<XrPassthroughStyle> {
+        self.lock().style.clone()
+    }
+
+    /// Sets the style of the layer, it is kept and applied again whenever the layer is created.
+    pub fn set_style(&self, style: XrPassthroughStyle) -> xr::Result<()> {
+        let mut inner = self.lock();
+        inner.style = Some(style);
+        inner.apply_style()
+    }
+
+    /// Forgets the objects of a lost session, they were destroyed along with it.
+    pub(crate) fn session_lost(&self) {
+        let mut inner = self.lock();
+        if inner.state != XrPassthroughState::Unavailable {
+            inner.restore = inner.state;
+        }
+        inner.session = None;
+        inner.passthrough = sys::PassthroughFB::NULL;
+        inner.layer = sys::PassthroughLayerFB::NULL;
+        inner.projected.clear();
+        inner.state = XrPassthroughState::Unavailable;
+    }
+}
+
+impl PassthroughInner {
+    /// The layers to submit this frame below and above the projection layer.
+    pub(crate) fn running_layers(&self) -> (Vec<XrPassthroughLayer>, Vec<XrPassthroughLayer>) {
+        let mut underlays = Vec::new();
+        let mut overlays = Vec::new();
+        if self.state != XrPassthroughState::Running {
+            return (underlays, overlays);
+        }
+        if self.reconstruction {
+            underlays.push(XrPassthroughLayer::new(self.layer));
+        }
+        for projected in self.projected.values() {
+            match projected.overlay {
+                true => overlays.push(XrPassthroughLayer::new(projected.layer)),
+                false => underlays.push(XrPassthroughLayer::new(projected.layer)),
+            }
+        }
+        (underlays, overlays)
+    }
+
+    pub(crate) fn fp(&self) -> xr::Result<(xr::raw::PassthroughFB, sys::Session)> {
+        match (&self.fp, &self.session) {
+            (Some(fp), Some(session)) => Ok((*fp, session.as_raw())),
+            _ => Err(sys::Result::ERROR_FEATURE_UNSUPPORTED),
+        }
+    }
+
+    fn start(&mut self) -> xr::Result<()> {
+        match self.state {
+            XrPassthroughState::Running => return Ok(()),
+            XrPassthroughState::Paused => return self.resume(),
+            XrPassthroughState::Unavailable | XrPassthroughState::Stopped => {}
+        }
+        let (fp, session) = self.fp()?;
+        let create_info = sys::PassthroughCreateInfoFB {
+            ty: sys::PassthroughCreateInfoFB::TYPE,
+            next: ptr::null(),
+            flags: xr::PassthroughFlagsFB::IS_RUNNING_AT_CREATION,
+        };
+        let mut passthrough = sys::PassthroughFB::NULL;
+        check(unsafe { (fp.create_passthrough)(session, &create_info, &mut passthrough) })?;
+
+        let layer_info = sys::PassthroughLayerCreateInfoFB {
+            ty: sys::PassthroughLayerCreateInfoFB::TYPE,
+            next: ptr::null(),
+            passthrough,
+            flags: match self.reconstruction {
+                true => xr::PassthroughFlagsFB::IS_RUNNING_AT_CREATION,
+                false => xr::PassthroughFlagsFB::EMPTY,
+            },
+            purpose: xr::PassthroughLayerPurposeFB::RECONSTRUCTION,
+        };
+        let mut layer = sys::PassthroughLayerFB::NULL;
+        let result = unsafe { (fp.create_passthrough_layer)(session, &layer_info, &mut layer) };
+        if let Err(err) = check(result) {
+            unsafe { (fp.destroy_passthrough)(passthrough) };
+            return Err(err);
+        }
+        // Only count as running once the style is on the layer
+        if let Err(err) = self.apply_style_to(layer) {
+            unsafe {
+                (fp.destroy_passthrough_layer)(layer);
+                (fp.destroy_passthrough)(passthrough);
+            }
+            return Err(err);
+        }
+        self.passthrough = passthrough;
+        self.layer = layer;
+        self.state = XrPassthroughState::Running;
+        self.restore = XrPassthroughState::Running;
+        Ok(())
+    }
+
+    fn pause(&mut self) -> xr::Result<()> {
+        if self.state != XrPassthroughState::Running {
+            return Ok(());
+        }
+        let (fp, _) = self.fp()?;
+        check(unsafe { (fp.passthrough_pause)(self.passthrough) })?;
+        self.state = XrPassthroughState::Paused;
+        self.restore = XrPassthroughState::Paused;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> xr::Result<()> {
+        match self.state {
+            XrPassthroughState::Paused => {}
+            XrPassthroughState::Running => return Ok(()),
+            _ => return Err(sys::Result::ERROR_CALL_ORDER_INVALID),
+        }
+        let (fp, _) = self.fp()?;
+        check(unsafe { (fp.passthrough_start)(self.passthrough) })?;
+        self.state = XrPassthroughState::Running;
+        self.restore = XrPassthroughState::Running;
+        Ok(())
+    }
+
+    fn destroy(&mut self) -> xr::Result<()> {
+        if matches!(
+            self.state,
+            XrPassthroughState::Unavailable | XrPassthroughState::Stopped
+        ) {
+            return Ok(());
+        }
+        let (fp, _) = self.fp()?;
+        for (_, projected) in self.projected.drain() {
+            projected.destroy(&fp, self.triangle_mesh_fp.as_ref());
+        }
+        let layer_result = unsafe { (fp.destroy_passthrough_layer)(self.layer) };
+        let result = unsafe { (fp.destroy_passthrough)(self.passthrough) };
+        self.layer = sys::PassthroughLayerFB::NULL;
+        self.passthrough = sys::PassthroughFB::NULL;
+        self.state = XrPassthroughState::Stopped;
+        check(layer_result)?;
+        check(result)
+    }
+
+    fn apply_style(&self) -> xr::Result<()> {
+        if self.layer == sys::PassthroughLayerFB::NULL {
+            return Ok(());
+        }
+        self.apply_style_to(self.layer)?;
+        for projected in self.projected.values() {
+            self.apply_style_to(projected.layer)?;
+        }
+        Ok(())
+    }
+
+    fn apply_style_to(&self, layer: sys::PassthroughLayerFB) -> xr::Result<()> {
+        let Some(style) = &self.style else {
+            return Ok(());
+        };
+        let (fp, _) = self.fp()?;
+        let mono_to_mono;
+        let mono_to_rgba;
+        let brightness_contrast_saturation;
+        let next: *const c_void = match &style.color_map {
+            XrPassthroughColorMap::None => ptr::null(),
+            XrPassthroughColorMap::MonoToMono(map) => {
+                mono_to_mono = sys::PassthroughColorMapMonoToMonoFB {
+                    ty: sys::PassthroughColorMapMonoToMonoFB::TYPE,
+                    next: ptr::null(),
+                    texture_color_map: **map,
+                };
+                &mono_to_mono as *const _ as *const c_void
+            }
+            XrPassthroughColorMap::MonoToRgba(map) => {
+                mono_to_rgba = sys::PassthroughColorMapMonoToRgbaFB {
+                    ty: sys::PassthroughColorMapMonoToRgbaFB::TYPE,
+                    next: ptr::null(),
+                    texture_color_map: (**map).map(color4f),
+                };
+                &mono_to_rgba as *const _ as *const c_void
+            }
+            XrPassthroughColorMap::BrightnessContrastSaturation {
+                brightness,
+                contrast,
+                saturation,
+            } => {
+                brightness_contrast_saturation = sys::PassthroughBrightnessContrastSaturationFB {
+                    ty: sys::PassthroughBrightnessContrastSaturationFB::TYPE,
+                    next: ptr::null(),
+                    brightness: *brightness,
+                    contrast: *contrast,
+                    saturation: *saturation,
+                };
+                &brightness_contrast_saturation as *const _ as *const c_void
+            }
+        };
+        let raw_style = sys::PassthroughStyleFB {
+            ty: sys::PassthroughStyleFB::TYPE,
+            next,
+            texture_opacity_factor: style.opacity,
+            edge_color: color4f(style.edge_color),
+        };
+        check(unsafe { (fp.passthrough_layer_set_style)(layer, &raw_style) })
+    }
+
+    fn create_projected(
+        &self,
+        stage: &xr::Space,
+        mesh: &Mesh,
+        mesh_id: AssetId<Mesh>,
+        overlay: bool,
+        transform: &GlobalTransform,
+    ) -> xr::Result<ProjectedLayer> {
+        let (fp, session) = self.fp()?;
+        let Some(mesh_fp) = &self.triangle_mesh_fp else {
+            return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
+        };
+        let (vertices, indices) =
+            triangle_mesh(mesh).ok_or(sys::Result::ERROR_VALIDATION_FAILURE)?;
+        let mesh_info = sys::TriangleMeshCreateInfoFB {
+            ty: sys::TriangleMeshCreateInfoFB::TYPE,
+            next: ptr::null(),
+            flags: sys::TriangleMeshFlagsFB::EMPTY,
+            winding_order: sys::WindingOrderFB::CCW,
+            vertex_count: vertices.len() as u32,
+            vertex_buffer: vertices.as_ptr(),
+            triangle_count: indices.len() as u32 / 3,
+            index_buffer: indices.as_ptr(),
+        };
+        let mut projected = ProjectedLayer {
+            layer: sys::PassthroughLayerFB::NULL,
+            mesh: sys::TriangleMeshFB::NULL,
+            geometry: sys::GeometryInstanceFB::NULL,
+            mesh_id,
+            overlay,
+        };
+        check(unsafe { (mesh_fp.create_triangle_mesh)(session, &mesh_info, &mut projected.mesh) })?;
+
+        let layer_info = sys::PassthroughLayerCreateInfoFB {
+            ty: sys::PassthroughLayerCreateInfoFB::TYPE,
+            next: ptr::null(),
+            passthrough: self.passthrough,
+            flags: xr::PassthroughFlagsFB::IS_RUNNING_AT_CREATION,
+            purpose: xr::PassthroughLayerPurposeFB::PROJECTED,
+        };
+        let result =
+            unsafe { (fp.create_passthrough_layer)(session, &layer_info, &mut projected.layer) };
+        if let Err(err) = check(result) {
+            projected.destroy(&fp, Some(mesh_fp));
+            return Err(err);
+        }
+
+        let (scale, pose) = geometry_pose(transform);
+        let geometry_info = sys::GeometryInstanceCreateInfoFB {
+            ty: sys::GeometryInstanceCreateInfoFB::TYPE,
+            next: ptr::null(),
+            layer: projected.layer,
+            mesh: projected.mesh,
+            base_space: stage.as_raw(),
+            pose,
+            scale,
+        };
+        let result = unsafe {
+            (fp.create_geometry_instance)(session, &geometry_info, &mut projected.geometry)
+        };
+        if let Err(err) = check(result) {
+            projected.destroy(&fp, Some(mesh_fp));
+            return Err(err);
+        }
+        if let Err(err) = self.apply_style_to(projected.layer) {
+            warn!("Failed to style a projected passthrough layer: {err}");
+        }
+        Ok(projected)
+    }
+
+    fn destroy_projected(&mut self, entity: Entity) {
+        let Some(projected) = self.projected.remove(&entity) else {
+            return;
+        };
+        if let Ok((fp, _)) = self.fp() {
+            projected.destroy(&fp, self.triangle_mesh_fp.as_ref());
+        }
+    }
+}
+
+impl ProjectedLayer {
+    fn destroy(&self, fp: &xr::raw::PassthroughFB, mesh_fp: Option<&xr::raw::TriangleMeshFB>) {
+        unsafe {
+            if self.geometry != sys::GeometryInstanceFB::NULL {
+                (fp.destroy_geometry_instance)(self.geometry);
+            }
+            if self.layer != sys::PassthroughLayerFB::NULL {
+                (fp.destroy_passthrough_layer)(self.layer);
+            }
+            if let (Some(mesh_fp), true) = (mesh_fp, self.mesh != sys::TriangleMeshFB::NULL) {
+                (mesh_fp.destroy_triangle_mesh)(self.mesh);
+            }
+        }
+    }
+}
 
-    if result != openxr::sys::Result::SUCCESS {
-        panic!("Failed to start passthough layer:\n{result:?}");
+impl Drop for PassthroughInner {
+    fn drop(&mut self) {
+        if let Err(err) = self.destroy() {
+            warn!("Failed to destroy passthrough: {err}");
+        }
     }
+}
 
-    let passthrough = PassthroughFB::NULL;
+/// Picks up the new session and brings passthrough back to the state it was in.
+pub(crate) fn setup_passthrough(
+    passthrough: Res<XrPassthrough>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    let Some(fp) = instance.exts().fb_passthrough else {
+        return;
+    };
+    let capabilities = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .and_then(|system| passthrough_capabilities(&instance, system));
+    let capabilities = match capabilities {
+        Ok(capabilities) => capabilities,
+        Err(err) => {
+            warn!("Failed to query passthrough support: {err}");
+            return;
+        }
+    };
+    let mut inner = passthrough.lock();
+    inner.capabilities = capabilities;
+    if !capabilities.supported {
+        info!("The system has no passthrough");
+        return;
+    }
+    inner.fp = Some(fp);
+    inner.triangle_mesh_fp = instance.exts().fb_triangle_mesh;
+    inner.session = Some((**session).clone());
+    inner.state = XrPassthroughState::Stopped;
+    let restore = inner.restore;
+    let result = match restore {
+        XrPassthroughState::Running => inner.start(),
+        XrPassthroughState::Paused => inner.start().and_then(|_| inner.pause()),
+        XrPassthroughState::Unavailable | XrPassthroughState::Stopped => Ok(()),
+    };
+    match result {
+        Ok(_) => info!("Passthrough {:?}", inner.state),
+        Err(err) => warn!("Failed to start passthrough: {err}"),
+    }
+}
 
-    let passthrough_layer_info = PassthroughLayerCreateInfoFB {
-        ty: PassthroughLayerCreateInfoFB::TYPE,
-        next: null(),
-        passthrough: passthrough,
-        flags: PassthroughFlagsFB::IS_RUNNING_AT_CREATION,
-        purpose: PassthroughLayerPurposeFB::RECONSTRUCTION,
+/// Creates, moves and destroys the layers of [`XrProjectedPassthrough`] entities.
+pub(crate) fn update_projected_passthrough(
+    passthrough: Res<XrPassthrough>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    meshes: Res<Assets<Mesh>>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    mut removed: RemovedComponents<XrProjectedPassthrough>,
+    tracking_root: Query<&GlobalTransform, With<OpenXRTrackingRoot>>,
+    query: Query<(Entity, Ref<XrProjectedPassthrough>, &GlobalTransform)>,
+) {
+    let mut inner = passthrough.lock();
+    for entity in removed.read() {
+        inner.destroy_projected(entity);
+    }
+    let modified = mesh_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    // Projected layers are created on the running passthrough feature
+    if inner.passthrough == sys::PassthroughFB::NULL {
+        return;
+    }
+    let (Ok((fp, _)), Some(_)) = (inner.fp(), inner.triangle_mesh_fp) else {
+        return;
     };
+    let root = tracking_root
+        .get_single()
+        .map(|root| root.affine().inverse())
+        .unwrap_or_default();
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for (entity, projected, transform) in &query {
+        // Poses are relative to the stage, which the tracking root places in the world
+        let transform = GlobalTransform::from(root * transform.affine());
+        let stale = match inner.projected.get(&entity) {
+            Some(layer) => projected.is_changed() || modified.contains(&layer.mesh_id),
+            None => true,
+        };
+        if stale {
+            inner.destroy_projected(entity);
+            // The mesh might still be loading, it is picked up once it is there
+            let Some(mesh) = meshes.get(&projected.mesh) else {
+                continue;
+            };
+            match inner.create_projected(
+                &input.stage,
+                mesh,
+                projected.mesh.id(),
+                projected.overlay,
+                &transform,
+            ) {
+                Ok(layer) => {
+                    inner.projected.insert(entity, layer);
+                }
+                Err(err) => {
+                    warn!("Failed to create a projected passthrough layer: {err}");
+                    continue;
+                }
+            }
+        }
+        let geometry = inner.projected[&entity].geometry;
+        let (scale, pose) = geometry_pose(&transform);
+        let geometry_transform = sys::GeometryInstanceTransformFB {
+            ty: sys::GeometryInstanceTransformFB::TYPE,
+            next: ptr::null(),
+            base_space: input.stage.as_raw(),
+            time,
+            pose,
+            scale,
+        };
+        let result = unsafe { (fp.geometry_instance_set_transform)(geometry, &geometry_transform) };
+        if let Err(err) = check(result) {
+            warn!("Failed to move a projected passthrough layer: {err}");
+        }
+    }
+}
 
-    let mut passthrough_layer_fb = PassthroughLayerFB::NULL;
-    let result = unsafe {
-        (passthrough_fb_vtable.create_passthrough_layer)(
-            session,
-            &passthrough_layer_info,
-            &mut passthrough_layer_fb,
-        )
+/// The positions and triangle indices of a triangle list mesh.
+fn triangle_mesh(mesh: &Mesh) -> Option<(Vec<xr::Vector3f>, Vec<u32>)> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
     };
+    let vertices = positions
+        .iter()
+        .map(|&[x, y, z]| xr::Vector3f { x, y, z })
+        .collect();
+    let indices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&index| index as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    Some((vertices, indices))
+}
+
+fn geometry_pose(transform: &GlobalTransform) -> (xr::Vector3f, xr::Posef) {
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+    let scale = xr::Vector3f {
+        x: scale.x,
+        y: scale.y,
+        z: scale.z,
+    };
+    let pose = xr::Posef {
+        orientation: xr::Quaternionf {
+            x: rotation.x,
+            y: rotation.y,
+            z: rotation.z,
+            w: rotation.w,
+        },
+        position: xr::Vector3f {
+            x: translation.x,
+            y: translation.y,
+            z: translation.z,
+        },
+    };
+    (scale, pose)
+}
 
-    if result != openxr::sys::Result::SUCCESS {
-        panic!("Failed to create a passthough layer:\n{result:?}");
+pub fn supports_passthrough(instance: &XrInstance, system: SystemId) -> xr::Result<bool> {
+    passthrough_capabilities(instance, system).map(|capabilities| capabilities.supported)
+}
+
+/// Reads the passthrough capabilities from the system properties.
+pub fn passthrough_capabilities(
+    instance: &XrInstance,
+    system: SystemId,
+) -> xr::Result<XrPassthroughCapabilities> {
+    if instance.exts().fb_passthrough.is_none() {
+        return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
     }
+    let mut properties = sys::SystemPassthroughPropertiesFB {
+        ty: sys::SystemPassthroughPropertiesFB::TYPE,
+        next: ptr::null(),
+        supports_passthrough: false.into(),
+    };
+    let mut properties2 = sys::SystemPassthroughProperties2FB {
+        ty: sys::SystemPassthroughProperties2FB::TYPE,
+        next: &mut properties as *mut _ as *const c_void,
+        capabilities: sys::PassthroughCapabilityFlagsFB::EMPTY,
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties2 as *mut _ as *mut c_void;
+    check(unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    })?;
+    let capabilities = properties2.capabilities;
+    // Runtimes without the second struct only fill in the first one
+    Ok(XrPassthroughCapabilities {
+        supported: properties.supports_passthrough.into()
+            || capabilities.contains(sys::PassthroughCapabilityFlagsFB::PASSTHROUGH_CAPABILITY),
+        color: capabilities.contains(sys::PassthroughCapabilityFlagsFB::COLOR),
+        layer_depth: capabilities.contains(sys::PassthroughCapabilityFlagsFB::LAYER_DEPTH),
+    })
+}
 
-    (XrPassthroughLayer::new(passthrough_layer_fb), passthrough_feature)
+fn check(result: sys::Result) -> xr::Result<()> {
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(())
 }
 
-pub fn supports_passthrough(a: &XrInstance, b: SystemId) -> Result<bool, ()> {
-    Ok(true)
+fn color4f(color: Color) -> sys::Color4f {
+    let [r, g, b, a] = color.as_linear_rgba_f32();
+    sys::Color4f { r, g, b, a }
 }
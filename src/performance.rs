@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use openxr as xr;
+
+use crate::resources::{XrInstance, XrSession};
+use crate::xr_init::{xr_only, XrSessionSetup};
+
+/// Performance level a domain of the device is asked to run at, from `XR_EXT_performance_settings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrPerformanceLevel {
+    /// Lowest clocks, for loading screens and other moments where frames can be dropped.
+    PowerSavings,
+    /// Clocks the device can hold indefinitely while staying cool.
+    SustainedLow,
+    /// Highest clocks the device can hold for long periods, the usual level while rendering.
+    SustainedHigh,
+    /// Short bursts above the sustainable clocks, the runtime may throttle back at any time.
+    Boost,
+}
+
+impl From<XrPerformanceLevel> for xr::PerfSettingsLevelEXT {
+    fn from(level: XrPerformanceLevel) -> Self {
+        match level {
+            XrPerformanceLevel::PowerSavings => xr::PerfSettingsLevelEXT::POWER_SAVINGS,
+            XrPerformanceLevel::SustainedLow => xr::PerfSettingsLevelEXT::SUSTAINED_LOW,
+            XrPerformanceLevel::SustainedHigh => xr::PerfSettingsLevelEXT::SUSTAINED_HIGH,
+            XrPerformanceLevel::Boost => xr::PerfSettingsLevelEXT::BOOST,
+        }
+    }
+}
+
+/// Requested CPU and GPU performance levels, `None` leaves the domain to the runtime.
+/// `XR_EXT_performance_settings` and `XR_EXT_thermal_query` are opt-in, enable them with
+/// `XrExtensions::enable_performance_settings`.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrPerformanceSettings {
+    pub cpu: Option<XrPerformanceLevel>,
+    pub gpu: Option<XrPerformanceLevel>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XrPerfDomain {
+    Cpu,
+    Gpu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XrPerfSubDomain {
+    Compositing,
+    Rendering,
+    Thermal,
+}
+
+/// How close a domain is to its limits, ordered from fine to impaired.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum XrPerfNotificationLevel {
+    #[default]
+    Normal,
+    /// The domain is about to hit its limits, the app should lower its workload.
+    Warning,
+    /// The domain is over its limits and the runtime is already degrading the experience.
+    Impaired,
+}
+
+/// Sent when the runtime reports that a domain changed its notification level.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XrPerformanceNotification {
+    pub domain: XrPerfDomain,
+    pub sub_domain: XrPerfSubDomain,
+    pub from_level: XrPerfNotificationLevel,
+    pub to_level: XrPerfNotificationLevel,
+}
+
+impl XrPerformanceNotification {
+    pub(crate) fn from_xr(
+        domain: xr::PerfSettingsDomainEXT,
+        sub_domain: xr::PerfSettingsSubDomainEXT,
+        from_level: xr::PerfSettingsNotificationLevelEXT,
+        to_level: xr::PerfSettingsNotificationLevelEXT,
+    ) -> Option<Self> {
+        let domain = match domain {
+            xr::PerfSettingsDomainEXT::CPU => XrPerfDomain::Cpu,
+            xr::PerfSettingsDomainEXT::GPU => XrPerfDomain::Gpu,
+            _ => return None,
+        };
+        let sub_domain = match sub_domain {
+            xr::PerfSettingsSubDomainEXT::COMPOSITING => XrPerfSubDomain::Compositing,
+            xr::PerfSettingsSubDomainEXT::RENDERING => XrPerfSubDomain::Rendering,
+            xr::PerfSettingsSubDomainEXT::THERMAL => XrPerfSubDomain::Thermal,
+            _ => return None,
+        };
+        Some(Self {
+            domain,
+            sub_domain,
+            from_level: notification_level(from_level),
+            to_level: notification_level(to_level),
+        })
+    }
+}
+
+fn notification_level(level: xr::PerfSettingsNotificationLevelEXT) -> XrPerfNotificationLevel {
+    match level {
+        xr::PerfSettingsNotificationLevelEXT::WARNING => XrPerfNotificationLevel::Warning,
+        xr::PerfSettingsNotificationLevelEXT::IMPAIRED => XrPerfNotificationLevel::Impaired,
+        _ => XrPerfNotificationLevel::Normal,
+    }
+}
+
+/// Latest thermal notification level of each domain, kept up to date from
+/// [`XrPerformanceNotification`]s and seeded with `XR_EXT_thermal_query` when available.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrThermalStatus {
+    pub cpu: XrPerfNotificationLevel,
+    pub gpu: XrPerfNotificationLevel,
+}
+
+impl XrThermalStatus {
+    /// Whether any domain warns about thermal throttling, a hint to lower the render load.
+    pub fn is_throttling(&self) -> bool {
+        self.cpu.max(self.gpu) >= XrPerfNotificationLevel::Warning
+    }
+}
+
+pub struct XrPerformancePlugin;
+
+impl Plugin for XrPerformancePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrPerformanceSettings>();
+        app.init_resource::<XrThermalStatus>();
+        // A recreated session starts with the runtime's levels again
+        app.add_systems(
+            XrSessionSetup,
+            (query_thermal_status, apply_performance_settings),
+        );
+        app.add_systems(
+            PreUpdate,
+            update_thermal_status
+                .run_if(xr_only())
+                .run_if(on_event::<XrPerformanceNotification>()),
+        );
+        app.add_systems(
+            PostUpdate,
+            apply_performance_settings
+                .run_if(xr_only())
+                .run_if(resource_changed::<XrPerformanceSettings>),
+        );
+    }
+}
+
+fn apply_performance_settings(
+    settings: Res<XrPerformanceSettings>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    if settings.cpu.is_none() && settings.gpu.is_none() {
+        return;
+    }
+    let Some(perf) = instance.exts().ext_performance_settings else {
+        warn!("Can't set performance levels, XR_EXT_performance_settings is not enabled");
+        return;
+    };
+    for (domain, level) in [
+        (xr::PerfSettingsDomainEXT::CPU, settings.cpu),
+        (xr::PerfSettingsDomainEXT::GPU, settings.gpu),
+    ] {
+        let Some(level) = level else {
+            continue;
+        };
+        let result = unsafe {
+            (perf.perf_settings_set_performance_level)(session.as_raw(), domain, level.into())
+        };
+        if result.into_raw() < 0 {
+            warn!("Failed to set {domain:?} performance level to {level:?}: {result}");
+        }
+    }
+}
+
+fn update_thermal_status(
+    mut status: ResMut<XrThermalStatus>,
+    mut notifications: EventReader<XrPerformanceNotification>,
+) {
+    for notification in notifications.read() {
+        if notification.sub_domain != XrPerfSubDomain::Thermal {
+            continue;
+        }
+        match notification.domain {
+            XrPerfDomain::Cpu => status.cpu = notification.to_level,
+            XrPerfDomain::Gpu => status.gpu = notification.to_level,
+        }
+    }
+}
+
+fn query_thermal_status(
+    mut status: ResMut<XrThermalStatus>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    // Notifications of a previous session don't apply to this one
+    *status = XrThermalStatus::default();
+    let Some(thermal) = instance.exts().ext_thermal_query else {
+        return;
+    };
+    for domain in [
+        xr::PerfSettingsDomainEXT::CPU,
+        xr::PerfSettingsDomainEXT::GPU,
+    ] {
+        let mut level = xr::PerfSettingsNotificationLevelEXT::NORMAL;
+        let mut headroom = 0.0;
+        let mut slope = 0.0;
+        let result = unsafe {
+            (thermal.thermal_get_temperature_trend)(
+                session.as_raw(),
+                domain,
+                &mut level,
+                &mut headroom,
+                &mut slope,
+            )
+        };
+        if result.into_raw() < 0 {
+            warn!("Failed to query the {domain:?} temperature trend: {result}");
+            continue;
+        }
+        match domain {
+            xr::PerfSettingsDomainEXT::CPU => status.cpu = notification_level(level),
+            _ => status.gpu = notification_level(level),
+        }
+    }
+}
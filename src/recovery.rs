@@ -0,0 +1,255 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::render::renderer::RenderDevice;
+use bevy::render::Extract;
+use openxr as xr;
+
+use crate::graphics::{self, XrSessionCreateInfo};
+use crate::input::XrInput;
+use crate::passthrough::XrPassthrough;
+use crate::resources::{
+    XrEnvironmentBlendMode, XrFormat, XrFrameErrors, XrFrameState, XrFrameWaiter, XrInstance,
+    XrResolution, XrSession, XrSessionRunning, XrSwapchain, XrViews,
+};
+use crate::xr_init::{XrEnableStatus, XrRenderData, XrSessionSetup, XrSessionTeardown};
+use crate::xr_input::hands::hand_tracking::{DisableHandTracking, HandTrackingData};
+use crate::xr_input::oculus_touch::ActionSets;
+
+/// The OpenXR call of the frame loop that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XrFrameCall {
+    PollEvent,
+    WaitFrame,
+    BeginFrame,
+    LocateViews,
+    AcquireImage,
+    WaitImage,
+    ReleaseImage,
+    EndFrame,
+}
+
+/// Sent when a call of the frame loop fails. The frame is skipped, session loss is additionally
+/// handled according to the [`XrRecoveryPolicy`].
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XrFrameError {
+    pub call: XrFrameCall,
+    pub error: xr::sys::Result,
+}
+
+impl XrFrameError {
+    pub fn new(call: XrFrameCall, error: xr::sys::Result) -> Self {
+        Self { call, error }
+    }
+
+    /// Whether the session is gone and no further frames can be submitted with it.
+    pub fn is_session_loss(&self) -> bool {
+        matches!(
+            self.error,
+            xr::sys::Result::ERROR_SESSION_LOST | xr::sys::Result::SESSION_LOSS_PENDING
+        )
+    }
+
+    /// Whether the whole instance is gone, which can't be recovered from.
+    pub fn is_instance_loss(&self) -> bool {
+        self.error == xr::sys::Result::ERROR_INSTANCE_LOST
+    }
+}
+
+/// What to do when the session is lost.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XrRecoveryPolicy {
+    /// Keep the app running and skip every XR frame until [`XrRestartSession`] is sent.
+    #[default]
+    SkipFrame,
+    /// Destroy the lost session and create a new one on the same instance and device.
+    RecreateSession,
+    /// Send [`AppExit`].
+    Exit,
+}
+
+/// Creates a new session after it was lost with [`XrRecoveryPolicy::SkipFrame`], e.g. once the
+/// user put the headset back on. Ignored while the session is running.
+#[derive(Event, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrRestartSession;
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum XrRecoveryState {
+    #[default]
+    Running,
+    /// The session was lost and the frame loop stopped.
+    Lost,
+    /// The lost session's resources were removed, a new session is created next frame once the
+    /// render world dropped its copies.
+    Recreating,
+}
+
+/// Moves errors reported by the render world into [`XrFrameError`] events.
+pub(crate) fn forward_render_frame_errors(
+    frame_errors: Res<XrFrameErrors>,
+    mut writer: EventWriter<XrFrameError>,
+) {
+    writer.send_batch(frame_errors.lock().unwrap().drain(..));
+}
+
+pub(crate) fn handle_frame_errors(
+    mut commands: Commands,
+    mut errors: EventReader<XrFrameError>,
+    mut state: ResMut<XrRecoveryState>,
+    mut app_exit: EventWriter<AppExit>,
+    policy: Res<XrRecoveryPolicy>,
+    session_running: Res<XrSessionRunning>,
+    passthrough: Res<XrPassthrough>,
+) {
+    let mut session_lost = false;
+    for error in errors.read() {
+        if error.is_instance_loss() {
+            error!("XR instance lost in {:?}, exiting", error.call);
+            app_exit.send(AppExit);
+            return;
+        }
+        session_lost |= error.is_session_loss();
+    }
+    if !session_lost || *state != XrRecoveryState::Running {
+        return;
+    }
+    session_running.store(false, std::sync::atomic::Ordering::Relaxed);
+    // Passthrough objects belong to the lost session, `setup_passthrough` restores them
+    passthrough.session_lost();
+    match *policy {
+        XrRecoveryPolicy::SkipFrame => {
+            warn!("XR session lost, skipping XR frames from now on");
+            *state = XrRecoveryState::Lost;
+            commands.insert_resource(XrEnableStatus::Waiting);
+        }
+        XrRecoveryPolicy::Exit => {
+            warn!("XR session lost, exiting");
+            app_exit.send(AppExit);
+        }
+        XrRecoveryPolicy::RecreateSession => {
+            warn!("XR session lost, recreating it");
+            *state = XrRecoveryState::Recreating;
+            drop_session(&mut commands);
+        }
+    }
+}
+
+/// Starts recreating a session lost with [`XrRecoveryPolicy::SkipFrame`] on [`XrRestartSession`].
+pub(crate) fn restart_lost_session(
+    mut commands: Commands,
+    mut restarts: EventReader<XrRestartSession>,
+    mut state: ResMut<XrRecoveryState>,
+) {
+    if restarts.read().count() == 0 || *state != XrRecoveryState::Lost {
+        return;
+    }
+    info!("Restarting the lost XR session");
+    *state = XrRecoveryState::Recreating;
+    drop_session(&mut commands);
+}
+
+/// Removes everything holding on to the lost session, so the runtime can destroy it. Plugins
+/// release their own handles in [`XrSessionTeardown`].
+fn drop_session(commands: &mut Commands) {
+    commands.add(|world: &mut World| world.run_schedule(XrSessionTeardown));
+    commands.insert_resource(XrEnableStatus::Waiting);
+    commands.remove_resource::<XrSession>();
+    commands.remove_resource::<XrFrameWaiter>();
+    commands.remove_resource::<XrSwapchain>();
+    commands.remove_resource::<XrInput>();
+    commands.remove_resource::<XrRenderData>();
+    commands.remove_resource::<HandTrackingData>();
+}
+
+/// Creates the replacement session, the frame after [`handle_frame_errors`] dropped the lost one.
+pub(crate) fn recreate_session(world: &mut World) {
+    let (Some(info), Some(instance), Some(device)) = (
+        world.get_resource::<XrSessionCreateInfo>().copied(),
+        world.get_resource::<XrInstance>().cloned(),
+        world.get_resource::<RenderDevice>().cloned(),
+    ) else {
+        error!("Can't recreate the XR session, the graphics context is missing");
+        world.insert_resource(XrRecoveryState::Lost);
+        return;
+    };
+    let (session, session_running, frame_waiter, swapchain, input) =
+        match graphics::create_session(&info, &instance, &device) {
+            Ok(resources) => resources,
+            Err(err) => {
+                // Try again next frame, the runtime might not be ready yet
+                warn!("Failed to recreate the XR session: {err}");
+                return;
+            }
+        };
+    info!("Recreated the XR session");
+
+    if let Some(action_sets) = world.get_resource::<ActionSets>() {
+        if let Err(err) = session.attach_action_sets(&action_sets.0.iter().collect::<Vec<_>>()) {
+            warn!("Failed to attach action sets to the new session: {err}");
+        }
+    }
+    let hands = instance.exts().ext_hand_tracking.is_some()
+        && world.get_resource::<DisableHandTracking>() != Some(&DisableHandTracking::Both);
+    if hands {
+        match HandTrackingData::new(&session) {
+            Ok(hands) => world.insert_resource(hands),
+            Err(err) => warn!("Failed to recreate the hand trackers: {err}"),
+        }
+    }
+
+    let render_data = XrRenderData {
+        xr_instance: instance,
+        xr_session: session.clone(),
+        xr_blend_mode: world.resource::<XrEnvironmentBlendMode>().clone(),
+        xr_resolution: world.resource::<XrResolution>().clone(),
+        xr_format: world.resource::<XrFormat>().clone(),
+        xr_session_running: session_running.clone(),
+        xr_frame_waiter: frame_waiter.clone(),
+        xr_swapchain: swapchain.clone(),
+        xr_input: input.clone(),
+        xr_views: world.resource::<XrViews>().clone(),
+        xr_frame_state: world.resource::<XrFrameState>().clone(),
+    };
+    world.insert_resource(session);
+    world.insert_resource(session_running);
+    world.insert_resource(frame_waiter);
+    world.insert_resource(swapchain);
+    world.insert_resource(input);
+    world.insert_resource(render_data);
+    world.insert_resource(XrEnableStatus::Enabled);
+    world.insert_resource(XrRecoveryState::Running);
+    // Only the per session objects are created again, XrPostSetup systems ran once already
+    world.run_schedule(XrSessionSetup);
+}
+
+/// Keeps the render world's copies of the session resources in sync with the main world, so a
+/// lost session is dropped there too and its replacement picked up.
+pub(crate) fn extract_session_resources(
+    mut commands: Commands,
+    status: Extract<Option<Res<XrEnableStatus>>>,
+    render_data: Extract<Option<Res<XrRenderData>>>,
+) {
+    let Some(status) = status.as_ref() else {
+        return;
+    };
+    if !status.is_changed() {
+        return;
+    }
+    commands.insert_resource(**status);
+    match (**status, render_data.as_ref()) {
+        (XrEnableStatus::Enabled, Some(data)) => {
+            commands.insert_resource(data.xr_session.clone());
+            commands.insert_resource(data.xr_session_running.clone());
+            commands.insert_resource(data.xr_frame_waiter.clone());
+            commands.insert_resource(data.xr_swapchain.clone());
+            commands.insert_resource(data.xr_input.clone());
+        }
+        (XrEnableStatus::Enabled, None) => {}
+        _ => {
+            commands.remove_resource::<XrSession>();
+            commands.remove_resource::<XrFrameWaiter>();
+            commands.remove_resource::<XrSwapchain>();
+            commands.remove_resource::<XrInput>();
+            commands.remove_resource::<XrRenderData>();
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use std::ptr;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 
+use crate::composition_layer::{XrCompositionLayerSettings, XrLayerSettings};
+use crate::foveation::XrFoveationDensityMap;
 use crate::resource_macros::*;
 use bevy::prelude::*;
 use openxr as xr;
@@ -14,11 +16,50 @@ xr_resource_wrapper!(XrPassthroughLayer, xr::sys::PassthroughLayerFB);
 xr_resource_wrapper!(XrEnvironmentBlendMode, xr::EnvironmentBlendMode);
 xr_resource_wrapper!(XrResolution, UVec2);
 xr_resource_wrapper!(XrFormat, wgpu::TextureFormat);
+xr_resource_wrapper!(XrViewConfigurationType, xr::ViewConfigurationType);
+/// Whether `xrWaitFrame` succeeded for the frame the main world just simulated. Extracted to the
+/// render world, which begins exactly one frame per successful wait.
+xr_resource_wrapper!(XrFrameWaited, bool);
 xr_arc_resource_wrapper!(XrSessionRunning, AtomicBool);
 xr_arc_resource_wrapper!(XrFrameWaiter, Mutex<xr::FrameWaiter>);
 xr_arc_resource_wrapper!(XrSwapchain, Swapchain);
 xr_arc_resource_wrapper!(XrFrameState, Mutex<xr::FrameState>);
 xr_arc_resource_wrapper!(XrViews, Mutex<Vec<xr::View>>);
+xr_arc_resource_wrapper!(XrFrameTimings, Mutex<FrameTimings>);
+xr_arc_resource_wrapper!(XrFrameErrors, Mutex<Vec<crate::recovery::XrFrameError>>);
+
+/// Timings of the last frame, shared between the main and render world. The durations are set
+/// when measured and taken once reported, so each is only reported once.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    /// Time spent blocked in `xrWaitFrame`.
+    pub wait_frame: Option<std::time::Duration>,
+    /// Time between releasing the swapchain image and `xrEndFrame` returning.
+    pub end_frame: Option<std::time::Duration>,
+    /// Whether the runtime told us not to render the last frame.
+    pub skipped: bool,
+}
+
+impl XrViewConfigurationType {
+    /// Number of views the runtime reports for this view configuration.
+    pub fn view_count(&self) -> usize {
+        match self.0 {
+            xr::ViewConfigurationType::PRIMARY_MONO => 1,
+            xr::ViewConfigurationType::PRIMARY_QUAD_VARJO => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// The secondary view configuration of the frame the main world just simulated, like the first
+/// person observer used for mixed reality capture. Copied into the render world with the frame
+/// state.
+#[derive(Resource, Clone, Default)]
+pub struct XrSecondaryViewState {
+    /// Whether the runtime wants the secondary view to be rendered this frame.
+    pub active: bool,
+    pub view: Option<xr::View>,
+}
 
 pub enum Swapchain {
     Vulkan(SwapchainInner<xr::Vulkan>),
@@ -31,12 +72,89 @@ impl Swapchain {
         }
     }
 
-    pub(crate) fn get_render_views(&self) -> (wgpu::TextureView, wgpu::TextureView) {
+    /// Begins the session, enabling the secondary view configuration if the swapchain has one.
+    pub(crate) fn begin_session(
+        &self,
+        session: &XrSession,
+        view_type: xr::ViewConfigurationType,
+    ) -> xr::Result<()> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.begin_session(session, view_type),
+        }
+    }
+
+    /// Waits on the next frame, also returning whether the secondary view is active.
+    pub(crate) fn wait_frame(
+        &self,
+        frame_waiter: &XrFrameWaiter,
+    ) -> xr::Result<(xr::FrameState, bool)> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.wait_frame(frame_waiter),
+        }
+    }
+
+    pub(crate) fn secondary_view_info(&self) -> Option<SecondaryViewInfo> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.secondary.as_ref().map(|s| s.info),
+        }
+    }
+
+    pub(crate) fn as_raw(&self) -> xr::sys::Swapchain {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.handle.lock().unwrap().as_raw(),
+        }
+    }
+
+    /// The fragment density maps `XR_FB_foveation_vulkan` attached to the swapchain images, indexed
+    /// like the images. Empty unless FB foveation is enabled.
+    pub fn foveation_density_maps(&self) -> &[XrFoveationDensityMap] {
+        match self {
+            Swapchain::Vulkan(swapchain) => &swapchain.foveation_density_maps,
+        }
+    }
+
+    /// The fragment density map of the currently acquired image.
+    pub fn current_foveation_density_map(&self) -> Option<XrFoveationDensityMap> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain
+                .foveation_density_maps
+                .get(*swapchain.image_index.lock().unwrap())
+                .copied(),
+        }
+    }
+
+    /// The index of the currently acquired image.
+    pub(crate) fn image_index(&self) -> usize {
+        match self {
+            Swapchain::Vulkan(swapchain) => *swapchain.image_index.lock().unwrap(),
+        }
+    }
+
+    pub(crate) fn frame_begun(&self) -> bool {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.frame_begun.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn image_ready(&self) -> bool {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.image_ready.load(Ordering::Relaxed),
+        }
+    }
+
+    /// One texture view per primary view, in the order the runtime locates them.
+    pub(crate) fn get_render_views(&self) -> Vec<wgpu::TextureView> {
         match self {
             Swapchain::Vulkan(swapchain) => swapchain.get_render_views(),
         }
     }
 
+    pub(crate) fn get_secondary_render_view(&self) -> Option<wgpu::TextureView> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.get_secondary_render_view(),
+        }
+    }
+
     pub(crate) fn acquire_image(&self) -> xr::Result<()> {
         match self {
             Swapchain::Vulkan(swapchain) => swapchain.acquire_image(),
@@ -55,6 +173,26 @@ impl Swapchain {
         }
     }
 
+    /// Acquires and waits on the image of the secondary view's swapchain.
+    pub(crate) fn acquire_secondary_image(&self) -> xr::Result<()> {
+        match self {
+            Swapchain::Vulkan(swapchain) => swapchain.acquire_secondary_image(),
+        }
+    }
+
+    /// Ends the frame without submitting any layers, for frames the compositor doesn't display.
+    pub(crate) fn end_without_layers(
+        &self,
+        predicted_display_time: xr::Time,
+        environment_blend_mode: xr::EnvironmentBlendMode,
+    ) -> xr::Result<()> {
+        match self {
+            Swapchain::Vulkan(swapchain) => {
+                swapchain.end_without_layers(predicted_display_time, environment_blend_mode)
+            }
+        }
+    }
+
     pub(crate) fn end(
         &self,
         predicted_display_time: xr::Time,
@@ -62,7 +200,10 @@ impl Swapchain {
         stage: &xr::Space,
         resolution: UVec2,
         environment_blend_mode: xr::EnvironmentBlendMode,
-        passthrough_layer: Option<&XrPassthroughLayer>,
+        passthrough_underlays: &[XrPassthroughLayer],
+        passthrough_overlays: &[XrPassthroughLayer],
+        secondary_view: Option<&xr::View>,
+        layer_settings: &XrLayerSettings,
     ) -> xr::Result<()> {
         match self {
             Swapchain::Vulkan(swapchain) => swapchain.end(
@@ -71,7 +212,10 @@ impl Swapchain {
                 stage,
                 resolution,
                 environment_blend_mode,
-                passthrough_layer,
+                passthrough_underlays,
+                passthrough_overlays,
+                secondary_view,
+                layer_settings,
             ),
         }
     }
@@ -82,48 +226,234 @@ pub struct SwapchainInner<G: xr::Graphics> {
     pub(crate) handle: Mutex<xr::Swapchain<G>>,
     pub(crate) buffers: Vec<wgpu::Texture>,
     pub(crate) image_index: Mutex<usize>,
+    /// Set by a successful `xrBeginFrame`, so `xrEndFrame` is only called for begun frames.
+    pub(crate) frame_begun: AtomicBool,
+    /// Set while an image is acquired and not released yet, waited on or not.
+    pub(crate) image_acquired: AtomicBool,
+    /// Set once the acquired image was waited on, so it is only released when it is ours.
+    pub(crate) image_ready: AtomicBool,
+    /// Set if a secondary view configuration was enabled for the session.
+    pub(crate) secondary: Option<SecondaryViewSwapchain<G>>,
+    /// One per image when the swapchain was created for `XR_FB_foveation_vulkan`.
+    pub(crate) foveation_density_maps: Vec<XrFoveationDensityMap>,
+}
+
+/// How the secondary view configuration of a session is set up.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SecondaryViewInfo {
+    pub(crate) view_type: xr::ViewConfigurationType,
+    pub(crate) resolution: UVec2,
+    pub(crate) blend_mode: xr::EnvironmentBlendMode,
+}
+
+/// The swapchain the secondary view is rendered into. `openxrs` has no support for secondary
+/// view configurations, so beginning the session, waiting and ending frames go through the raw
+/// function pointers while one is enabled.
+pub struct SecondaryViewSwapchain<G: xr::Graphics> {
+    pub(crate) info: SecondaryViewInfo,
+    pub(crate) session: xr::Session<G>,
+    pub(crate) handle: Mutex<xr::Swapchain<G>>,
+    pub(crate) buffers: Vec<wgpu::Texture>,
+    pub(crate) image_index: Mutex<usize>,
+    pub(crate) image_acquired: AtomicBool,
+    pub(crate) image_ready: AtomicBool,
+}
+
+/// Hands back an image whose wait failed, so the swapchain doesn't run out of images. The
+/// runtime may refuse to release an image that was never waited on, which only leaves the
+/// swapchain as stuck as it already was.
+fn abandon_image<G: xr::Graphics>(handle: &mut xr::Swapchain<G>, acquired: &AtomicBool) {
+    if !acquired.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = handle.release_image() {
+        warn!("Failed to release a swapchain image that couldn't be waited on: {e}");
+    }
 }
 
 impl<G: xr::Graphics> SwapchainInner<G> {
     fn begin(&self) -> xr::Result<()> {
-        self.stream.lock().unwrap().begin()
+        self.stream.lock().unwrap().begin()?;
+        self.frame_begun.store(true, Ordering::Relaxed);
+        Ok(())
     }
 
-    fn get_render_views(&self) -> (wgpu::TextureView, wgpu::TextureView) {
+    fn begin_session(
+        &self,
+        session: &XrSession,
+        view_type: xr::ViewConfigurationType,
+    ) -> xr::Result<()> {
+        let Some(secondary) = &self.secondary else {
+            return session.begin(view_type).map(|_| ());
+        };
+        let secondary_begin_info = xr::sys::SecondaryViewConfigurationSessionBeginInfoMSFT {
+            ty: xr::sys::SecondaryViewConfigurationSessionBeginInfoMSFT::TYPE,
+            next: ptr::null(),
+            view_configuration_count: 1,
+            enabled_view_configuration_types: &secondary.info.view_type,
+        };
+        let begin_info = xr::sys::SessionBeginInfo {
+            ty: xr::sys::SessionBeginInfo::TYPE,
+            next: &secondary_begin_info as *const _ as *const _,
+            primary_view_configuration_type: view_type,
+        };
+        let result =
+            unsafe { (session.instance().fp().begin_session)(session.as_raw(), &begin_info) };
+        if result.into_raw() < 0 {
+            return Err(result);
+        }
+        Ok(())
+    }
+
+    fn wait_frame(
+        &self,
+        frame_waiter: &Mutex<xr::FrameWaiter>,
+    ) -> xr::Result<(xr::FrameState, bool)> {
+        // Stays locked during the raw call too, waits must not overlap
+        let mut frame_waiter = frame_waiter.lock().unwrap();
+        let Some(secondary) = &self.secondary else {
+            return Ok((frame_waiter.wait()?, false));
+        };
+        let mut secondary_state = xr::sys::SecondaryViewConfigurationStateMSFT {
+            ty: xr::sys::SecondaryViewConfigurationStateMSFT::TYPE,
+            next: ptr::null_mut(),
+            view_configuration_type: secondary.info.view_type,
+            active: false.into(),
+        };
+        let mut secondary_frame_state = xr::sys::SecondaryViewConfigurationFrameStateMSFT {
+            ty: xr::sys::SecondaryViewConfigurationFrameStateMSFT::TYPE,
+            next: ptr::null_mut(),
+            view_configuration_count: 1,
+            view_configuration_states: &mut secondary_state,
+        };
+        let mut frame_state = xr::sys::FrameState {
+            ty: xr::sys::FrameState::TYPE,
+            next: &mut secondary_frame_state as *mut _ as *mut _,
+            predicted_display_time: xr::Time::from_nanos(0),
+            predicted_display_period: xr::Duration::from_nanos(0),
+            should_render: false.into(),
+        };
+        let wait_info = xr::sys::FrameWaitInfo {
+            ty: xr::sys::FrameWaitInfo::TYPE,
+            next: ptr::null(),
+        };
+        let result = unsafe {
+            (secondary.session.instance().fp().wait_frame)(
+                secondary.session.as_raw(),
+                &wait_info,
+                &mut frame_state,
+            )
+        };
+        if result.into_raw() < 0 {
+            return Err(result);
+        }
+        Ok((
+            xr::FrameState {
+                predicted_display_time: frame_state.predicted_display_time,
+                predicted_display_period: frame_state.predicted_display_period,
+                should_render: frame_state.should_render.into(),
+            },
+            secondary_state.active.into(),
+        ))
+    }
+
+    fn get_render_views(&self) -> Vec<wgpu::TextureView> {
         let texture = &self.buffers[*self.image_index.lock().unwrap()];
 
-        (
-            texture.create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                array_layer_count: Some(1),
-                ..Default::default()
-            }),
-            texture.create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                array_layer_count: Some(1),
-                base_array_layer: 1,
-                ..Default::default()
-            }),
-        )
+        (0..texture.depth_or_array_layers())
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    array_layer_count: Some(1),
+                    base_array_layer: layer,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    fn get_secondary_render_view(&self) -> Option<wgpu::TextureView> {
+        let secondary = self.secondary.as_ref()?;
+        let texture = &secondary.buffers[*secondary.image_index.lock().unwrap()];
+        Some(texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            array_layer_count: Some(1),
+            ..Default::default()
+        }))
+    }
+
+    fn acquire_secondary_image(&self) -> xr::Result<()> {
+        let Some(secondary) = &self.secondary else {
+            return Ok(());
+        };
+        let mut handle = secondary.handle.lock().unwrap();
+        *secondary.image_index.lock().unwrap() = handle.acquire_image()? as _;
+        secondary.image_acquired.store(true, Ordering::Relaxed);
+        if let Err(e) = handle.wait_image(xr::Duration::INFINITE) {
+            abandon_image(&mut handle, &secondary.image_acquired);
+            return Err(e);
+        }
+        secondary.image_ready.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Releases the secondary view's image, returning the secondary swapchain if the image can be
+    /// submitted.
+    fn release_secondary_image(&self) -> Option<&SecondaryViewSwapchain<G>> {
+        let secondary = self.secondary.as_ref()?;
+        if !secondary.image_ready.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        secondary.image_acquired.store(false, Ordering::Relaxed);
+        match secondary.handle.lock().unwrap().release_image() {
+            Ok(()) => Some(secondary),
+            Err(e) => {
+                warn!("error: {}", e);
+                None
+            }
+        }
     }
 
     fn acquire_image(&self) -> xr::Result<()> {
         let image_index = self.handle.lock().unwrap().acquire_image()?;
         *self.image_index.lock().unwrap() = image_index as _;
+        self.image_acquired.store(true, Ordering::Relaxed);
         Ok(())
     }
 
     fn wait_image(&self) -> xr::Result<()> {
-        self.handle
-            .lock()
-            .unwrap()
-            .wait_image(xr::Duration::INFINITE)
+        let mut handle = self.handle.lock().unwrap();
+        if let Err(e) = handle.wait_image(xr::Duration::INFINITE) {
+            abandon_image(&mut handle, &self.image_acquired);
+            return Err(e);
+        }
+        self.image_ready.store(true, Ordering::Relaxed);
+        Ok(())
     }
 
     fn release_image(&self) -> xr::Result<()> {
+        if !self.image_ready.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.image_acquired.store(false, Ordering::Relaxed);
         self.handle.lock().unwrap().release_image()
     }
 
+    fn end_without_layers(
+        &self,
+        predicted_display_time: xr::Time,
+        environment_blend_mode: xr::EnvironmentBlendMode,
+    ) -> xr::Result<()> {
+        if !self.frame_begun.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.release_secondary_image();
+        self.stream
+            .lock()
+            .unwrap()
+            .end(predicted_display_time, environment_blend_mode, &[])
+    }
+
     fn end(
         &self,
         predicted_display_time: xr::Time,
@@ -131,7 +461,10 @@ impl<G: xr::Graphics> SwapchainInner<G> {
         stage: &xr::Space,
         resolution: UVec2,
         environment_blend_mode: xr::EnvironmentBlendMode,
-        passthrough_layer: Option<&XrPassthroughLayer>,
+        passthrough_underlays: &[XrPassthroughLayer],
+        passthrough_overlays: &[XrPassthroughLayer],
+        secondary_view: Option<&xr::View>,
+        layer_settings: &XrLayerSettings,
     ) -> xr::Result<()> {
         let rect = xr::Rect2Di {
             offset: xr::Offset2Di { x: 0, y: 0 },
@@ -140,78 +473,173 @@ impl<G: xr::Graphics> SwapchainInner<G> {
                 height: resolution.y as _,
             },
         };
+        if !self.frame_begun.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
         let swapchain = self.handle.lock().unwrap();
         if views.len() == 0 {
             warn!("views are len of 0");
-            return Ok(());
-        }
-        match passthrough_layer {
-            Some(pass) => {
-                // info!("Rendering with pass through");
-                let passthrough_layer = xr::sys::CompositionLayerPassthroughFB {
-                    ty: CompositionLayerPassthroughFB::TYPE,
-                    next: ptr::null(),
-                    flags: CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA,
-                    space: xr::sys::Space::NULL,
-                    layer_handle: pass.0,
-                };
-                self.stream.lock().unwrap().end(
-                    predicted_display_time,
-                    environment_blend_mode,
-                    &[
-                        unsafe {
-                            &*(&passthrough_layer as *const _ as *const CompositionLayerBase<G>)
-                        },
-                        &xr::CompositionLayerProjection::new()
-                            .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
-                            .space(stage)
-                            .views(&[
-                                xr::CompositionLayerProjectionView::new()
-                                    .pose(views[0].pose)
-                                    .fov(views[0].fov)
-                                    .sub_image(
-                                        xr::SwapchainSubImage::new()
-                                            .swapchain(&swapchain)
-                                            .image_array_index(0)
-                                            .image_rect(rect),
-                                    ),
-                                xr::CompositionLayerProjectionView::new()
-                                    .pose(views[1].pose)
-                                    .fov(views[1].fov)
-                                    .sub_image(
-                                        xr::SwapchainSubImage::new()
-                                            .swapchain(&swapchain)
-                                            .image_array_index(1)
-                                            .image_rect(rect),
-                                    ),
-                            ]),
-                    ],
-                )
-            }
-            None => self.stream.lock().unwrap().end(
+            return self.submit(
                 predicted_display_time,
                 environment_blend_mode,
-                &[&xr::CompositionLayerProjection::new().space(stage).views(&[
-                    xr::CompositionLayerProjectionView::new()
-                        .pose(views[0].pose)
-                        .fov(views[0].fov)
-                        .sub_image(
-                            xr::SwapchainSubImage::new()
-                                .swapchain(&swapchain)
-                                .image_array_index(0)
-                                .image_rect(rect),
-                        ),
-                    xr::CompositionLayerProjectionView::new()
-                        .pose(views[1].pose)
-                        .fov(views[1].fov)
-                        .sub_image(
-                            xr::SwapchainSubImage::new()
-                                .swapchain(&swapchain)
-                                .image_array_index(1)
-                                .image_rect(rect),
-                        ),
-                ])],
+                &[],
+                stage,
+                secondary_view,
+                &layer_settings.secondary,
+            );
+        }
+        let projection_views = views
+            .iter()
+            .enumerate()
+            .map(|(index, view)| xr::sys::CompositionLayerProjectionView {
+                ty: xr::sys::CompositionLayerProjectionView::TYPE,
+                next: ptr::null(),
+                pose: view.pose,
+                fov: view.fov,
+                sub_image: xr::sys::SwapchainSubImage {
+                    swapchain: swapchain.as_raw(),
+                    image_rect: rect,
+                    image_array_index: index as u32,
+                },
+            })
+            .collect::<Vec<_>>();
+        // Built by hand, `xr::CompositionLayerProjection` can't chain the color scale and blend
+        // structs
+        let mut projection_chain = layer_settings.projection.chain();
+        let projection = xr::sys::CompositionLayerProjection {
+            ty: xr::sys::CompositionLayerProjection::TYPE,
+            next: projection_chain.head(),
+            layer_flags: layer_settings.projection.flags.unwrap_or(
+                match passthrough_underlays.is_empty() {
+                    true => CompositionLayerFlags::EMPTY,
+                    false => CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA,
+                },
             ),
+            space: stage.as_raw(),
+            view_count: projection_views.len() as u32,
+            views: projection_views.as_ptr(),
+        };
+        // The chains are complete before any of them is linked, so they don't move afterwards
+        let mut passthrough_chains = passthrough_underlays
+            .iter()
+            .chain(passthrough_overlays)
+            .map(|_| layer_settings.passthrough.chain())
+            .collect::<Vec<_>>();
+        let passthrough_layers = passthrough_underlays
+            .iter()
+            .chain(passthrough_overlays)
+            .zip(&mut passthrough_chains)
+            .map(|(layer, chain)| xr::sys::CompositionLayerPassthroughFB {
+                ty: CompositionLayerPassthroughFB::TYPE,
+                next: chain.head(),
+                flags: layer_settings
+                    .passthrough
+                    .flags
+                    .unwrap_or(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA),
+                space: xr::sys::Space::NULL,
+                layer_handle: layer.0,
+            })
+            .collect::<Vec<_>>();
+        let (underlays, overlays) = passthrough_layers.split_at(passthrough_underlays.len());
+        let layers =
+            underlays
+                .iter()
+                .map(|layer| unsafe { &*(layer as *const _ as *const CompositionLayerBase<G>) })
+                .chain([unsafe { &*(&projection as *const _ as *const CompositionLayerBase<G>) }])
+                .chain(overlays.iter().map(|layer| unsafe {
+                    &*(layer as *const _ as *const CompositionLayerBase<G>)
+                }))
+                .collect::<Vec<_>>();
+        self.submit(
+            predicted_display_time,
+            environment_blend_mode,
+            &layers,
+            stage,
+            secondary_view,
+            &layer_settings.secondary,
+        )
+    }
+
+    /// Ends the frame with the given primary layers, adding the secondary view's projection layer
+    /// if its image was rendered this frame.
+    fn submit(
+        &self,
+        predicted_display_time: xr::Time,
+        environment_blend_mode: xr::EnvironmentBlendMode,
+        layers: &[&CompositionLayerBase<G>],
+        stage: &xr::Space,
+        secondary_view: Option<&xr::View>,
+        secondary_settings: &XrCompositionLayerSettings,
+    ) -> xr::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        let (Some(secondary), Some(view)) = (self.release_secondary_image(), secondary_view) else {
+            return stream.end(predicted_display_time, environment_blend_mode, layers);
+        };
+        let handle = secondary.handle.lock().unwrap();
+        let projection_view = xr::sys::CompositionLayerProjectionView {
+            ty: xr::sys::CompositionLayerProjectionView::TYPE,
+            next: ptr::null(),
+            pose: view.pose,
+            fov: view.fov,
+            sub_image: xr::sys::SwapchainSubImage {
+                swapchain: handle.as_raw(),
+                image_rect: xr::Rect2Di {
+                    offset: xr::Offset2Di { x: 0, y: 0 },
+                    extent: xr::Extent2Di {
+                        width: secondary.info.resolution.x as _,
+                        height: secondary.info.resolution.y as _,
+                    },
+                },
+                image_array_index: 0,
+            },
+        };
+        let mut chain = secondary_settings.chain();
+        let projection = xr::sys::CompositionLayerProjection {
+            ty: xr::sys::CompositionLayerProjection::TYPE,
+            next: chain.head(),
+            layer_flags: secondary_settings
+                .flags
+                .unwrap_or(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA),
+            space: stage.as_raw(),
+            view_count: 1,
+            views: &projection_view,
+        };
+        let secondary_layers =
+            [&projection as *const _ as *const xr::sys::CompositionLayerBaseHeader];
+        let layer_info = xr::sys::SecondaryViewConfigurationLayerInfoMSFT {
+            ty: xr::sys::SecondaryViewConfigurationLayerInfoMSFT::TYPE,
+            next: ptr::null(),
+            view_configuration_type: secondary.info.view_type,
+            environment_blend_mode: secondary.info.blend_mode,
+            layer_count: secondary_layers.len() as u32,
+            layers: secondary_layers.as_ptr(),
+        };
+        let secondary_end_info = xr::sys::SecondaryViewConfigurationFrameEndInfoMSFT {
+            ty: xr::sys::SecondaryViewConfigurationFrameEndInfoMSFT::TYPE,
+            next: ptr::null(),
+            view_configuration_count: 1,
+            view_configuration_layers_info: &layer_info,
+        };
+        let layers = layers
+            .iter()
+            .map(|layer| *layer as *const _ as *const xr::sys::CompositionLayerBaseHeader)
+            .collect::<Vec<_>>();
+        let end_info = xr::sys::FrameEndInfo {
+            ty: xr::sys::FrameEndInfo::TYPE,
+            next: &secondary_end_info as *const _ as *const _,
+            display_time: predicted_display_time,
+            environment_blend_mode,
+            layer_count: layers.len() as u32,
+            layers: layers.as_ptr(),
+        };
+        // The frame stream stays locked so this can't race the regular `xrEndFrame`
+        let result = unsafe {
+            (secondary.session.instance().fp().end_frame)(secondary.session.as_raw(), &end_info)
+        };
+        drop(stream);
+        if result.into_raw() < 0 {
+            return Err(result);
         }
+        Ok(())
     }
 }
@@ -0,0 +1,562 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use openxr as xr;
+use xr::sys;
+
+use super::fb::{FbAnchors, XrSpaceEventFB};
+use super::msft::MsftAnchors;
+use super::{locate_space, transform_to_pose, XrUuid};
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown};
+use crate::xr_input::trackers::OpenXRTrackingRoot;
+
+/// The extension anchors are created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrAnchorBackend {
+    /// `XR_FB_spatial_entity` with its storage and query extensions.
+    Fb,
+    /// `XR_MSFT_spatial_anchor`, saving needs `XR_MSFT_spatial_anchor_persistence`.
+    Msft,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XrAnchorState {
+    /// The anchor is being created or loaded.
+    #[default]
+    Pending,
+    /// The runtime knows where the anchor is, its [`Transform`] follows it.
+    Tracking,
+    /// The anchor exists but the runtime lost track of it, the [`Transform`] keeps the last pose.
+    NotTracking,
+    /// The anchor could not be created, see [`XrAnchorEvent::Failed`].
+    Failed,
+}
+
+/// Pins an entity to the real world. An anchor is created at the entity's [`GlobalTransform`]
+/// once it is spawned, from then on its [`Transform`] is set in world space to where the runtime
+/// tracks the anchor, so anchor entities should not have a parent.
+#[derive(Component, Clone, Debug, Default)]
+pub struct XrAnchor {
+    persistent: bool,
+    state: XrAnchorState,
+    uuid: Option<XrUuid>,
+    saved: bool,
+    requested: bool,
+}
+
+impl XrAnchor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An anchor that is saved to local storage as soon as it is created, it is loaded again in
+    /// later sessions while [`XrAnchors::load_saved`] is set.
+    pub fn persistent() -> Self {
+        Self {
+            persistent: true,
+            ..default()
+        }
+    }
+
+    pub fn state(&self) -> XrAnchorState {
+        self.state
+    }
+
+    /// Identifies the anchor across sessions, `None` until it is created.
+    pub fn uuid(&self) -> Option<XrUuid> {
+        self.uuid
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved
+    }
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub enum XrAnchorEvent {
+    Created {
+        entity: Entity,
+        uuid: XrUuid,
+    },
+    Saved {
+        entity: Entity,
+        uuid: XrUuid,
+    },
+    Erased {
+        entity: Entity,
+        uuid: XrUuid,
+    },
+    /// A saved anchor was loaded, either onto the entity with its [`XrAnchor::uuid`] or onto a
+    /// newly spawned entity.
+    Loaded {
+        entity: Entity,
+        uuid: XrUuid,
+    },
+    /// A request failed, `entity` is `None` when loading the saved anchors failed.
+    Failed {
+        entity: Option<Entity>,
+        error: sys::Result,
+    },
+}
+
+/// The anchors of the session. Saving and erasing is queued and happens asynchronously, the
+/// outcome is sent as [`XrAnchorEvent`].
+#[derive(Resource)]
+pub struct XrAnchors {
+    /// Loads the anchors saved to local storage when the session starts.
+    pub load_saved: bool,
+    backend: Option<AnchorBackend>,
+    requests: Vec<(Entity, AnchorRequest)>,
+    spaces: HashMap<Entity, AnchorSpace>,
+}
+
+impl Default for XrAnchors {
+    fn default() -> Self {
+        Self {
+            load_saved: true,
+            backend: None,
+            requests: Vec::new(),
+            spaces: HashMap::new(),
+        }
+    }
+}
+
+impl XrAnchors {
+    /// `None` when neither anchor extension is enabled or there is no session.
+    pub fn backend(&self) -> Option<XrAnchorBackend> {
+        self.backend.as_ref().map(|backend| match backend {
+            AnchorBackend::Fb(_) => XrAnchorBackend::Fb,
+            AnchorBackend::Msft(_) => XrAnchorBackend::Msft,
+        })
+    }
+
+    /// Saves the anchor of `entity` to local storage, once it is created.
+    pub fn save(&mut self, entity: Entity) {
+        self.requests.push((entity, AnchorRequest::Save));
+    }
+
+    /// Erases the anchor of `entity` from local storage. The anchor itself stays, despawn the
+    /// entity to get rid of it.
+    pub fn erase(&mut self, entity: Entity) {
+        self.requests.push((entity, AnchorRequest::Erase));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum AnchorRequest {
+    Save,
+    Erase,
+}
+
+/// The space an anchor is located with.
+pub(crate) struct AnchorSpace {
+    pub(crate) space: sys::Space,
+    pub(crate) uuid: XrUuid,
+    /// The anchor the space was created from, only used by the MSFT backend.
+    pub(crate) msft_anchor: sys::SpatialAnchorMSFT,
+}
+
+impl AnchorSpace {
+    pub(crate) fn new(space: sys::Space, uuid: XrUuid) -> Self {
+        Self {
+            space,
+            uuid,
+            msft_anchor: sys::SpatialAnchorMSFT::NULL,
+        }
+    }
+}
+
+/// The outcome of a backend request.
+pub(crate) enum AnchorCompletion {
+    Created {
+        entity: Entity,
+        result: xr::Result<AnchorSpace>,
+    },
+    Saved {
+        entity: Entity,
+        result: xr::Result<()>,
+    },
+    Erased {
+        entity: Entity,
+        uuid: XrUuid,
+        result: xr::Result<()>,
+    },
+    Loaded {
+        result: xr::Result<AnchorSpace>,
+    },
+}
+
+pub(crate) enum AnchorBackend {
+    Fb(FbAnchors),
+    Msft(MsftAnchors),
+}
+
+impl AnchorBackend {
+    fn create(
+        &mut self,
+        entity: Entity,
+        stage: &xr::Space,
+        pose: xr::Posef,
+        time: xr::Time,
+    ) -> xr::Result<()> {
+        match self {
+            Self::Fb(fb) => fb.create(entity, stage, pose, time),
+            Self::Msft(msft) => msft.create(entity, stage, pose, time),
+        }
+    }
+
+    fn save(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        match self {
+            Self::Fb(fb) => fb.save(entity, anchor),
+            Self::Msft(msft) => msft.save(entity, anchor),
+        }
+    }
+
+    fn erase(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        match self {
+            Self::Fb(fb) => fb.erase(entity, anchor),
+            Self::Msft(msft) => msft.erase(entity, anchor),
+        }
+    }
+
+    fn load(&mut self) -> xr::Result<()> {
+        match self {
+            Self::Fb(fb) => fb.load(),
+            Self::Msft(msft) => msft.load(),
+        }
+    }
+
+    fn destroy(&mut self, anchor: AnchorSpace) {
+        match self {
+            Self::Fb(fb) => fb.destroy(anchor),
+            Self::Msft(msft) => msft.destroy(anchor),
+        }
+    }
+
+    fn poll(&mut self, events: &mut EventReader<XrSpaceEventFB>) -> Vec<AnchorCompletion> {
+        match self {
+            Self::Fb(fb) => fb.poll(events.read()),
+            Self::Msft(msft) => {
+                events.clear();
+                msft.poll()
+            }
+        }
+    }
+}
+
+/// Creates, tracks, saves and loads [`XrAnchor`] entities. Anchors fail with
+/// `ERROR_EXTENSION_NOT_PRESENT` when neither `XR_FB_spatial_entity` nor `XR_MSFT_spatial_anchor`
+/// is enabled.
+pub struct XrAnchorPlugin;
+
+impl Plugin for XrAnchorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<XrAnchorEvent>();
+        app.init_resource::<XrAnchors>();
+        app.add_systems(XrSessionSetup, setup_anchors);
+        app.add_systems(XrSessionTeardown, teardown_anchors);
+        app.add_systems(
+            PreUpdate,
+            (update_anchors, track_anchors)
+                .chain()
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+        app.add_systems(
+            PostUpdate,
+            create_anchors
+                .run_if(xr_only())
+                .after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn setup_anchors(
+    mut anchors: ResMut<XrAnchors>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    mut events: EventWriter<XrAnchorEvent>,
+) {
+    let load_saved = anchors.load_saved;
+    let mut backend = if FbAnchors::supported(&instance) {
+        AnchorBackend::Fb(FbAnchors::new(&instance, &session))
+    } else if MsftAnchors::supported(&instance) {
+        match MsftAnchors::new(&instance, &session) {
+            Ok(msft) => AnchorBackend::Msft(msft),
+            Err(err) => {
+                warn!("Failed to connect to the spatial anchor store: {err}");
+                return;
+            }
+        }
+    } else {
+        return;
+    };
+    if load_saved {
+        if let Err(err) = backend.load() {
+            warn!("Failed to load the saved anchors: {err}");
+            events.send(XrAnchorEvent::Failed {
+                entity: None,
+                error: err,
+            });
+        }
+    }
+    anchors.backend = Some(backend);
+}
+
+/// The spaces and the store connection of a lost session go away with it, the anchors are
+/// created or loaded again in [`setup_anchors`].
+fn teardown_anchors(mut anchors: ResMut<XrAnchors>, mut query: Query<&mut XrAnchor>) {
+    if let Some(AnchorBackend::Msft(msft)) = &mut anchors.backend {
+        msft.session_lost();
+    }
+    anchors.backend = None;
+    anchors.requests.clear();
+    anchors.spaces.clear();
+    let load_saved = anchors.load_saved;
+    for mut anchor in &mut query {
+        anchor.state = XrAnchorState::Pending;
+        // Saved anchors come back with the others, everything else is created anew
+        if !(anchor.saved && load_saved) {
+            anchor.uuid = None;
+            anchor.saved = false;
+            anchor.requested = false;
+        }
+    }
+}
+
+/// Handles the completed requests and sends the queued ones.
+fn update_anchors(
+    mut commands: Commands,
+    mut anchors: ResMut<XrAnchors>,
+    mut space_events: EventReader<XrSpaceEventFB>,
+    mut removed: RemovedComponents<XrAnchor>,
+    mut events: EventWriter<XrAnchorEvent>,
+    mut query: Query<(Entity, &mut XrAnchor)>,
+) {
+    let XrAnchors {
+        backend,
+        requests,
+        spaces,
+        ..
+    } = &mut *anchors;
+    let Some(backend) = backend else {
+        space_events.clear();
+        for (entity, _) in requests.drain(..) {
+            events.send(XrAnchorEvent::Failed {
+                entity: Some(entity),
+                error: sys::Result::ERROR_EXTENSION_NOT_PRESENT,
+            });
+        }
+        return;
+    };
+    for entity in removed.read() {
+        if let Some(space) = spaces.remove(&entity) {
+            backend.destroy(space);
+        }
+    }
+    for completion in backend.poll(&mut space_events) {
+        match completion {
+            AnchorCompletion::Created { entity, result } => {
+                let Ok((_, mut anchor)) = query.get_mut(entity) else {
+                    // Despawned while the anchor was being created
+                    if let Ok(space) = result {
+                        backend.destroy(space);
+                    }
+                    continue;
+                };
+                match result {
+                    Ok(space) => {
+                        anchor.uuid = Some(space.uuid);
+                        if anchor.persistent {
+                            requests.push((entity, AnchorRequest::Save));
+                        }
+                        events.send(XrAnchorEvent::Created {
+                            entity,
+                            uuid: space.uuid,
+                        });
+                        spaces.insert(entity, space);
+                    }
+                    Err(err) => {
+                        warn!("Failed to create an anchor: {err}");
+                        anchor.state = XrAnchorState::Failed;
+                        events.send(XrAnchorEvent::Failed {
+                            entity: Some(entity),
+                            error: err,
+                        });
+                    }
+                }
+            }
+            AnchorCompletion::Saved { entity, result } => {
+                let Ok((_, mut anchor)) = query.get_mut(entity) else {
+                    continue;
+                };
+                match (result, anchor.uuid) {
+                    (Ok(_), Some(uuid)) => {
+                        anchor.saved = true;
+                        events.send(XrAnchorEvent::Saved { entity, uuid });
+                    }
+                    (Ok(_), None) => {}
+                    (Err(err), _) => {
+                        warn!("Failed to save an anchor: {err}");
+                        events.send(XrAnchorEvent::Failed {
+                            entity: Some(entity),
+                            error: err,
+                        });
+                    }
+                }
+            }
+            AnchorCompletion::Erased {
+                entity,
+                uuid,
+                result,
+            } => match result {
+                Ok(_) => {
+                    if let Ok((_, mut anchor)) = query.get_mut(entity) {
+                        anchor.saved = false;
+                    }
+                    events.send(XrAnchorEvent::Erased { entity, uuid });
+                }
+                Err(err) => {
+                    warn!("Failed to erase anchor {uuid}: {err}");
+                    events.send(XrAnchorEvent::Failed {
+                        entity: Some(entity),
+                        error: err,
+                    });
+                }
+            },
+            AnchorCompletion::Loaded { result } => {
+                let space = match result {
+                    Ok(space) => space,
+                    Err(err) => {
+                        warn!("Failed to load a saved anchor: {err}");
+                        events.send(XrAnchorEvent::Failed {
+                            entity: None,
+                            error: err,
+                        });
+                        continue;
+                    }
+                };
+                let uuid = space.uuid;
+                if spaces.values().any(|space| space.uuid == uuid) {
+                    backend.destroy(space);
+                    continue;
+                }
+                let existing = query
+                    .iter_mut()
+                    .find(|(_, anchor)| anchor.uuid == Some(uuid));
+                let entity = match existing {
+                    Some((entity, mut anchor)) => {
+                        anchor.saved = true;
+                        entity
+                    }
+                    None => commands
+                        .spawn((
+                            XrAnchor {
+                                persistent: true,
+                                state: XrAnchorState::Pending,
+                                uuid: Some(uuid),
+                                saved: true,
+                                requested: true,
+                            },
+                            SpatialBundle::default(),
+                        ))
+                        .id(),
+                };
+                spaces.insert(entity, space);
+                events.send(XrAnchorEvent::Loaded { entity, uuid });
+            }
+        }
+    }
+    requests.retain(|&(entity, request)| {
+        let Some(space) = spaces.get(&entity) else {
+            // Wait for anchors that are still being created
+            return query
+                .get(entity)
+                .is_ok_and(|(_, anchor)| anchor.state == XrAnchorState::Pending);
+        };
+        let (result, action) = match request {
+            AnchorRequest::Save => (backend.save(entity, space), "save"),
+            AnchorRequest::Erase => (backend.erase(entity, space), "erase"),
+        };
+        if let Err(err) = result {
+            warn!("Failed to {action} anchor {}: {err}", space.uuid);
+            events.send(XrAnchorEvent::Failed {
+                entity: Some(entity),
+                error: err,
+            });
+        }
+        false
+    });
+}
+
+/// Moves the anchor entities to where the runtime tracks them.
+fn track_anchors(
+    anchors: Res<XrAnchors>,
+    instance: Res<XrInstance>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    tracking_root: Query<&GlobalTransform, With<OpenXRTrackingRoot>>,
+    mut query: Query<(&mut XrAnchor, &mut Transform)>,
+) {
+    let root = tracking_root
+        .get_single()
+        .map(|root| root.compute_transform())
+        .unwrap_or_default();
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for (entity, space) in &anchors.spaces {
+        let Ok((mut anchor, mut transform)) = query.get_mut(*entity) else {
+            continue;
+        };
+        match locate_space(&instance, space.space, &input.stage, time) {
+            Ok(Some(pose)) => {
+                *transform = root * pose;
+                anchor.state = XrAnchorState::Tracking;
+            }
+            Ok(None) => anchor.state = XrAnchorState::NotTracking,
+            Err(err) => {
+                warn!("Failed to locate anchor {}: {err}", space.uuid);
+                anchor.state = XrAnchorState::NotTracking;
+            }
+        }
+    }
+}
+
+/// Creates the anchors of newly spawned [`XrAnchor`] entities.
+fn create_anchors(
+    mut anchors: ResMut<XrAnchors>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut events: EventWriter<XrAnchorEvent>,
+    tracking_root: Query<&GlobalTransform, With<OpenXRTrackingRoot>>,
+    mut query: Query<(Entity, &mut XrAnchor, &GlobalTransform)>,
+) {
+    let root = tracking_root
+        .get_single()
+        .map(|root| root.affine().inverse())
+        .unwrap_or_default();
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for (entity, mut anchor, transform) in &mut query {
+        if anchor.requested {
+            continue;
+        }
+        anchor.requested = true;
+        // Poses are relative to the stage, which the tracking root places in the world
+        let transform = GlobalTransform::from(root * transform.affine()).compute_transform();
+        let result = match anchors.backend.as_mut() {
+            Some(backend) => {
+                backend.create(entity, &input.stage, transform_to_pose(&transform), time)
+            }
+            None => Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT),
+        };
+        if let Err(err) = result {
+            warn!("Failed to create an anchor: {err}");
+            anchor.state = XrAnchorState::Failed;
+            events.send(XrAnchorEvent::Failed {
+                entity: Some(entity),
+                error: err,
+            });
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::ptr;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use openxr as xr;
+use xr::sys;
+
+use super::anchors::{AnchorCompletion, AnchorSpace};
+use super::XrUuid;
+
+/// Completion of an asynchronous `XR_FB_spatial_entity` request, forwarded from the event queue.
+#[derive(Event, Clone, Copy, Debug)]
+pub enum XrSpaceEventFB {
+    AnchorCreated {
+        request: sys::AsyncRequestIdFB,
+        result: sys::Result,
+        space: sys::Space,
+        uuid: XrUuid,
+    },
+    StatusSet {
+        request: sys::AsyncRequestIdFB,
+        result: sys::Result,
+        space: sys::Space,
+        uuid: XrUuid,
+    },
+    QueryResultsAvailable {
+        request: sys::AsyncRequestIdFB,
+    },
+    QueryComplete {
+        request: sys::AsyncRequestIdFB,
+        result: sys::Result,
+    },
+    Saved {
+        request: sys::AsyncRequestIdFB,
+        result: sys::Result,
+        uuid: XrUuid,
+    },
+    Erased {
+        request: sys::AsyncRequestIdFB,
+        result: sys::Result,
+        uuid: XrUuid,
+    },
+}
+
+impl XrSpaceEventFB {
+    pub(crate) fn from_xr(event: &xr::Event) -> Option<Self> {
+        use xr::Event::*;
+        Some(match event {
+            SpatialAnchorCreateCompleteFB(e) => Self::AnchorCreated {
+                request: e.request_id(),
+                result: e.result(),
+                space: e.space(),
+                uuid: e.uuid().into(),
+            },
+            SpaceSetStatusCompleteFB(e) => Self::StatusSet {
+                request: e.request_id(),
+                result: e.result(),
+                space: e.space(),
+                uuid: e.uuid().into(),
+            },
+            SpaceQueryResultsAvailableFB(e) => Self::QueryResultsAvailable {
+                request: e.request_id(),
+            },
+            SpaceQueryCompleteFB(e) => Self::QueryComplete {
+                request: e.request_id(),
+                result: e.result(),
+            },
+            SpaceSaveCompleteFB(e) => Self::Saved {
+                request: e.request_id(),
+                result: e.result(),
+                uuid: e.uuid().into(),
+            },
+            SpaceEraseCompleteFB(e) => Self::Erased {
+                request: e.request_id(),
+                result: e.result(),
+                uuid: e.uuid().into(),
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// What an outstanding request is for.
+enum FbRequest {
+    Create(Entity),
+    /// Created anchors have to be made storable before they can be saved.
+    EnableStorable(Entity),
+    Save(Entity),
+    Erase(Entity),
+    Query,
+    /// Loaded anchors have to be made locatable before they can be tracked.
+    EnableLocatable,
+}
+
+/// Spatial anchors through `XR_FB_spatial_entity`, its storage and query extensions. Every call
+/// completes asynchronously with an [`XrSpaceEventFB`].
+pub(crate) struct FbAnchors {
+    instance: xr::Instance,
+    session: sys::Session,
+    pending: HashMap<sys::AsyncRequestIdFB, FbRequest>,
+    /// Saves held back until the anchor of the entity is storable.
+    waiting_saves: HashMap<Entity, sys::Space>,
+    completions: Vec<AnchorCompletion>,
+}
+
+impl FbAnchors {
+    pub(crate) fn new(instance: &xr::Instance, session: &xr::Session<xr::AnyGraphics>) -> Self {
+        Self {
+            instance: instance.clone(),
+            session: session.as_raw(),
+            pending: HashMap::new(),
+            waiting_saves: HashMap::new(),
+            completions: Vec::new(),
+        }
+    }
+
+    pub(crate) fn supported(instance: &xr::Instance) -> bool {
+        let exts = instance.exts();
+        exts.fb_spatial_entity.is_some()
+            && exts.fb_spatial_entity_storage.is_some()
+            && exts.fb_spatial_entity_query.is_some()
+    }
+
+    pub(crate) fn create(
+        &mut self,
+        entity: Entity,
+        stage: &xr::Space,
+        pose: xr::Posef,
+        time: xr::Time,
+    ) -> xr::Result<()> {
+        let fp = self.instance.exts().fb_spatial_entity.unwrap();
+        let info = sys::SpatialAnchorCreateInfoFB {
+            ty: sys::SpatialAnchorCreateInfoFB::TYPE,
+            next: ptr::null(),
+            space: stage.as_raw(),
+            pose_in_space: pose,
+            time,
+        };
+        let mut request = sys::AsyncRequestIdFB::from_raw(0);
+        check(unsafe { (fp.create_spatial_anchor)(self.session, &info, &mut request) })?;
+        self.pending.insert(request, FbRequest::Create(entity));
+        Ok(())
+    }
+
+    pub(crate) fn save(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        // xrSaveSpaceFB fails until the space is storable
+        let enabling = self
+            .pending
+            .values()
+            .any(|request| matches!(request, FbRequest::EnableStorable(e) if *e == entity));
+        if enabling {
+            self.waiting_saves.insert(entity, anchor.space);
+            return Ok(());
+        }
+        self.save_space(entity, anchor.space)
+    }
+
+    fn save_space(&mut self, entity: Entity, space: sys::Space) -> xr::Result<()> {
+        let fp = self.instance.exts().fb_spatial_entity_storage.unwrap();
+        let info = sys::SpaceSaveInfoFB {
+            ty: sys::SpaceSaveInfoFB::TYPE,
+            next: ptr::null(),
+            space,
+            location: sys::SpaceStorageLocationFB::LOCAL,
+            persistence_mode: sys::SpacePersistenceModeFB::INDEFINITE,
+        };
+        let mut request = sys::AsyncRequestIdFB::from_raw(0);
+        check(unsafe { (fp.save_space)(self.session, &info, &mut request) })?;
+        self.pending.insert(request, FbRequest::Save(entity));
+        Ok(())
+    }
+
+    pub(crate) fn erase(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        let fp = self.instance.exts().fb_spatial_entity_storage.unwrap();
+        let info = sys::SpaceEraseInfoFB {
+            ty: sys::SpaceEraseInfoFB::TYPE,
+            next: ptr::null(),
+            space: anchor.space,
+            location: sys::SpaceStorageLocationFB::LOCAL,
+        };
+        let mut request = sys::AsyncRequestIdFB::from_raw(0);
+        check(unsafe { (fp.erase_space)(self.session, &info, &mut request) })?;
+        self.pending.insert(request, FbRequest::Erase(entity));
+        Ok(())
+    }
+
+    /// Queries every anchor saved to local storage.
+    pub(crate) fn load(&mut self) -> xr::Result<()> {
+        let request = query_spaces(
+            &self.instance,
+            self.session,
+            sys::SpaceComponentTypeFB::STORABLE,
+        )?;
+        self.pending.insert(request, FbRequest::Query);
+        Ok(())
+    }
+
+    pub(crate) fn destroy(&mut self, anchor: AnchorSpace) {
+        self.waiting_saves.retain(|_, space| *space != anchor.space);
+        unsafe { (self.instance.fp().destroy_space)(anchor.space) };
+    }
+
+    pub(crate) fn poll<'a>(
+        &mut self,
+        events: impl Iterator<Item = &'a XrSpaceEventFB>,
+    ) -> Vec<AnchorCompletion> {
+        for event in events {
+            self.handle_event(event);
+        }
+        std::mem::take(&mut self.completions)
+    }
+
+    fn handle_event(&mut self, event: &XrSpaceEventFB) {
+        match *event {
+            XrSpaceEventFB::AnchorCreated {
+                request,
+                result,
+                space,
+                uuid,
+            } => {
+                let Some(FbRequest::Create(entity)) = self.pending.remove(&request) else {
+                    return;
+                };
+                if let Err(err) = check(result) {
+                    self.completions.push(AnchorCompletion::Created {
+                        entity,
+                        result: Err(err),
+                    });
+                    return;
+                }
+                let anchor = AnchorSpace::new(space, uuid);
+                match enable_component(&self.instance, space, sys::SpaceComponentTypeFB::STORABLE) {
+                    Ok(Some(request)) => {
+                        self.pending
+                            .insert(request, FbRequest::EnableStorable(entity));
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!("Failed to make anchor {uuid} storable: {err}"),
+                }
+                self.completions.push(AnchorCompletion::Created {
+                    entity,
+                    result: Ok(anchor),
+                });
+            }
+            XrSpaceEventFB::StatusSet {
+                request,
+                result,
+                space,
+                uuid,
+            } => match self.pending.remove(&request) {
+                Some(FbRequest::EnableStorable(entity)) => {
+                    let result = match result {
+                        sys::Result::ERROR_SPACE_COMPONENT_STATUS_ALREADY_SET_FB => Ok(()),
+                        result => check(result),
+                    };
+                    let waiting = self.waiting_saves.remove(&entity);
+                    match (result, waiting) {
+                        (Ok(()), Some(space)) => {
+                            if let Err(err) = self.save_space(entity, space) {
+                                self.completions.push(AnchorCompletion::Saved {
+                                    entity,
+                                    result: Err(err),
+                                });
+                            }
+                        }
+                        (Ok(()), None) => {}
+                        // The save can't happen without the storable component
+                        (Err(err), Some(_)) => self.completions.push(AnchorCompletion::Saved {
+                            entity,
+                            result: Err(err),
+                        }),
+                        (Err(err), None) => warn!("Failed to make anchor {uuid} storable: {err}"),
+                    }
+                }
+                Some(FbRequest::EnableLocatable) => {
+                    let result = match result {
+                        sys::Result::ERROR_SPACE_COMPONENT_STATUS_ALREADY_SET_FB => Ok(()),
+                        result => check(result),
+                    };
+                    self.completions.push(AnchorCompletion::Loaded {
+                        result: result.map(|_| AnchorSpace::new(space, uuid)),
+                    });
+                }
+                _ => {}
+            },
+            XrSpaceEventFB::QueryResultsAvailable { request } => {
+                if !matches!(self.pending.get(&request), Some(FbRequest::Query)) {
+                    return;
+                }
+                match retrieve_query_results(&self.instance, self.session, request) {
+                    Ok(results) => {
+                        for result in results {
+                            self.enable_locatable(result.space, result.uuid.into());
+                        }
+                    }
+                    Err(err) => self
+                        .completions
+                        .push(AnchorCompletion::Loaded { result: Err(err) }),
+                }
+            }
+            XrSpaceEventFB::QueryComplete { request, result } => {
+                if !matches!(self.pending.get(&request), Some(FbRequest::Query)) {
+                    return;
+                }
+                self.pending.remove(&request);
+                if let Err(err) = check(result) {
+                    self.completions
+                        .push(AnchorCompletion::Loaded { result: Err(err) });
+                }
+            }
+            XrSpaceEventFB::Saved {
+                request, result, ..
+            } => {
+                if let Some(FbRequest::Save(entity)) = self.pending.remove(&request) {
+                    self.completions.push(AnchorCompletion::Saved {
+                        entity,
+                        result: check(result),
+                    });
+                }
+            }
+            XrSpaceEventFB::Erased {
+                request,
+                result,
+                uuid,
+            } => {
+                if let Some(FbRequest::Erase(entity)) = self.pending.remove(&request) {
+                    self.completions.push(AnchorCompletion::Erased {
+                        entity,
+                        uuid,
+                        result: check(result),
+                    });
+                }
+            }
+        }
+    }
+
+    fn enable_locatable(&mut self, space: sys::Space, uuid: XrUuid) {
+        match enable_component(&self.instance, space, sys::SpaceComponentTypeFB::LOCATABLE) {
+            Ok(Some(request)) => {
+                self.pending.insert(request, FbRequest::EnableLocatable);
+            }
+            Ok(None) => self.completions.push(AnchorCompletion::Loaded {
+                result: Ok(AnchorSpace::new(space, uuid)),
+            }),
+            Err(err) => self
+                .completions
+                .push(AnchorCompletion::Loaded { result: Err(err) }),
+        }
+    }
+}
+
+const MAX_QUERY_RESULTS: u32 = 128;
+
+/// Enables a component of the space, `None` when it already was enabled.
+pub(crate) fn enable_component(
+    instance: &xr::Instance,
+    space: sys::Space,
+    component_type: sys::SpaceComponentTypeFB,
+) -> xr::Result<Option<sys::AsyncRequestIdFB>> {
+    let fp = instance.exts().fb_spatial_entity.unwrap();
+    let info = sys::SpaceComponentStatusSetInfoFB {
+        ty: sys::SpaceComponentStatusSetInfoFB::TYPE,
+        next: ptr::null(),
+        component_type,
+        enabled: true.into(),
+        timeout: xr::Duration::NONE,
+    };
+    let mut request = sys::AsyncRequestIdFB::from_raw(0);
+    match unsafe { (fp.set_space_component_status)(space, &info, &mut request) } {
+        sys::Result::ERROR_SPACE_COMPONENT_STATUS_ALREADY_SET_FB => Ok(None),
+        result => check(result).map(|_| Some(request)),
+    }
+}
+
+/// Queries the spaces in local storage that have a component, the results are announced with
+/// [`XrSpaceEventFB::QueryResultsAvailable`].
+pub(crate) fn query_spaces(
+    instance: &xr::Instance,
+    session: sys::Session,
+    component_type: sys::SpaceComponentTypeFB,
+) -> xr::Result<sys::AsyncRequestIdFB> {
+    let fp = instance.exts().fb_spatial_entity_query.unwrap();
+    let location_filter = sys::SpaceStorageLocationFilterInfoFB {
+        ty: sys::SpaceStorageLocationFilterInfoFB::TYPE,
+        next: ptr::null(),
+        location: sys::SpaceStorageLocationFB::LOCAL,
+    };
+    let component_filter = sys::SpaceComponentFilterInfoFB {
+        ty: sys::SpaceComponentFilterInfoFB::TYPE,
+        next: &location_filter as *const _ as *const _,
+        component_type,
+    };
+    let info = sys::SpaceQueryInfoFB {
+        ty: sys::SpaceQueryInfoFB::TYPE,
+        next: ptr::null(),
+        query_action: sys::SpaceQueryActionFB::LOAD,
+        max_result_count: MAX_QUERY_RESULTS,
+        timeout: xr::Duration::NONE,
+        filter: &component_filter as *const _ as *const _,
+        exclude_filter: ptr::null(),
+    };
+    let mut request = sys::AsyncRequestIdFB::from_raw(0);
+    check(unsafe { (fp.query_spaces)(session, &info as *const _ as *const _, &mut request) })?;
+    Ok(request)
+}
+
+pub(crate) fn retrieve_query_results(
+    instance: &xr::Instance,
+    session: sys::Session,
+    request: sys::AsyncRequestIdFB,
+) -> xr::Result<Vec<sys::SpaceQueryResultFB>> {
+    let fp = instance.exts().fb_spatial_entity_query.unwrap();
+    let mut results = sys::SpaceQueryResultsFB {
+        ty: sys::SpaceQueryResultsFB::TYPE,
+        next: ptr::null_mut(),
+        result_capacity_input: 0,
+        result_count_output: 0,
+        results: ptr::null_mut(),
+    };
+    check(unsafe { (fp.retrieve_space_query_results)(session, request, &mut results) })?;
+    let mut buffer = vec![
+        sys::SpaceQueryResultFB {
+            space: sys::Space::NULL,
+            uuid: sys::UuidEXT { data: [0; 16] },
+        };
+        results.result_count_output as usize
+    ];
+    results.result_capacity_input = buffer.len() as u32;
+    results.results = buffer.as_mut_ptr();
+    check(unsafe { (fp.retrieve_space_query_results)(session, request, &mut results) })?;
+    buffer.truncate(results.result_count_output as usize);
+    Ok(buffer)
+}
+
+pub(crate) fn check(result: sys::Result) -> xr::Result<()> {
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+pub mod anchors;
+mod fb;
+mod msft;
+pub mod scene;
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::ptr;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+use openxr as xr;
+use xr::sys;
+
+use crate::xr_input::{QuatConv, Vec3Conv};
+
+pub use fb::XrSpaceEventFB;
+
+/// Identifies a spatial entity, stays the same across sessions once the entity is saved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XrUuid(pub [u8; 16]);
+
+impl XrUuid {
+    /// A random version 4 UUID, for backends that don't hand out their own.
+    pub(crate) fn new_v4() -> Self {
+        let mut bytes = [0; 16];
+        for half in bytes.chunks_mut(8) {
+            let mut hasher = RandomState::new().build_hasher();
+            if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+                hasher.write_u128(time.as_nanos());
+            }
+            half.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+}
+
+impl From<sys::UuidEXT> for XrUuid {
+    fn from(uuid: sys::UuidEXT) -> Self {
+        Self(uuid.data)
+    }
+}
+
+impl From<XrUuid> for sys::UuidEXT {
+    fn from(uuid: XrUuid) -> Self {
+        sys::UuidEXT { data: uuid.0 }
+    }
+}
+
+impl fmt::Display for XrUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if matches!(index, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for XrUuid {
+    type Err = ();
+
+    /// Parses the hyphenated form [`XrUuid`] is displayed in.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.replace('-', "");
+        if s.len() != 36 || hex.len() != 32 {
+            return Err(());
+        }
+        let mut bytes = [0; 16];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2).ok_or(())?, 16)
+                .map_err(|_| ())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+pub(crate) fn transform_to_pose(transform: &Transform) -> xr::Posef {
+    xr::Posef {
+        orientation: xr::Quaternionf {
+            x: transform.rotation.x,
+            y: transform.rotation.y,
+            z: transform.rotation.z,
+            w: transform.rotation.w,
+        },
+        position: xr::Vector3f {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            z: transform.translation.z,
+        },
+    }
+}
+
+pub(crate) fn pose_to_transform(pose: &xr::Posef) -> Transform {
+    Transform::from_translation(pose.position.to_vec3()).with_rotation(pose.orientation.to_quat())
+}
+
+/// Locates a raw space in `base`, `None` while the runtime can't track it.
+pub(crate) fn locate_space(
+    instance: &xr::Instance,
+    space: sys::Space,
+    base: &xr::Space,
+    time: xr::Time,
+) -> xr::Result<Option<Transform>> {
+    let mut location = sys::SpaceLocation {
+        ty: sys::SpaceLocation::TYPE,
+        next: ptr::null_mut(),
+        location_flags: xr::SpaceLocationFlags::EMPTY,
+        pose: xr::Posef::IDENTITY,
+    };
+    let result = unsafe { (instance.fp().locate_space)(space, base.as_raw(), time, &mut location) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let valid = xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID;
+    Ok(location
+        .location_flags
+        .contains(valid)
+        .then(|| pose_to_transform(&location.pose)))
+}
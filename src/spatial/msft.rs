@@ -0,0 +1,202 @@
+use std::ffi::{c_char, CStr};
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use openxr as xr;
+use xr::sys;
+
+use super::anchors::{AnchorCompletion, AnchorSpace};
+use super::fb::check;
+use super::XrUuid;
+
+/// Spatial anchors through `XR_MSFT_spatial_anchor`. The calls complete right away, anchors are
+/// persisted in the anchor store under their UUID as name.
+pub(crate) struct MsftAnchors {
+    instance: xr::Instance,
+    session: sys::Session,
+    store: sys::SpatialAnchorStoreConnectionMSFT,
+    completions: Vec<AnchorCompletion>,
+}
+
+impl MsftAnchors {
+    pub(crate) fn new(
+        instance: &xr::Instance,
+        session: &xr::Session<xr::AnyGraphics>,
+    ) -> xr::Result<Self> {
+        let mut store = sys::SpatialAnchorStoreConnectionMSFT::NULL;
+        if let Some(fp) = instance.exts().msft_spatial_anchor_persistence {
+            check(unsafe {
+                (fp.create_spatial_anchor_store_connection)(session.as_raw(), &mut store)
+            })?;
+        }
+        Ok(Self {
+            instance: instance.clone(),
+            session: session.as_raw(),
+            store,
+            completions: Vec::new(),
+        })
+    }
+
+    pub(crate) fn supported(instance: &xr::Instance) -> bool {
+        instance.exts().msft_spatial_anchor.is_some()
+    }
+
+    pub(crate) fn create(
+        &mut self,
+        entity: Entity,
+        stage: &xr::Space,
+        pose: xr::Posef,
+        time: xr::Time,
+    ) -> xr::Result<()> {
+        let fp = self.instance.exts().msft_spatial_anchor.unwrap();
+        let info = sys::SpatialAnchorCreateInfoMSFT {
+            ty: sys::SpatialAnchorCreateInfoMSFT::TYPE,
+            next: ptr::null(),
+            space: stage.as_raw(),
+            pose,
+            time,
+        };
+        let mut anchor = sys::SpatialAnchorMSFT::NULL;
+        check(unsafe { (fp.create_spatial_anchor)(self.session, &info, &mut anchor) })?;
+        let result = self.anchor_space(anchor, XrUuid::new_v4());
+        self.completions
+            .push(AnchorCompletion::Created { entity, result });
+        Ok(())
+    }
+
+    pub(crate) fn save(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        let fp = self.persistence()?;
+        let info = sys::SpatialAnchorPersistenceInfoMSFT {
+            ty: sys::SpatialAnchorPersistenceInfoMSFT::TYPE,
+            next: ptr::null(),
+            spatial_anchor_persistence_name: persistence_name(anchor.uuid),
+            spatial_anchor: anchor.msft_anchor,
+        };
+        let result = check(unsafe { (fp.persist_spatial_anchor)(self.store, &info) });
+        self.completions
+            .push(AnchorCompletion::Saved { entity, result });
+        Ok(())
+    }
+
+    pub(crate) fn erase(&mut self, entity: Entity, anchor: &AnchorSpace) -> xr::Result<()> {
+        let fp = self.persistence()?;
+        let name = persistence_name(anchor.uuid);
+        let result = check(unsafe { (fp.unpersist_spatial_anchor)(self.store, &name) });
+        self.completions.push(AnchorCompletion::Erased {
+            entity,
+            uuid: anchor.uuid,
+            result,
+        });
+        Ok(())
+    }
+
+    /// Loads every anchor of the store named after a UUID, other names belong to someone else.
+    pub(crate) fn load(&mut self) -> xr::Result<()> {
+        let fp = self.persistence()?;
+        let mut count = 0;
+        check(unsafe {
+            (fp.enumerate_persisted_spatial_anchor_names)(
+                self.store,
+                0,
+                &mut count,
+                ptr::null_mut(),
+            )
+        })?;
+        let mut names =
+            vec![unsafe { mem::zeroed::<sys::SpatialAnchorPersistenceNameMSFT>() }; count as usize];
+        check(unsafe {
+            (fp.enumerate_persisted_spatial_anchor_names)(
+                self.store,
+                names.len() as u32,
+                &mut count,
+                names.as_mut_ptr(),
+            )
+        })?;
+        names.truncate(count as usize);
+        for name in names {
+            let name_str = unsafe { CStr::from_ptr(name.name.as_ptr()) };
+            let Some(uuid) = name_str.to_str().ok().and_then(|name| name.parse().ok()) else {
+                continue;
+            };
+            let info = sys::SpatialAnchorFromPersistedAnchorCreateInfoMSFT {
+                ty: sys::SpatialAnchorFromPersistedAnchorCreateInfoMSFT::TYPE,
+                next: ptr::null(),
+                spatial_anchor_store: self.store,
+                spatial_anchor_persistence_name: name,
+            };
+            let mut anchor = sys::SpatialAnchorMSFT::NULL;
+            let result = check(unsafe {
+                (fp.create_spatial_anchor_from_persisted_name)(self.session, &info, &mut anchor)
+            })
+            .and_then(|_| self.anchor_space(anchor, uuid));
+            self.completions.push(AnchorCompletion::Loaded { result });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn destroy(&mut self, anchor: AnchorSpace) {
+        let fp = self.instance.exts().msft_spatial_anchor.unwrap();
+        unsafe {
+            (self.instance.fp().destroy_space)(anchor.space);
+            (fp.destroy_spatial_anchor)(anchor.msft_anchor);
+        }
+    }
+
+    pub(crate) fn poll(&mut self) -> Vec<AnchorCompletion> {
+        mem::take(&mut self.completions)
+    }
+
+    /// Forgets the store connection of a lost session, it was destroyed along with it.
+    pub(crate) fn session_lost(&mut self) {
+        self.store = sys::SpatialAnchorStoreConnectionMSFT::NULL;
+    }
+
+    fn persistence(&self) -> xr::Result<xr::raw::SpatialAnchorPersistenceMSFT> {
+        match self.instance.exts().msft_spatial_anchor_persistence {
+            Some(fp) if self.store != sys::SpatialAnchorStoreConnectionMSFT::NULL => Ok(fp),
+            _ => Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT),
+        }
+    }
+
+    /// Wraps the anchor in a space to locate it, the anchor is destroyed when that fails.
+    fn anchor_space(
+        &self,
+        anchor: sys::SpatialAnchorMSFT,
+        uuid: XrUuid,
+    ) -> xr::Result<AnchorSpace> {
+        let fp = self.instance.exts().msft_spatial_anchor.unwrap();
+        let info = sys::SpatialAnchorSpaceCreateInfoMSFT {
+            ty: sys::SpatialAnchorSpaceCreateInfoMSFT::TYPE,
+            next: ptr::null(),
+            anchor,
+            pose_in_anchor_space: xr::Posef::IDENTITY,
+        };
+        let mut space = sys::Space::NULL;
+        let result = unsafe { (fp.create_spatial_anchor_space)(self.session, &info, &mut space) };
+        if let Err(err) = check(result) {
+            unsafe { (fp.destroy_spatial_anchor)(anchor) };
+            return Err(err);
+        }
+        Ok(AnchorSpace {
+            space,
+            uuid,
+            msft_anchor: anchor,
+        })
+    }
+}
+
+impl Drop for MsftAnchors {
+    fn drop(&mut self) {
+        if let Ok(fp) = self.persistence() {
+            unsafe { (fp.destroy_spatial_anchor_store_connection)(self.store) };
+        }
+    }
+}
+
+fn persistence_name(uuid: XrUuid) -> sys::SpatialAnchorPersistenceNameMSFT {
+    let mut name: sys::SpatialAnchorPersistenceNameMSFT = unsafe { mem::zeroed() };
+    for (dst, src) in name.name.iter_mut().zip(uuid.to_string().bytes()) {
+        *dst = src as c_char;
+    }
+    name
+}
@@ -0,0 +1,620 @@
+use std::ffi::c_char;
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::primitives::Aabb;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::utils::{HashMap, HashSet};
+use openxr as xr;
+use xr::sys;
+
+use super::fb::{check, enable_component, query_spaces, retrieve_query_results, XrSpaceEventFB};
+use super::{locate_space, XrUuid};
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown};
+use crate::xr_input::trackers::OpenXRTrackingRoot;
+
+/// What a part of the room is, as set up by the user.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum XrSemanticLabel {
+    Floor,
+    Ceiling,
+    WallFace,
+    InvisibleWallFace,
+    DoorFrame,
+    WindowFrame,
+    Table,
+    Couch,
+    Bed,
+    Storage,
+    Screen,
+    Lamp,
+    Plant,
+    WallArt,
+    /// A label this version doesn't know, as reported by the runtime.
+    Other(String),
+}
+
+impl XrSemanticLabel {
+    fn parse(label: &str) -> Self {
+        match label {
+            "FLOOR" => Self::Floor,
+            "CEILING" => Self::Ceiling,
+            "WALL_FACE" => Self::WallFace,
+            "INVISIBLE_WALL_FACE" => Self::InvisibleWallFace,
+            "DOOR_FRAME" => Self::DoorFrame,
+            "WINDOW_FRAME" => Self::WindowFrame,
+            "TABLE" => Self::Table,
+            "COUCH" => Self::Couch,
+            "BED" => Self::Bed,
+            "STORAGE" => Self::Storage,
+            "SCREEN" => Self::Screen,
+            "LAMP" => Self::Lamp,
+            "PLANT" => Self::Plant,
+            "WALL_ART" => Self::WallArt,
+            other => Self::Other(other.to_owned()),
+        }
+    }
+}
+
+/// The parts of a room, only set on the room entity.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XrRoomLayout {
+    pub floor: Option<XrUuid>,
+    pub ceiling: Option<XrUuid>,
+    pub walls: Vec<XrUuid>,
+}
+
+/// A part of the captured room. The geometry is in the entity's local space: planes lie in the XY
+/// plane facing +Z, which points into the room for walls, floor and ceiling.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct XrSceneAnchor {
+    pub uuid: XrUuid,
+    pub labels: Vec<XrSemanticLabel>,
+    /// The extent of a plane.
+    pub bounds_2d: Option<Rect>,
+    /// The outline of a plane, empty for anything that isn't one.
+    pub boundary_2d: Vec<Vec2>,
+    /// The extent of a volume, like a table or couch.
+    pub bounds_3d: Option<Aabb>,
+    pub room_layout: Option<XrRoomLayout>,
+    /// The anchors a container like the room is made of, needs `XR_FB_spatial_entity_container`.
+    pub contained: Vec<XrUuid>,
+}
+
+impl XrSceneAnchor {
+    pub fn has_label(&self, label: &XrSemanticLabel) -> bool {
+        self.labels.contains(label)
+    }
+}
+
+#[derive(Event, Clone, Debug)]
+pub enum XrSceneEvent {
+    /// The scene differs from the last query, e.g. because the user captured the room again.
+    Changed {
+        added: Vec<Entity>,
+        updated: Vec<Entity>,
+        removed: Vec<XrUuid>,
+    },
+    Failed {
+        error: sys::Result,
+    },
+}
+
+/// The room the user captured in the system's space setup. It is queried when the session starts
+/// and again on [`XrScene::refresh`], every part of it is spawned as entity with an
+/// [`XrSceneAnchor`] that follows it around.
+#[derive(Resource, Default)]
+pub struct XrScene {
+    /// Adds a [`Mesh`] of the volume or plane to every scene entity, e.g. for colliders or
+    /// occlusion. Only applies to entities spawned or updated afterwards.
+    pub meshes: bool,
+    refresh: bool,
+    query: Option<sys::AsyncRequestIdFB>,
+    results: Vec<sys::SpaceQueryResultFB>,
+    spaces: HashMap<XrUuid, SceneSpace>,
+    /// Outstanding requests to make a scene anchor locatable.
+    locatable_requests: HashMap<sys::AsyncRequestIdFB, XrUuid>,
+}
+
+impl XrScene {
+    /// Queries the scene again, changes are announced with [`XrSceneEvent::Changed`].
+    pub fn refresh(&mut self) {
+        self.refresh = true;
+    }
+
+    pub fn entity(&self, uuid: XrUuid) -> Option<Entity> {
+        self.spaces.get(&uuid).map(|space| space.entity)
+    }
+}
+
+struct SceneSpace {
+    space: sys::Space,
+    entity: Entity,
+    /// The `LOCATABLE` component is enabled, until then the entity isn't moved.
+    locatable: bool,
+}
+
+/// Spawns the captured room through `XR_FB_scene`. Nothing happens when it, `XR_FB_spatial_entity`
+/// or `XR_FB_spatial_entity_query` is not enabled.
+pub struct XrScenePlugin;
+
+impl Plugin for XrScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<XrSceneEvent>();
+        app.init_resource::<XrScene>();
+        app.add_systems(XrSessionSetup, setup_scene);
+        app.add_systems(XrSessionTeardown, teardown_scene);
+        app.add_systems(
+            PreUpdate,
+            (update_scene, track_scene)
+                .chain()
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+    }
+}
+
+fn supports_scene(instance: &xr::Instance) -> bool {
+    let exts = instance.exts();
+    exts.fb_scene.is_some()
+        && exts.fb_spatial_entity.is_some()
+        && exts.fb_spatial_entity_query.is_some()
+}
+
+fn setup_scene(mut scene: ResMut<XrScene>) {
+    scene.refresh = true;
+}
+
+/// The spaces and the pending query of a lost session go away with it, the entities stay and get
+/// the new spaces once the scene is queried again.
+fn teardown_scene(mut scene: ResMut<XrScene>) {
+    for space in scene.spaces.values_mut() {
+        space.space = sys::Space::NULL;
+        space.locatable = false;
+    }
+    scene.query = None;
+    scene.results.clear();
+    scene.locatable_requests.clear();
+}
+
+fn update_scene(
+    mut commands: Commands,
+    mut scene: ResMut<XrScene>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    mut space_events: EventReader<XrSpaceEventFB>,
+    mut events: EventWriter<XrSceneEvent>,
+    query: Query<&XrSceneAnchor>,
+) {
+    if !supports_scene(&instance) {
+        space_events.clear();
+        return;
+    }
+    let session = session.as_raw();
+    if scene.refresh && scene.query.is_none() {
+        scene.refresh = false;
+        match query_spaces(
+            &instance,
+            session,
+            sys::SpaceComponentTypeFB::SEMANTIC_LABELS,
+        ) {
+            Ok(request) => scene.query = Some(request),
+            Err(err) => {
+                warn!("Failed to query the scene: {err}");
+                events.send(XrSceneEvent::Failed { error: err });
+            }
+        }
+    }
+    for event in space_events.read() {
+        match *event {
+            XrSpaceEventFB::StatusSet {
+                request,
+                result,
+                space,
+                uuid,
+            } => {
+                if scene.locatable_requests.remove(&request).is_none() {
+                    continue;
+                }
+                let result = match result {
+                    sys::Result::ERROR_SPACE_COMPONENT_STATUS_ALREADY_SET_FB => Ok(()),
+                    result => check(result),
+                };
+                match result {
+                    Ok(()) => {
+                        // The scene might have been queried again in the meantime
+                        if let Some(scene_space) = scene.spaces.get_mut(&uuid) {
+                            scene_space.locatable |= scene_space.space == space;
+                        }
+                    }
+                    Err(err) => warn!("Failed to make scene anchor {uuid} locatable: {err}"),
+                }
+            }
+            XrSpaceEventFB::QueryResultsAvailable { request } if scene.query == Some(request) => {
+                match retrieve_query_results(&instance, session, request) {
+                    Ok(results) => scene.results.extend(results),
+                    Err(err) => warn!("Failed to retrieve the scene: {err}"),
+                }
+            }
+            XrSpaceEventFB::QueryComplete { request, result } if scene.query == Some(request) => {
+                scene.query = None;
+                let results = mem::take(&mut scene.results);
+                if let Err(err) = check(result) {
+                    warn!("Failed to query the scene: {err}");
+                    events.send(XrSceneEvent::Failed { error: err });
+                    continue;
+                }
+                let event = apply_scene(
+                    &mut commands,
+                    &mut scene,
+                    &mut meshes,
+                    &instance,
+                    session,
+                    &query,
+                    results,
+                );
+                if let Some(event) = event {
+                    events.send(event);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Spawns, updates and despawns the scene entities, returns what changed.
+fn apply_scene(
+    commands: &mut Commands,
+    scene: &mut XrScene,
+    meshes: &mut Assets<Mesh>,
+    instance: &xr::Instance,
+    session: sys::Session,
+    query: &Query<&XrSceneAnchor>,
+    results: Vec<sys::SpaceQueryResultFB>,
+) -> Option<XrSceneEvent> {
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    let mut seen = HashSet::new();
+    for result in results {
+        let uuid = XrUuid::from(result.uuid);
+        if !seen.insert(uuid) {
+            continue;
+        }
+        // Located once the status set event reports the component enabled
+        let locatable =
+            match enable_component(instance, result.space, sys::SpaceComponentTypeFB::LOCATABLE) {
+                Ok(Some(request)) => {
+                    scene.locatable_requests.insert(request, uuid);
+                    false
+                }
+                Ok(None) => true,
+                Err(err) => {
+                    warn!("Failed to make scene anchor {uuid} locatable: {err}");
+                    false
+                }
+            };
+        let anchor = read_scene_anchor(instance, session, result.space, uuid);
+        let existing = scene
+            .spaces
+            .get(&uuid)
+            .and_then(|space| Some((space.entity, query.get(space.entity).ok()?)));
+        let entity = match existing {
+            Some((_, current)) if *current == anchor => None,
+            Some((entity, _)) => {
+                updated.push(entity);
+                Some(entity)
+            }
+            None => {
+                let entity = commands.spawn(SpatialBundle::default()).id();
+                added.push(entity);
+                Some(entity)
+            }
+        };
+        if let Some(entity) = entity {
+            let mesh = scene
+                .meshes
+                .then(|| scene_mesh(&anchor))
+                .flatten()
+                .map(|mesh| meshes.add(mesh));
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.insert(anchor);
+            if let Some(mesh) = mesh {
+                entity_commands.insert(mesh);
+            }
+        }
+        let entity = entity.unwrap_or_else(|| scene.spaces[&uuid].entity);
+        let previous = scene.spaces.insert(
+            uuid,
+            SceneSpace {
+                space: result.space,
+                entity,
+                locatable,
+            },
+        );
+        if let Some(previous) = previous {
+            if previous.space != sys::Space::NULL && previous.space != result.space {
+                unsafe { (instance.fp().destroy_space)(previous.space) };
+            }
+        }
+    }
+    let mut removed = Vec::new();
+    scene.spaces.retain(|uuid, space| {
+        if seen.contains(uuid) {
+            return true;
+        }
+        if space.space != sys::Space::NULL {
+            unsafe { (instance.fp().destroy_space)(space.space) };
+        }
+        if let Some(entity) = commands.get_entity(space.entity) {
+            entity.despawn_recursive();
+        }
+        removed.push(*uuid);
+        false
+    });
+    if added.is_empty() && updated.is_empty() && removed.is_empty() {
+        return None;
+    }
+    info!(
+        "Scene changed: {} added, {} updated, {} removed",
+        added.len(),
+        updated.len(),
+        removed.len()
+    );
+    Some(XrSceneEvent::Changed {
+        added,
+        updated,
+        removed,
+    })
+}
+
+/// Moves the scene entities to where the runtime tracks them.
+fn track_scene(
+    scene: Res<XrScene>,
+    instance: Res<XrInstance>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    tracking_root: Query<&GlobalTransform, With<OpenXRTrackingRoot>>,
+    mut query: Query<&mut Transform, With<XrSceneAnchor>>,
+) {
+    let root = tracking_root
+        .get_single()
+        .map(|root| root.compute_transform())
+        .unwrap_or_default();
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for space in scene.spaces.values() {
+        if space.space == sys::Space::NULL || !space.locatable {
+            continue;
+        }
+        let Ok(mut transform) = query.get_mut(space.entity) else {
+            continue;
+        };
+        if let Ok(Some(pose)) = locate_space(&instance, space.space, &input.stage, time) {
+            *transform = root * pose;
+        }
+    }
+}
+
+/// Reads the components of a scene anchor, the ones it doesn't have are left empty.
+fn read_scene_anchor(
+    instance: &xr::Instance,
+    session: sys::Session,
+    space: sys::Space,
+    uuid: XrUuid,
+) -> XrSceneAnchor {
+    let fp = instance.exts().fb_scene.unwrap();
+    let contained = match instance.exts().fb_spatial_entity_container {
+        Some(container_fp) => space_container(&container_fp, session, space).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    XrSceneAnchor {
+        uuid,
+        labels: semantic_labels(&fp, session, space).unwrap_or_default(),
+        bounds_2d: bounding_box_2d(&fp, session, space).ok(),
+        boundary_2d: boundary_2d(&fp, session, space).unwrap_or_default(),
+        bounds_3d: bounding_box_3d(&fp, session, space).ok(),
+        room_layout: room_layout(&fp, session, space).ok(),
+        contained,
+    }
+}
+
+fn semantic_labels(
+    fp: &xr::raw::SceneFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<Vec<XrSemanticLabel>> {
+    let mut labels = sys::SemanticLabelsFB {
+        ty: sys::SemanticLabelsFB::TYPE,
+        next: ptr::null(),
+        buffer_capacity_input: 0,
+        buffer_count_output: 0,
+        buffer: ptr::null_mut(),
+    };
+    check(unsafe { (fp.get_space_semantic_labels)(session, space, &mut labels) })?;
+    let mut buffer = vec![0 as c_char; labels.buffer_count_output as usize];
+    labels.buffer_capacity_input = buffer.len() as u32;
+    labels.buffer = buffer.as_mut_ptr();
+    check(unsafe { (fp.get_space_semantic_labels)(session, space, &mut labels) })?;
+    let bytes = buffer
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as u8)
+        .collect::<Vec<_>>();
+    Ok(String::from_utf8_lossy(&bytes)
+        .split(',')
+        .filter(|label| !label.is_empty())
+        .map(XrSemanticLabel::parse)
+        .collect())
+}
+
+fn bounding_box_2d(
+    fp: &xr::raw::SceneFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<Rect> {
+    let mut rect: sys::Rect2Df = unsafe { mem::zeroed() };
+    check(unsafe { (fp.get_space_bounding_box2_d)(session, space, &mut rect) })?;
+    let min = Vec2::new(rect.offset.x, rect.offset.y);
+    let size = Vec2::new(rect.extent.width, rect.extent.height);
+    Ok(Rect::from_corners(min, min + size))
+}
+
+fn bounding_box_3d(
+    fp: &xr::raw::SceneFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<Aabb> {
+    let mut rect: sys::Rect3DfFB = unsafe { mem::zeroed() };
+    check(unsafe { (fp.get_space_bounding_box3_d)(session, space, &mut rect) })?;
+    let min = Vec3::new(rect.offset.x, rect.offset.y, rect.offset.z);
+    let size = Vec3::new(rect.extent.width, rect.extent.height, rect.extent.depth);
+    Ok(Aabb::from_min_max(min, min + size))
+}
+
+fn boundary_2d(
+    fp: &xr::raw::SceneFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<Vec<Vec2>> {
+    let mut boundary = sys::Boundary2DFB {
+        ty: sys::Boundary2DFB::TYPE,
+        next: ptr::null(),
+        vertex_capacity_input: 0,
+        vertex_count_output: 0,
+        vertices: ptr::null_mut(),
+    };
+    check(unsafe { (fp.get_space_boundary2_d)(session, space, &mut boundary) })?;
+    let mut vertices = vec![xr::Vector2f { x: 0.0, y: 0.0 }; boundary.vertex_count_output as usize];
+    boundary.vertex_capacity_input = vertices.len() as u32;
+    boundary.vertices = vertices.as_mut_ptr();
+    check(unsafe { (fp.get_space_boundary2_d)(session, space, &mut boundary) })?;
+    vertices.truncate(boundary.vertex_count_output as usize);
+    Ok(vertices
+        .into_iter()
+        .map(|vertex| Vec2::new(vertex.x, vertex.y))
+        .collect())
+}
+
+fn room_layout(
+    fp: &xr::raw::SceneFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<XrRoomLayout> {
+    let mut layout = sys::RoomLayoutFB {
+        ty: sys::RoomLayoutFB::TYPE,
+        next: ptr::null(),
+        floor_uuid: sys::UuidEXT { data: [0; 16] },
+        ceiling_uuid: sys::UuidEXT { data: [0; 16] },
+        wall_uuid_capacity_input: 0,
+        wall_uuid_count_output: 0,
+        wall_uuids: ptr::null_mut(),
+    };
+    check(unsafe { (fp.get_space_room_layout)(session, space, &mut layout) })?;
+    let mut walls = vec![sys::UuidEXT { data: [0; 16] }; layout.wall_uuid_count_output as usize];
+    layout.wall_uuid_capacity_input = walls.len() as u32;
+    layout.wall_uuids = walls.as_mut_ptr();
+    check(unsafe { (fp.get_space_room_layout)(session, space, &mut layout) })?;
+    walls.truncate(layout.wall_uuid_count_output as usize);
+    // A zero UUID marks a part the room doesn't have
+    let uuid = |uuid: sys::UuidEXT| (uuid.data != [0; 16]).then(|| XrUuid::from(uuid));
+    Ok(XrRoomLayout {
+        floor: uuid(layout.floor_uuid),
+        ceiling: uuid(layout.ceiling_uuid),
+        walls: walls.into_iter().filter_map(uuid).collect(),
+    })
+}
+
+fn space_container(
+    fp: &xr::raw::SpatialEntityContainerFB,
+    session: sys::Session,
+    space: sys::Space,
+) -> xr::Result<Vec<XrUuid>> {
+    let mut container = sys::SpaceContainerFB {
+        ty: sys::SpaceContainerFB::TYPE,
+        next: ptr::null(),
+        uuid_capacity_input: 0,
+        uuid_count_output: 0,
+        uuids: ptr::null_mut(),
+    };
+    check(unsafe { (fp.get_space_container)(session, space, &mut container) })?;
+    let mut uuids = vec![sys::UuidEXT { data: [0; 16] }; container.uuid_count_output as usize];
+    container.uuid_capacity_input = uuids.len() as u32;
+    container.uuids = uuids.as_mut_ptr();
+    check(unsafe { (fp.get_space_container)(session, space, &mut container) })?;
+    uuids.truncate(container.uuid_count_output as usize);
+    Ok(uuids.into_iter().map(XrUuid::from).collect())
+}
+
+/// The volume of the anchor if it has one, otherwise its plane.
+fn scene_mesh(anchor: &XrSceneAnchor) -> Option<Mesh> {
+    if let Some(bounds) = anchor.bounds_3d {
+        let size = Vec3::from(bounds.half_extents * 2.0);
+        return Some(Mesh::from(Cuboid::from_size(size)).translated_by(bounds.center.into()));
+    }
+    if anchor.boundary_2d.len() >= 3 {
+        return Some(polygon_mesh(&anchor.boundary_2d));
+    }
+    anchor.bounds_2d.map(|rect| {
+        Mesh::from(Rectangle::from_size(rect.size())).translated_by(rect.center().extend(0.0))
+    })
+}
+
+/// Triangulates a plane's outline by ear clipping, rooms are often not convex.
+fn polygon_mesh(vertices: &[Vec2]) -> Mesh {
+    let signed_area = vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum::<f32>();
+    let mut remaining = (0..vertices.len() as u32).collect::<Vec<_>>();
+    // Counter-clockwise, so the triangles face +Z
+    if signed_area < 0.0 {
+        remaining.reverse();
+    }
+    let mut indices = Vec::with_capacity((vertices.len() - 2) * 3);
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corner = |index: usize| {
+            [
+                remaining[(index + len - 1) % len],
+                remaining[index],
+                remaining[(index + 1) % len],
+            ]
+        };
+        let is_ear = |index: usize| {
+            let triangle = corner(index);
+            let [a, b, c] = triangle.map(|vertex| vertices[vertex as usize]);
+            (b - a).perp_dot(c - b) > 0.0
+                && !remaining.iter().any(|vertex| {
+                    !triangle.contains(vertex) && in_triangle(vertices[*vertex as usize], a, b, c)
+                })
+        };
+        // A degenerate outline might have no ear left, clip anyway to finish
+        let ear = (0..len).find(|&index| is_ear(index)).unwrap_or(0);
+        indices.extend(corner(ear));
+        remaining.remove(ear);
+    }
+    indices.extend(remaining);
+    let positions = vertices
+        .iter()
+        .map(|vertex| [vertex.x, vertex.y, 0.0])
+        .collect::<Vec<_>>();
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn in_triangle(point: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    (b - a).perp_dot(point - a) >= 0.0
+        && (c - b).perp_dot(point - b) >= 0.0
+        && (a - c).perp_dot(point - c) >= 0.0
+}
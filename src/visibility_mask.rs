@@ -0,0 +1,288 @@
+use std::ptr;
+
+use bevy::pbr::{NotShadowCaster, NotShadowReceiver};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::view::{NoFrustumCulling, RenderLayers};
+use openxr as xr;
+use xr::sys;
+
+use crate::resources::{XrInstance, XrSession, XrViewConfigurationType};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup};
+use crate::xr_input::xr_camera::{Eye, XRProjection, XrCameraType};
+
+/// The first of the render layers the hidden area meshes are drawn on, one layer per view.
+pub const VISIBILITY_MASK_FIRST_LAYER: u8 = RenderLayers::TOTAL_LAYERS as u8 - 4;
+
+/// Sent when the runtime changed the visibility mask of a view, e.g. after the lens distance was
+/// adjusted. [`XrVisibilityMasks`] is updated in place.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XrVisibilityMaskChanged {
+    pub view_index: u32,
+}
+
+/// The `XR_KHR_visibility_mask` meshes of every view, indexed like the views. The vertices are in
+/// view space on the `z = -1` plane, a mesh placed as child of an eye camera and scaled by the
+/// distance lines up with the lens.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct XrVisibilityMasks {
+    /// The area the lenses can never show.
+    pub hidden: Vec<Handle<Mesh>>,
+    /// The area the lenses do show.
+    pub visible: Vec<Handle<Mesh>>,
+}
+
+/// The hidden area mesh drawn in front of an eye camera.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct XrVisibilityMask {
+    pub eye: Eye,
+}
+
+/// Fetches the visibility masks and draws each eye's hidden area as black occluder right behind
+/// the near plane, so the depth prepass and early depth testing skip shading pixels the lens
+/// can't show. Nothing happens when `XR_KHR_visibility_mask` is not enabled, enable it with
+/// [`XrExtensions::enable_visibility_mask`] on runtimes that support it.
+///
+/// [`XrExtensions::enable_visibility_mask`]: crate::graphics::extensions::XrExtensions::enable_visibility_mask
+pub struct XrVisibilityMaskPlugin;
+
+impl Plugin for XrVisibilityMaskPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrVisibilityMasks>();
+        app.add_systems(XrSessionSetup, setup_visibility_masks);
+        app.add_systems(
+            PreUpdate,
+            update_visibility_masks
+                .run_if(xr_only())
+                .run_if(on_event::<XrVisibilityMaskChanged>())
+                .after(xr_begin_frame),
+        );
+        app.add_systems(
+            PostUpdate,
+            update_visibility_mask_distance.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn setup_visibility_masks(
+    mut commands: Commands,
+    mut masks: ResMut<XrVisibilityMasks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    view_type: Res<XrViewConfigurationType>,
+    existing: Query<Entity, With<XrVisibilityMask>>,
+    mut cameras: Query<(
+        Entity,
+        &XrCameraType,
+        Option<&mut RenderLayers>,
+        Has<Visibility>,
+    )>,
+) {
+    if instance.exts().khr_visibility_mask.is_none() {
+        return;
+    }
+    // XrSessionSetup runs again after the session was recreated
+    for entity in &existing {
+        commands.entity(entity).despawn_recursive();
+    }
+    masks.hidden.clear();
+    masks.visible.clear();
+    for view_index in 0..view_type.view_count() as u32 {
+        let hidden = mask_mesh(
+            &instance,
+            &session,
+            **view_type,
+            view_index,
+            sys::VisibilityMaskTypeKHR::HIDDEN_TRIANGLE_MESH,
+        );
+        let visible = mask_mesh(
+            &instance,
+            &session,
+            **view_type,
+            view_index,
+            sys::VisibilityMaskTypeKHR::VISIBLE_TRIANGLE_MESH,
+        );
+        masks.hidden.push(meshes.add(hidden));
+        masks.visible.push(meshes.add(visible));
+    }
+
+    let material = materials.add(StandardMaterial {
+        base_color: Color::BLACK,
+        unlit: true,
+        cull_mode: None,
+        ..default()
+    });
+    for (entity, camera_type, layers, has_visibility) in &mut cameras {
+        let XrCameraType::Xr(eye) = camera_type else {
+            continue;
+        };
+        let Some(mesh) = masks.hidden.get(*eye as usize) else {
+            continue;
+        };
+        let layer = VISIBILITY_MASK_FIRST_LAYER + *eye as u8;
+        match layers {
+            Some(mut layers) => *layers = layers.with(layer),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(RenderLayers::layer(0).with(layer));
+            }
+        }
+        // The mask only inherits visibility through a parent that has it
+        if !has_visibility {
+            commands.entity(entity).insert(VisibilityBundle::default());
+        }
+        let mask = commands
+            .spawn((
+                PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    ..default()
+                },
+                RenderLayers::layer(layer),
+                NotShadowCaster,
+                NotShadowReceiver,
+                NoFrustumCulling,
+                XrVisibilityMask { eye: *eye },
+            ))
+            .id();
+        commands.entity(entity).add_child(mask);
+    }
+}
+
+fn update_visibility_masks(
+    mut events: EventReader<XrVisibilityMaskChanged>,
+    masks: Res<XrVisibilityMasks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    view_type: Res<XrViewConfigurationType>,
+) {
+    for event in events.read() {
+        let index = event.view_index as usize;
+        if let Some(handle) = masks.hidden.get(index) {
+            let mesh = mask_mesh(
+                &instance,
+                &session,
+                **view_type,
+                event.view_index,
+                sys::VisibilityMaskTypeKHR::HIDDEN_TRIANGLE_MESH,
+            );
+            meshes.insert(handle.clone(), mesh);
+        }
+        if let Some(handle) = masks.visible.get(index) {
+            let mesh = mask_mesh(
+                &instance,
+                &session,
+                **view_type,
+                event.view_index,
+                sys::VisibilityMaskTypeKHR::VISIBLE_TRIANGLE_MESH,
+            );
+            meshes.insert(handle.clone(), mesh);
+        }
+    }
+}
+
+/// Keeps the masks just behind the near plane of their eye camera.
+fn update_visibility_mask_distance(
+    cameras: Query<&XRProjection>,
+    mut masks: Query<(&Parent, &mut Transform), With<XrVisibilityMask>>,
+) {
+    for (parent, mut transform) in &mut masks {
+        let Ok(projection) = cameras.get(parent.get()) else {
+            continue;
+        };
+        let scale = Vec3::splat(projection.near * 1.001);
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
+
+fn mask_mesh(
+    instance: &XrInstance,
+    session: &XrSession,
+    view_type: xr::ViewConfigurationType,
+    view_index: u32,
+    mask_type: sys::VisibilityMaskTypeKHR,
+) -> Mesh {
+    let (vertices, indices) =
+        match get_visibility_mask(instance, session, view_type, view_index, mask_type) {
+            Ok(mask) => mask,
+            Err(e) => {
+                warn!("error: {}", e);
+                (Vec::new(), Vec::new())
+            }
+        };
+    let positions = vertices
+        .iter()
+        .map(|vertex| [vertex.x, vertex.y, -1.0])
+        .collect::<Vec<_>>();
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn get_visibility_mask(
+    instance: &XrInstance,
+    session: &XrSession,
+    view_type: xr::ViewConfigurationType,
+    view_index: u32,
+    mask_type: sys::VisibilityMaskTypeKHR,
+) -> xr::Result<(Vec<xr::Vector2f>, Vec<u32>)> {
+    let Some(ext) = instance.exts().khr_visibility_mask.as_ref() else {
+        return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
+    };
+    let mut mask = sys::VisibilityMaskKHR {
+        ty: sys::VisibilityMaskKHR::TYPE,
+        next: ptr::null_mut(),
+        vertex_capacity_input: 0,
+        vertex_count_output: 0,
+        vertices: ptr::null_mut(),
+        index_capacity_input: 0,
+        index_count_output: 0,
+        indices: ptr::null_mut(),
+    };
+    let result = unsafe {
+        (ext.get_visibility_mask)(
+            session.as_raw(),
+            view_type,
+            view_index,
+            mask_type,
+            &mut mask,
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let mut vertices = vec![xr::Vector2f::default(); mask.vertex_count_output as usize];
+    let mut indices = vec![0; mask.index_count_output as usize];
+    mask.vertex_capacity_input = vertices.len() as u32;
+    mask.vertices = vertices.as_mut_ptr();
+    mask.index_capacity_input = indices.len() as u32;
+    mask.indices = indices.as_mut_ptr();
+    let result = unsafe {
+        (ext.get_visibility_mask)(
+            session.as_raw(),
+            view_type,
+            view_index,
+            mask_type,
+            &mut mask,
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    vertices.truncate(mask.vertex_count_output as usize);
+    indices.truncate(mask.index_count_output as usize);
+    Ok((vertices, indices))
+}
@@ -78,6 +78,16 @@ pub struct XrSetup;
 pub struct XrPrePostSetup;
 #[derive(Debug, ScheduleLabel, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct XrPostSetup;
+/// Creates the objects that belong to a session, like action spaces and trackers. Runs before
+/// [`XrPostSetup`] on startup and alone when a lost session is recreated, so systems in it must
+/// cope with running more than once.
+#[derive(Debug, ScheduleLabel, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct XrSessionSetup;
+/// Releases the objects created in [`XrSessionSetup`] when the session is lost. Every space,
+/// tracker or other handle created from the session keeps it alive, so the runtime can only
+/// destroy it, and a new session can only be created, once all of them are gone.
+#[derive(Debug, ScheduleLabel, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct XrSessionTeardown;
 
 #[derive(Debug, ScheduleLabel, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct XrPreCleanup;
@@ -97,6 +107,13 @@ pub fn xr_only() -> impl FnMut(Option<Res<'_, XrEnableStatus>>) -> bool {
     resource_exists_and_equals(XrEnableStatus::Enabled)
 }
 
+/// Run condition that is false while the compositor doesn't need a new frame, e.g. while the
+/// headset is off-face or a system overlay covers the app. Always true when XR is not running, so
+/// visual-only systems can use it to save work on standalone devices without breaking flatscreen.
+pub fn xr_should_render() -> impl FnMut(Option<Res<'_, XrFrameState>>) -> bool {
+    |frame_state| frame_state.map_or(true, |state| state.lock().unwrap().should_render)
+}
+
 impl Plugin for RenderRestartPlugin {
     fn build(&self, app: &mut App) {
         add_schedules(app);
@@ -134,6 +151,8 @@ fn add_schedules(app: &mut App) {
         Schedule::new(XrPreSetup),
         Schedule::new(XrSetup),
         Schedule::new(XrPrePostSetup),
+        Schedule::new(XrSessionSetup),
+        Schedule::new(XrSessionTeardown),
         Schedule::new(XrPostSetup),
         Schedule::new(XrPreRenderUpdate),
         Schedule::new(XrRenderUpdate),
@@ -153,6 +172,7 @@ pub fn setup_xr(world: &mut World) {
     world.run_schedule(XrPreSetup);
     world.run_schedule(XrSetup);
     world.run_schedule(XrPrePostSetup);
+    world.run_schedule(XrSessionSetup);
     world.run_schedule(XrPostSetup);
 }
 fn cleanup_xr(world: &mut World) {
@@ -28,6 +28,7 @@ fn create_action<T: xr::ActionTy>(
     action: &SetupAction,
     action_name: &'static str,
     oxr_action_set: &xr::ActionSet,
+    instance: &xr::Instance,
     hands: &[xr::Path],
 ) -> xr::Action<T> {
     match action.handednes {
@@ -37,6 +38,15 @@ fn create_action<T: xr::ActionTy>(
         ActionHandednes::Double => oxr_action_set
             .create_action(action_name, &action.pretty_name, hands)
             .unwrap_or_else(|_| panic!("Unable to create action: {}", action_name)),
+        ActionHandednes::Custom(paths) => {
+            let paths = paths
+                .iter()
+                .map(|path| instance.string_to_path(path).unwrap())
+                .collect::<Vec<_>>();
+            oxr_action_set
+                .create_action(action_name, &action.pretty_name, &paths)
+                .unwrap_or_else(|_| panic!("Unable to create action: {}", action_name))
+        }
     }
 }
 pub fn setup_oxr_actions(world: &mut World) {
@@ -63,19 +73,19 @@ pub fn setup_oxr_actions(world: &mut World) {
             use self::create_action as ca;
             let typed_action = match action.action_type {
                 ActionType::Vec2 => {
-                    TypedAction::Vec2(ca(&action, action_name, &oxr_action_set, &hands))
+                    TypedAction::Vec2(ca(&action, action_name, &oxr_action_set, instance, &hands))
                 }
                 ActionType::F32 => {
-                    TypedAction::F32(ca(&action, action_name, &oxr_action_set, &hands))
+                    TypedAction::F32(ca(&action, action_name, &oxr_action_set, instance, &hands))
                 }
                 ActionType::Bool => {
-                    TypedAction::Bool(ca(&action, action_name, &oxr_action_set, &hands))
+                    TypedAction::Bool(ca(&action, action_name, &oxr_action_set, instance, &hands))
                 }
                 ActionType::PoseF => {
-                    TypedAction::PoseF(ca(&action, action_name, &oxr_action_set, &hands))
+                    TypedAction::PoseF(ca(&action, action_name, &oxr_action_set, instance, &hands))
                 }
                 ActionType::Haptic => {
-                    TypedAction::Haptic(ca(&action, action_name, &oxr_action_set, &hands))
+                    TypedAction::Haptic(ca(&action, action_name, &oxr_action_set, instance, &hands))
                 }
             };
             actions.insert(action_name, typed_action);
@@ -152,6 +162,8 @@ pub fn setup_oxr_actions(world: &mut World) {
 pub enum ActionHandednes {
     Single,
     Double,
+    /// Subaction paths for devices other than the hands, like `/user/vive_tracker_htcx/role/waist`.
+    Custom(&'static [&'static str]),
 }
 
 #[derive(Clone, Copy)]
@@ -0,0 +1,417 @@
+use std::ffi::c_void;
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use openxr as xr;
+use xr::sys;
+
+use crate::graphics::extensions::load_function;
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown};
+
+use super::hands::{BoneTrackingStatus, HandBone};
+use super::trackers::OpenXRTracker;
+use super::{Hand, QuatConv, Vec3Conv};
+
+pub(crate) const FB_BODY_TRACKING_EXTENSION: &str = "XR_FB_body_tracking";
+
+/// A joint of the tracked body, in the joint order of `XR_FB_body_tracking`.
+#[derive(Component, Debug, Clone, Copy)]
+pub enum BodyBone {
+    Root,
+    Hips,
+    SpineLower,
+    SpineMiddle,
+    SpineUpper,
+    Chest,
+    Neck,
+    Head,
+    LeftShoulder,
+    LeftScapula,
+    LeftArmUpper,
+    LeftArmLower,
+    LeftHandWristTwist,
+    RightShoulder,
+    RightScapula,
+    RightArmUpper,
+    RightArmLower,
+    RightHandWristTwist,
+    Hand(Hand, HandBone),
+}
+
+const UPPER_BODY_BONES: [BodyBone; 18] = [
+    BodyBone::Root,
+    BodyBone::Hips,
+    BodyBone::SpineLower,
+    BodyBone::SpineMiddle,
+    BodyBone::SpineUpper,
+    BodyBone::Chest,
+    BodyBone::Neck,
+    BodyBone::Head,
+    BodyBone::LeftShoulder,
+    BodyBone::LeftScapula,
+    BodyBone::LeftArmUpper,
+    BodyBone::LeftArmLower,
+    BodyBone::LeftHandWristTwist,
+    BodyBone::RightShoulder,
+    BodyBone::RightScapula,
+    BodyBone::RightArmUpper,
+    BodyBone::RightArmLower,
+    BodyBone::RightHandWristTwist,
+];
+
+const BODY_BONE_COUNT: usize = UPPER_BODY_BONES.len() + 2 * HandBone::get_all_bones().len();
+
+impl BodyBone {
+    pub fn get_all_bones() -> Vec<BodyBone> {
+        let hand_bones = |hand| {
+            HandBone::get_all_bones()
+                .into_iter()
+                .map(move |bone| BodyBone::Hand(hand, bone))
+        };
+        UPPER_BODY_BONES
+            .into_iter()
+            .chain(hand_bones(Hand::Left))
+            .chain(hand_bones(Hand::Right))
+            .collect()
+    }
+    pub fn get_index_from_bone(&self) -> usize {
+        let hand_bones = HandBone::get_all_bones().len();
+        match self {
+            BodyBone::Hand(Hand::Left, bone) => UPPER_BODY_BONES.len() + bone.get_index_from_bone(),
+            BodyBone::Hand(Hand::Right, bone) => {
+                UPPER_BODY_BONES.len() + hand_bones + bone.get_index_from_bone()
+            }
+            BodyBone::Root => 0,
+            BodyBone::Hips => 1,
+            BodyBone::SpineLower => 2,
+            BodyBone::SpineMiddle => 3,
+            BodyBone::SpineUpper => 4,
+            BodyBone::Chest => 5,
+            BodyBone::Neck => 6,
+            BodyBone::Head => 7,
+            BodyBone::LeftShoulder => 8,
+            BodyBone::LeftScapula => 9,
+            BodyBone::LeftArmUpper => 10,
+            BodyBone::LeftArmLower => 11,
+            BodyBone::LeftHandWristTwist => 12,
+            BodyBone::RightShoulder => 13,
+            BodyBone::RightScapula => 14,
+            BodyBone::RightArmUpper => 15,
+            BodyBone::RightArmLower => 16,
+            BodyBone::RightHandWristTwist => 17,
+        }
+    }
+    /// The bone this one hangs from, `None` for the root.
+    pub fn parent(&self) -> Option<BodyBone> {
+        Some(match self {
+            BodyBone::Root => return None,
+            BodyBone::Hips => BodyBone::Root,
+            BodyBone::SpineLower => BodyBone::Hips,
+            BodyBone::SpineMiddle => BodyBone::SpineLower,
+            BodyBone::SpineUpper => BodyBone::SpineMiddle,
+            BodyBone::Chest => BodyBone::SpineUpper,
+            BodyBone::Neck | BodyBone::LeftShoulder | BodyBone::RightShoulder => BodyBone::Chest,
+            BodyBone::Head => BodyBone::Neck,
+            BodyBone::LeftScapula => BodyBone::LeftShoulder,
+            BodyBone::LeftArmUpper => BodyBone::LeftScapula,
+            BodyBone::LeftArmLower => BodyBone::LeftArmUpper,
+            BodyBone::LeftHandWristTwist => BodyBone::LeftArmLower,
+            BodyBone::RightScapula => BodyBone::RightShoulder,
+            BodyBone::RightArmUpper => BodyBone::RightScapula,
+            BodyBone::RightArmLower => BodyBone::RightArmUpper,
+            BodyBone::RightHandWristTwist => BodyBone::RightArmLower,
+            BodyBone::Hand(Hand::Left, HandBone::Wrist) => BodyBone::LeftArmLower,
+            BodyBone::Hand(Hand::Right, HandBone::Wrist) => BodyBone::RightArmLower,
+            BodyBone::Hand(hand, bone) if bone.is_finger() && !is_metacarpal(bone) => {
+                BodyBone::Hand(
+                    *hand,
+                    HandBone::get_all_bones()[bone.get_index_from_bone() - 1],
+                )
+            }
+            BodyBone::Hand(hand, _) => BodyBone::Hand(*hand, HandBone::Wrist),
+        })
+    }
+}
+
+// `HandBone::is_metacarpal` counts the little finger tip instead of its metacarpal.
+fn is_metacarpal(bone: &HandBone) -> bool {
+    matches!(
+        bone,
+        HandBone::ThumbMetacarpal
+            | HandBone::IndexMetacarpal
+            | HandBone::MiddleMetacarpal
+            | HandBone::RingMetacarpal
+            | HandBone::LittleMetacarpal
+    )
+}
+
+/// The state of the body tracker, updated every frame.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct XrBodyState {
+    /// The body is tracked, the [`BodyBone`]s keep their last pose otherwise.
+    pub active: bool,
+    /// How confident the runtime is in the joint poses, between 0 and 1.
+    pub confidence: f32,
+}
+
+#[repr(C)]
+struct BodyTrackerCreateInfoFB {
+    ty: sys::StructureType,
+    next: *const c_void,
+    body_joint_set: i32,
+}
+
+#[repr(C)]
+struct BodyJointsLocateInfoFB {
+    ty: sys::StructureType,
+    next: *const c_void,
+    base_space: sys::Space,
+    time: xr::Time,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BodyJointLocationFB {
+    location_flags: xr::SpaceLocationFlags,
+    pose: xr::Posef,
+}
+
+#[repr(C)]
+struct BodyJointLocationsFB {
+    ty: sys::StructureType,
+    next: *mut c_void,
+    is_active: sys::Bool32,
+    confidence: f32,
+    joint_count: u32,
+    joint_locations: *mut BodyJointLocationFB,
+    skeleton_changed_count: u32,
+    time: xr::Time,
+}
+
+#[repr(C)]
+struct SystemBodyTrackingPropertiesFB {
+    ty: sys::StructureType,
+    next: *mut c_void,
+    supports_body_tracking: sys::Bool32,
+}
+
+const TYPE_BODY_TRACKER_CREATE_INFO_FB: sys::StructureType =
+    sys::StructureType::from_raw(1000076001);
+const TYPE_BODY_JOINTS_LOCATE_INFO_FB: sys::StructureType =
+    sys::StructureType::from_raw(1000076002);
+const TYPE_SYSTEM_BODY_TRACKING_PROPERTIES_FB: sys::StructureType =
+    sys::StructureType::from_raw(1000076004);
+const TYPE_BODY_JOINT_LOCATIONS_FB: sys::StructureType = sys::StructureType::from_raw(1000076005);
+const BODY_JOINT_SET_DEFAULT_FB: i32 = 0;
+
+type CreateBodyTrackerFB = unsafe extern "system" fn(
+    sys::Session,
+    *const BodyTrackerCreateInfoFB,
+    *mut u64,
+) -> sys::Result;
+type DestroyBodyTrackerFB = unsafe extern "system" fn(u64) -> sys::Result;
+type LocateBodyJointsFB = unsafe extern "system" fn(
+    u64,
+    *const BodyJointsLocateInfoFB,
+    *mut BodyJointLocationsFB,
+) -> sys::Result;
+
+/// `XR_FB_body_tracking` is missing from the generated bindings, so its functions are loaded by
+/// name.
+#[derive(Clone, Copy)]
+struct BodyTrackingFB {
+    create_body_tracker: CreateBodyTrackerFB,
+    destroy_body_tracker: DestroyBodyTrackerFB,
+    locate_body_joints: LocateBodyJointsFB,
+}
+
+impl BodyTrackingFB {
+    fn load(instance: &xr::Instance) -> xr::Result<Self> {
+        unsafe {
+            Ok(Self {
+                create_body_tracker: mem::transmute(load_function(
+                    instance,
+                    b"xrCreateBodyTrackerFB\0",
+                )?),
+                destroy_body_tracker: mem::transmute(load_function(
+                    instance,
+                    b"xrDestroyBodyTrackerFB\0",
+                )?),
+                locate_body_joints: mem::transmute(load_function(
+                    instance,
+                    b"xrLocateBodyJointsFB\0",
+                )?),
+            })
+        }
+    }
+}
+
+#[derive(Resource)]
+struct BodyTracker {
+    fp: BodyTrackingFB,
+    tracker: u64,
+}
+
+impl Drop for BodyTracker {
+    fn drop(&mut self) {
+        if self.tracker != 0 {
+            unsafe { (self.fp.destroy_body_tracker)(self.tracker) };
+        }
+    }
+}
+
+/// Tracks the body through `XR_FB_body_tracking` and spawns a tracker entity for every
+/// [`BodyBone`]. Nothing happens when the extension is not enabled or the system can't track the
+/// body.
+pub struct XrBodyTrackingPlugin;
+
+impl Plugin for XrBodyTrackingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrBodyState>();
+        app.add_systems(XrSessionSetup, setup_body_tracking);
+        app.add_systems(XrSessionTeardown, teardown_body_tracking);
+        app.add_systems(
+            PreUpdate,
+            update_body_bones.run_if(xr_only()).after(xr_begin_frame),
+        );
+    }
+}
+
+/// The tracker of a lost session was destroyed along with it.
+fn teardown_body_tracking(mut commands: Commands, tracker: Option<ResMut<BodyTracker>>) {
+    if let Some(mut tracker) = tracker {
+        tracker.tracker = 0;
+    }
+    commands.remove_resource::<BodyTracker>();
+}
+
+fn setup_body_tracking(
+    mut commands: Commands,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    query: Query<(), With<BodyBone>>,
+) {
+    let supported = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .and_then(|system| supports_body_tracking(&instance, system));
+    match supported {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("The system can't track the body");
+            return;
+        }
+        Err(sys::Result::ERROR_FUNCTION_UNSUPPORTED) => return,
+        Err(err) => {
+            warn!("Failed to query body tracking support: {err}");
+            return;
+        }
+    }
+    let fp = match BodyTrackingFB::load(&instance) {
+        Ok(fp) => fp,
+        Err(err) => {
+            warn!("Failed to load the body tracking functions: {err}");
+            return;
+        }
+    };
+    let info = BodyTrackerCreateInfoFB {
+        ty: TYPE_BODY_TRACKER_CREATE_INFO_FB,
+        next: ptr::null(),
+        body_joint_set: BODY_JOINT_SET_DEFAULT_FB,
+    };
+    let mut tracker = 0;
+    let result = unsafe { (fp.create_body_tracker)(session.as_raw(), &info, &mut tracker) };
+    if result.into_raw() < 0 {
+        warn!("Failed to create the body tracker: {result}");
+        return;
+    }
+    commands.insert_resource(BodyTracker { fp, tracker });
+    if query.is_empty() {
+        for bone in BodyBone::get_all_bones() {
+            commands.spawn((
+                SpatialBundle::default(),
+                bone,
+                OpenXRTracker,
+                BoneTrackingStatus::Emulated,
+            ));
+        }
+    }
+}
+
+fn update_body_bones(
+    tracker: Option<Res<BodyTracker>>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut state: ResMut<XrBodyState>,
+    mut query: Query<(&BodyBone, &mut Transform, &mut BoneTrackingStatus)>,
+) {
+    let Some(tracker) = tracker else {
+        return;
+    };
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    let info = BodyJointsLocateInfoFB {
+        ty: TYPE_BODY_JOINTS_LOCATE_INFO_FB,
+        next: ptr::null(),
+        base_space: input.stage.as_raw(),
+        time,
+    };
+    let mut joints = [BodyJointLocationFB {
+        location_flags: xr::SpaceLocationFlags::EMPTY,
+        pose: xr::Posef::IDENTITY,
+    }; BODY_BONE_COUNT];
+    let mut locations = BodyJointLocationsFB {
+        ty: TYPE_BODY_JOINT_LOCATIONS_FB,
+        next: ptr::null_mut(),
+        is_active: false.into(),
+        confidence: 0.0,
+        joint_count: joints.len() as u32,
+        joint_locations: joints.as_mut_ptr(),
+        skeleton_changed_count: 0,
+        time,
+    };
+    let result = unsafe { (tracker.fp.locate_body_joints)(tracker.tracker, &info, &mut locations) };
+    if result.into_raw() < 0 {
+        warn!("Failed to locate the body joints: {result}");
+        state.active = false;
+        return;
+    }
+    state.active = locations.is_active.into();
+    state.confidence = locations.confidence;
+    for (bone, mut transform, mut status) in &mut query {
+        let joint = joints[bone.get_index_from_bone()];
+        let valid = state.active
+            && joint.location_flags.contains(
+                xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID,
+            );
+        if !valid {
+            *status = BoneTrackingStatus::Emulated;
+            continue;
+        }
+        *status = BoneTrackingStatus::Tracked;
+        *transform = Transform::from_translation(joint.pose.position.to_vec3())
+            .with_rotation(joint.pose.orientation.to_quat());
+    }
+}
+
+/// Whether the system can track the body, fails with `ERROR_FUNCTION_UNSUPPORTED` when
+/// `XR_FB_body_tracking` is not enabled.
+pub fn supports_body_tracking(instance: &XrInstance, system: xr::SystemId) -> xr::Result<bool> {
+    unsafe { load_function(instance, b"xrCreateBodyTrackerFB\0") }?;
+    let mut properties = SystemBodyTrackingPropertiesFB {
+        ty: TYPE_SYSTEM_BODY_TRACKING_PROPERTIES_FB,
+        next: ptr::null_mut(),
+        supports_body_tracking: false.into(),
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties as *mut _ as *mut _;
+    let result = unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(properties.supports_body_tracking.into())
+}
@@ -0,0 +1,185 @@
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use openxr as xr;
+use xr::sys;
+
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown, XrSetup};
+
+use super::actions::{ActionHandednes, ActionType, SetupActionSets, XrActionSets, XrBinding};
+use super::interactions::{XRInteractorState, XRRayInteractor};
+use super::trackers::{AimPose, OpenXRTracker};
+use super::{QuatConv, Vec3Conv};
+
+const EYE_GAZE_ACTION_SET: &str = "eye_gaze";
+const GAZE_POSE_ACTION: &str = "gaze_pose";
+
+/// Where the user is looking, placed in the tracking root. The gaze points along the entity's
+/// forward direction and it is a [`XRRayInteractor`], so looking at an interactable hovers it.
+/// Remove the [`XRRayInteractor`] to opt out, or set its [`XRInteractorState`] to select.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrEyeGaze {
+    /// The runtime provided a gaze this frame, the transform keeps the last one otherwise.
+    pub valid: bool,
+    /// The gaze is actively tracked instead of inferred.
+    pub tracked: bool,
+}
+
+#[derive(Resource, Default)]
+struct EyeGazeSpace(Option<xr::Space>);
+
+/// Tracks the eyes through `XR_EXT_eye_gaze_interaction`. Nothing happens when the extension is
+/// not enabled or the system has no eye tracking.
+pub struct XrEyeGazePlugin;
+
+impl Plugin for XrEyeGazePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EyeGazeSpace>();
+        app.add_systems(XrSetup, setup_eye_gaze_actions);
+        app.add_systems(XrSessionSetup, setup_eye_gaze);
+        app.add_systems(XrSessionTeardown, teardown_eye_gaze);
+        app.add_systems(
+            PreUpdate,
+            update_eye_gaze.run_if(xr_only()).after(xr_begin_frame),
+        );
+    }
+}
+
+fn setup_eye_gaze_actions(instance: Res<XrInstance>, mut action_sets: ResMut<SetupActionSets>) {
+    let supported = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .and_then(|system| supports_eye_gaze(&instance, system));
+    match supported {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("The system has no eye tracking");
+            return;
+        }
+        Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT) => return,
+        Err(err) => {
+            warn!("Failed to query eye tracking support: {err}");
+            return;
+        }
+    }
+    let action_set = action_sets.add_action_set(EYE_GAZE_ACTION_SET, "Eye Gaze Input".into(), 0);
+    action_set.new_action(
+        GAZE_POSE_ACTION,
+        "Gaze Pose".into(),
+        ActionType::PoseF,
+        ActionHandednes::Single,
+    );
+    action_set.suggest_binding(
+        "/interaction_profiles/ext/eye_gaze_interaction",
+        &[XrBinding::new(
+            GAZE_POSE_ACTION,
+            "/user/eyes_ext/input/gaze_ext/pose",
+        )],
+    );
+}
+
+fn setup_eye_gaze(
+    mut commands: Commands,
+    mut gaze_space: ResMut<EyeGazeSpace>,
+    action_sets: Res<XrActionSets>,
+    session: Res<XrSession>,
+    query: Query<(), With<XrEyeGaze>>,
+) {
+    let Ok(action) = action_sets.get_action_posef(EYE_GAZE_ACTION_SET, GAZE_POSE_ACTION) else {
+        return;
+    };
+    match action.create_space((**session).clone(), xr::Path::NULL, xr::Posef::IDENTITY) {
+        Ok(space) => gaze_space.0 = Some(space),
+        Err(err) => {
+            warn!("Failed to create the eye gaze space: {err}");
+            return;
+        }
+    }
+    if query.is_empty() {
+        commands.spawn((
+            XrEyeGaze::default(),
+            AimPose(Transform::IDENTITY),
+            XRRayInteractor,
+            XRInteractorState::default(),
+            SpatialBundle::default(),
+            OpenXRTracker,
+        ));
+    }
+}
+
+/// The gaze space keeps its session alive, so it is dropped with a lost session.
+fn teardown_eye_gaze(mut gaze_space: ResMut<EyeGazeSpace>) {
+    gaze_space.0 = None;
+}
+
+fn update_eye_gaze(
+    gaze_space: Res<EyeGazeSpace>,
+    action_sets: Res<XrActionSets>,
+    session: Res<XrSession>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut query: Query<(&mut XrEyeGaze, &mut Transform, Option<&mut AimPose>)>,
+) {
+    let Some(space) = gaze_space.0.as_ref() else {
+        return;
+    };
+    let active = action_sets
+        .get_action_posef(EYE_GAZE_ACTION_SET, GAZE_POSE_ACTION)
+        .map_or(false, |action| {
+            action.is_active(&session, xr::Path::NULL).unwrap_or(false)
+        });
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    let location = match space.locate(&input.stage, time) {
+        Ok(location) if active => Some(location),
+        Ok(_) => None,
+        Err(err) => {
+            warn!("Failed to locate the eye gaze: {err}");
+            None
+        }
+    };
+    for (mut gaze, mut transform, aim) in &mut query {
+        let Some(location) = location else {
+            gaze.valid = false;
+            gaze.tracked = false;
+            continue;
+        };
+        let flags = location.location_flags;
+        gaze.valid = flags.contains(
+            xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID,
+        );
+        gaze.tracked = flags.contains(xr::SpaceLocationFlags::ORIENTATION_TRACKED);
+        if !gaze.valid {
+            continue;
+        }
+        *transform = Transform::from_translation(location.pose.position.to_vec3())
+            .with_rotation(location.pose.orientation.to_quat());
+        if let Some(mut aim) = aim {
+            aim.0 = *transform;
+        }
+    }
+}
+
+/// Whether the system can track the eyes, fails when `XR_EXT_eye_gaze_interaction` is not
+/// enabled.
+pub fn supports_eye_gaze(instance: &XrInstance, system: xr::SystemId) -> xr::Result<bool> {
+    if instance.exts().ext_eye_gaze_interaction.is_none() {
+        return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
+    }
+    let mut properties = sys::SystemEyeGazeInteractionPropertiesEXT {
+        ty: sys::SystemEyeGazeInteractionPropertiesEXT::TYPE,
+        next: ptr::null_mut(),
+        supports_eye_gaze_interaction: false.into(),
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties as *mut _ as *mut _;
+    let result = unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok(properties.supports_eye_gaze_interaction.into())
+}
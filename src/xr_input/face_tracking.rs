@@ -0,0 +1,295 @@
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use bevy::render::mesh::morph::MorphWeights;
+use bevy::utils::HashMap;
+use openxr as xr;
+use xr::sys;
+
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown};
+
+/// The eye expressions in the order of `XrEyeExpressionHTC`.
+pub const EYE_EXPRESSIONS: [&str; 14] = [
+    "eye_left_blink",
+    "eye_left_wide",
+    "eye_right_blink",
+    "eye_right_wide",
+    "eye_left_squeeze",
+    "eye_right_squeeze",
+    "eye_left_down",
+    "eye_right_down",
+    "eye_left_out",
+    "eye_right_in",
+    "eye_left_in",
+    "eye_right_out",
+    "eye_left_up",
+    "eye_right_up",
+];
+
+/// The lip expressions in the order of `XrLipExpressionHTC`.
+pub const LIP_EXPRESSIONS: [&str; 37] = [
+    "jaw_right",
+    "jaw_left",
+    "jaw_forward",
+    "jaw_open",
+    "mouth_ape_shape",
+    "mouth_upper_right",
+    "mouth_upper_left",
+    "mouth_lower_right",
+    "mouth_lower_left",
+    "mouth_upper_overturn",
+    "mouth_lower_overturn",
+    "mouth_pout",
+    "mouth_smile_right",
+    "mouth_smile_left",
+    "mouth_sad_right",
+    "mouth_sad_left",
+    "cheek_puff_right",
+    "cheek_puff_left",
+    "cheek_suck",
+    "mouth_upper_upright",
+    "mouth_upper_upleft",
+    "mouth_lower_downright",
+    "mouth_lower_downleft",
+    "mouth_upper_inside",
+    "mouth_lower_inside",
+    "mouth_lower_overlay",
+    "tongue_longstep1",
+    "tongue_left",
+    "tongue_right",
+    "tongue_up",
+    "tongue_down",
+    "tongue_roll",
+    "tongue_longstep2",
+    "tongue_upright_morph",
+    "tongue_upleft_morph",
+    "tongue_downright_morph",
+    "tongue_downleft_morph",
+];
+
+/// The latest face expression weights between 0 and 1, indexed like [`EYE_EXPRESSIONS`] and
+/// [`LIP_EXPRESSIONS`]. A list is empty while that part of the face is not tracked.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct XrFaceExpressions {
+    pub eye: Vec<f32>,
+    pub lip: Vec<f32>,
+}
+
+impl XrFaceExpressions {
+    /// The weight of an expression from [`EYE_EXPRESSIONS`] or [`LIP_EXPRESSIONS`].
+    pub fn weight(&self, expression: &str) -> Option<f32> {
+        if let Some(index) = EYE_EXPRESSIONS.iter().position(|name| *name == expression) {
+            return self.eye.get(index).copied();
+        }
+        let index = LIP_EXPRESSIONS
+            .iter()
+            .position(|name| *name == expression)?;
+        self.lip.get(index).copied()
+    }
+}
+
+/// Drives the [`MorphWeights`] of this entity with the [`XrFaceExpressions`]. Morph targets named
+/// after an expression follow it, ignoring case, `rename` maps other morph target names onto an
+/// expression. Targets without an expression or while it isn't tracked are left alone.
+#[derive(Component, Clone, Debug, Default)]
+pub struct XrFaceMorphTargets {
+    pub rename: HashMap<String, String>,
+}
+
+#[derive(Resource)]
+struct FaceTrackers {
+    fp: xr::raw::FacialTrackingHTC,
+    eye: sys::FacialTrackerHTC,
+    lip: sys::FacialTrackerHTC,
+}
+
+impl FaceTrackers {
+    /// Forgets the trackers of a lost session, they were destroyed along with it.
+    fn session_lost(&mut self) {
+        self.eye = sys::FacialTrackerHTC::NULL;
+        self.lip = sys::FacialTrackerHTC::NULL;
+    }
+}
+
+impl Drop for FaceTrackers {
+    fn drop(&mut self) {
+        for tracker in [self.eye, self.lip] {
+            if tracker != sys::FacialTrackerHTC::NULL {
+                unsafe { (self.fp.destroy_facial_tracker)(tracker) };
+            }
+        }
+    }
+}
+
+/// Tracks the face through `XR_HTC_facial_tracking` into [`XrFaceExpressions`]. Nothing happens
+/// when the extension is not enabled or the system can't track the face.
+pub struct XrFaceTrackingPlugin;
+
+impl Plugin for XrFaceTrackingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrFaceExpressions>();
+        app.add_systems(XrSessionSetup, setup_face_tracking);
+        app.add_systems(XrSessionTeardown, teardown_face_tracking);
+        app.add_systems(
+            PreUpdate,
+            (update_face_expressions, apply_face_morph_targets)
+                .chain()
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+    }
+}
+
+fn setup_face_tracking(
+    mut commands: Commands,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+) {
+    let Some(fp) = instance.exts().htc_facial_tracking else {
+        return;
+    };
+    let support = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .and_then(|system| face_tracking_support(&instance, system));
+    let (eye_supported, lip_supported) = match support {
+        Ok(support) => support,
+        Err(err) => {
+            warn!("Failed to query face tracking support: {err}");
+            return;
+        }
+    };
+    let create = |supported: bool, facial_tracking_type| {
+        if !supported {
+            return sys::FacialTrackerHTC::NULL;
+        }
+        let info = sys::FacialTrackerCreateInfoHTC {
+            ty: sys::FacialTrackerCreateInfoHTC::TYPE,
+            next: ptr::null(),
+            facial_tracking_type,
+        };
+        let mut tracker = sys::FacialTrackerHTC::NULL;
+        let result = unsafe { (fp.create_facial_tracker)(session.as_raw(), &info, &mut tracker) };
+        if result.into_raw() < 0 {
+            warn!("Failed to create a {facial_tracking_type:?} face tracker: {result}");
+            return sys::FacialTrackerHTC::NULL;
+        }
+        tracker
+    };
+    let trackers = FaceTrackers {
+        fp,
+        eye: create(eye_supported, sys::FacialTrackingTypeHTC::EYE_DEFAULT),
+        lip: create(lip_supported, sys::FacialTrackingTypeHTC::LIP_DEFAULT),
+    };
+    if trackers.eye == sys::FacialTrackerHTC::NULL && trackers.lip == sys::FacialTrackerHTC::NULL {
+        info!("The system can't track the face");
+        return;
+    }
+    commands.insert_resource(trackers);
+}
+
+fn teardown_face_tracking(mut commands: Commands, trackers: Option<ResMut<FaceTrackers>>) {
+    if let Some(mut trackers) = trackers {
+        trackers.session_lost();
+    }
+    commands.remove_resource::<FaceTrackers>();
+}
+
+fn update_face_expressions(
+    trackers: Option<Res<FaceTrackers>>,
+    frame_state: Res<XrFrameState>,
+    mut expressions: ResMut<XrFaceExpressions>,
+) {
+    let Some(trackers) = trackers else {
+        return;
+    };
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    let expressions = &mut *expressions;
+    for (tracker, weights, count) in [
+        (trackers.eye, &mut expressions.eye, EYE_EXPRESSIONS.len()),
+        (trackers.lip, &mut expressions.lip, LIP_EXPRESSIONS.len()),
+    ] {
+        weights.clear();
+        if tracker == sys::FacialTrackerHTC::NULL {
+            continue;
+        }
+        let mut buffer = vec![0.0; count];
+        let mut facial_expressions = sys::FacialExpressionsHTC {
+            ty: sys::FacialExpressionsHTC::TYPE,
+            next: ptr::null(),
+            is_active: false.into(),
+            sample_time: time,
+            expression_count: count as u32,
+            expression_weightings: buffer.as_mut_ptr(),
+        };
+        let result =
+            unsafe { (trackers.fp.get_facial_expressions)(tracker, &mut facial_expressions) };
+        if result.into_raw() < 0 {
+            warn!("Failed to get the face expressions: {result}");
+            continue;
+        }
+        if facial_expressions.is_active.into() {
+            *weights = buffer;
+        }
+    }
+}
+
+fn apply_face_morph_targets(
+    expressions: Res<XrFaceExpressions>,
+    meshes: Res<Assets<Mesh>>,
+    mut query: Query<(&XrFaceMorphTargets, &mut MorphWeights)>,
+) {
+    if expressions.eye.is_empty() && expressions.lip.is_empty() {
+        return;
+    }
+    for (targets, mut weights) in &mut query {
+        let Some(names) = weights
+            .first_mesh()
+            .and_then(|mesh| meshes.get(mesh))
+            .and_then(|mesh| mesh.morph_target_names())
+        else {
+            continue;
+        };
+        let names = names.to_vec();
+        for (weight, name) in weights.weights_mut().iter_mut().zip(names) {
+            let expression = match targets.rename.get(&name) {
+                Some(expression) => expression.clone(),
+                None => name.to_lowercase(),
+            };
+            if let Some(value) = expressions.weight(&expression) {
+                *weight = value;
+            }
+        }
+    }
+}
+
+/// Whether the system can track the eyes and the lips, fails when `XR_HTC_facial_tracking` is not
+/// enabled.
+pub fn face_tracking_support(
+    instance: &XrInstance,
+    system: xr::SystemId,
+) -> xr::Result<(bool, bool)> {
+    if instance.exts().htc_facial_tracking.is_none() {
+        return Err(sys::Result::ERROR_EXTENSION_NOT_PRESENT);
+    }
+    let mut properties = sys::SystemFacialTrackingPropertiesHTC {
+        ty: sys::SystemFacialTrackingPropertiesHTC::TYPE,
+        next: ptr::null_mut(),
+        support_eye_facial_tracking: false.into(),
+        support_lip_facial_tracking: false.into(),
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties as *mut _ as *mut _;
+    let result = unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    Ok((
+        properties.support_eye_facial_tracking.into(),
+        properties.support_lip_facial_tracking.into(),
+    ))
+}
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use super::common::HandBoneRadius;
+use super::hand_tracking::{DisableHandTracking, HandTrackingData};
+use super::{BoneTrackingStatus, HandBone};
+use crate::input::XrInput;
+use crate::resources::XrFrameState;
+use crate::xr_input::trackers::{OpenXRTracker, OpenXRTrackingRoot};
+use crate::xr_input::Hand;
+
+/// The number of capsules `XR_FB_hand_tracking_capsules` reports per hand.
+pub const FB_CAPSULE_COUNT: usize = 19;
+
+/// The capsules built from the joints when the runtime reports none, as start and end joint.
+const JOINT_CAPSULES: [(HandBone, HandBone); FB_CAPSULE_COUNT] = [
+    (HandBone::ThumbMetacarpal, HandBone::ThumbProximal),
+    (HandBone::ThumbProximal, HandBone::ThumbDistal),
+    (HandBone::ThumbDistal, HandBone::ThumbTip),
+    (HandBone::IndexMetacarpal, HandBone::IndexProximal),
+    (HandBone::IndexProximal, HandBone::IndexIntermediate),
+    (HandBone::IndexIntermediate, HandBone::IndexDistal),
+    (HandBone::IndexDistal, HandBone::IndexTip),
+    (HandBone::MiddleMetacarpal, HandBone::MiddleProximal),
+    (HandBone::MiddleProximal, HandBone::MiddleIntermediate),
+    (HandBone::MiddleIntermediate, HandBone::MiddleDistal),
+    (HandBone::MiddleDistal, HandBone::MiddleTip),
+    (HandBone::RingMetacarpal, HandBone::RingProximal),
+    (HandBone::RingProximal, HandBone::RingIntermediate),
+    (HandBone::RingIntermediate, HandBone::RingDistal),
+    (HandBone::RingDistal, HandBone::RingTip),
+    (HandBone::LittleMetacarpal, HandBone::LittleProximal),
+    (HandBone::LittleProximal, HandBone::LittleIntermediate),
+    (HandBone::LittleIntermediate, HandBone::LittleDistal),
+    (HandBone::LittleDistal, HandBone::LittleTip),
+];
+
+/// A capsule from `start` to `end`, both ends rounded by `radius`.
+#[derive(Clone, Copy, Debug)]
+pub struct HandCapsule {
+    /// The bone the capsule moves with.
+    pub bone: HandBone,
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+}
+
+impl HandCapsule {
+    pub fn center(&self) -> Vec3 {
+        (self.start + self.end) / 2.0
+    }
+    pub fn half_length(&self) -> f32 {
+        self.start.distance(self.end) / 2.0
+    }
+    /// The rotation taking the Y axis onto the capsule, for engines building capsules along Y.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_arc(Vec3::Y, (self.end - self.start).normalize_or_zero())
+    }
+}
+
+/// The collision capsules of a hand, on an entity per hand spawned by
+/// [`HandCollisionCapsulesPlugin`]. The capsules are in the entity's space, which sits in the
+/// tracking root like the [`HandBone`]s, so a compound collider of them on this entity follows the
+/// hand. Nothing is tied to a physics engine.
+#[derive(Component, Clone, Debug, Default)]
+pub struct HandCollisionCapsules {
+    pub capsules: Vec<HandCapsule>,
+    /// The capsules come from `XR_FB_hand_tracking_capsules` instead of the joint radii.
+    pub from_runtime: bool,
+}
+
+/// Keeps a [`HandCollisionCapsules`] per hand up to date, from `XR_FB_hand_tracking_capsules`
+/// when the hand is tracked and the extension is enabled, and from the [`HandBone`] entities and
+/// their radii otherwise, so emulated hands collide as well.
+pub struct HandCollisionCapsulesPlugin;
+
+impl Plugin for HandCollisionCapsulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_hand_capsules);
+        app.add_systems(
+            PostUpdate,
+            update_hand_capsules.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn spawn_hand_capsules(mut commands: Commands) {
+    for hand in [Hand::Left, Hand::Right] {
+        commands.spawn((
+            SpatialBundle::default(),
+            hand,
+            HandCollisionCapsules::default(),
+            OpenXRTracker,
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_hand_capsules(
+    hand_tracking: Option<Res<HandTrackingData>>,
+    xr_input: Option<Res<XrInput>>,
+    xr_frame_state: Option<Res<XrFrameState>>,
+    disabled_tracking: Option<Res<DisableHandTracking>>,
+    root_query: Query<&Transform, (With<OpenXRTrackingRoot>, Without<HandBone>)>,
+    bones: Query<(
+        &Transform,
+        &Hand,
+        &HandBone,
+        &HandBoneRadius,
+        &BoneTrackingStatus,
+    )>,
+    mut query: Query<(&Hand, &mut HandCollisionCapsules)>,
+) {
+    let hand_ref = match (&hand_tracking, &xr_input, &xr_frame_state) {
+        (Some(tracking), Some(input), Some(frame_state)) => {
+            Some(tracking.get_ref(input, frame_state))
+        }
+        _ => None,
+    };
+    for (hand, mut capsules) in &mut query {
+        let mut joints = [None; 26];
+        let mut tracked = false;
+        for (transform, _, bone, radius, status) in bones.iter().filter(|bone| bone.1 == hand) {
+            joints[bone.get_index_from_bone()] = Some((transform.translation, radius.0));
+            tracked |= *status == BoneTrackingStatus::Tracked;
+        }
+        let disabled = match (hand, disabled_tracking.as_deref()) {
+            (_, Some(DisableHandTracking::Both)) => true,
+            (Hand::Left, Some(DisableHandTracking::OnlyLeft)) => true,
+            (Hand::Right, Some(DisableHandTracking::OnlyRight)) => true,
+            _ => false,
+        };
+        let runtime_capsules = hand_ref
+            .as_ref()
+            .filter(|_| tracked && !disabled)
+            .and_then(|hand_ref| hand_ref.get_capsules(*hand));
+        if let (Some(runtime_capsules), Ok(root)) = (runtime_capsules, root_query.get_single()) {
+            // placed like the tracked bones
+            capsules.capsules = runtime_capsules
+                .map(|capsule| HandCapsule {
+                    start: root.transform_point(capsule.start),
+                    end: root.transform_point(capsule.end),
+                    ..capsule
+                })
+                .to_vec();
+            capsules.from_runtime = true;
+            continue;
+        }
+        capsules.capsules.clear();
+        capsules.from_runtime = false;
+        for (start, end) in JOINT_CAPSULES {
+            let (Some((start_position, radius)), Some((end_position, _))) = (
+                joints[start.get_index_from_bone()],
+                joints[end.get_index_from_bone()],
+            ) else {
+                continue;
+            };
+            capsules.capsules.push(HandCapsule {
+                bone: start,
+                start: start_position,
+                end: end_position,
+                radius,
+            });
+        }
+    }
+}
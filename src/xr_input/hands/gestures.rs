@@ -0,0 +1,679 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::utils::HashMap;
+
+use super::{BoneTrackingStatus, HandBone};
+use crate::xr_input::Hand;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HandFinger {
+    Thumb,
+    Index,
+    Middle,
+    Ring,
+    Little,
+}
+
+impl HandFinger {
+    pub fn get_all_fingers() -> [HandFinger; 5] {
+        [
+            HandFinger::Thumb,
+            HandFinger::Index,
+            HandFinger::Middle,
+            HandFinger::Ring,
+            HandFinger::Little,
+        ]
+    }
+    /// The joints of the finger from the wrist to the tip.
+    pub fn joints(&self) -> &'static [HandBone] {
+        match self {
+            HandFinger::Thumb => &[
+                HandBone::Wrist,
+                HandBone::ThumbMetacarpal,
+                HandBone::ThumbProximal,
+                HandBone::ThumbDistal,
+                HandBone::ThumbTip,
+            ],
+            HandFinger::Index => &[
+                HandBone::Wrist,
+                HandBone::IndexMetacarpal,
+                HandBone::IndexProximal,
+                HandBone::IndexIntermediate,
+                HandBone::IndexDistal,
+                HandBone::IndexTip,
+            ],
+            HandFinger::Middle => &[
+                HandBone::Wrist,
+                HandBone::MiddleMetacarpal,
+                HandBone::MiddleProximal,
+                HandBone::MiddleIntermediate,
+                HandBone::MiddleDistal,
+                HandBone::MiddleTip,
+            ],
+            HandFinger::Ring => &[
+                HandBone::Wrist,
+                HandBone::RingMetacarpal,
+                HandBone::RingProximal,
+                HandBone::RingIntermediate,
+                HandBone::RingDistal,
+                HandBone::RingTip,
+            ],
+            HandFinger::Little => &[
+                HandBone::Wrist,
+                HandBone::LittleMetacarpal,
+                HandBone::LittleProximal,
+                HandBone::LittleIntermediate,
+                HandBone::LittleDistal,
+                HandBone::LittleTip,
+            ],
+        }
+    }
+    /// The summed bend of the knuckles when the finger is fully curled.
+    fn max_curl(&self) -> f32 {
+        match self {
+            HandFinger::Thumb => PI * 0.75,
+            _ => PI * 1.5,
+        }
+    }
+}
+
+/// The joint positions of a hand in the space of its [`HandBone`] entities, and the angles the
+/// fingers bend at each joint.
+#[derive(Clone, Debug)]
+pub struct XrHandPose {
+    pub hand: Hand,
+    pub positions: [Vec3; 26],
+    /// The angle in radians between the bone towards each joint and the bone away from it, 0 for
+    /// the palm, the wrist and the tips.
+    pub bends: [f32; 26],
+    /// Every bone was tracked, not emulated.
+    pub tracked: bool,
+}
+
+impl XrHandPose {
+    pub fn new(hand: Hand, positions: [Vec3; 26], tracked: bool) -> Self {
+        let mut bends = [0.0; 26];
+        for finger in HandFinger::get_all_fingers() {
+            let joints = finger.joints();
+            for i in 1..joints.len() - 1 {
+                let position = |bone: HandBone| positions[bone.get_index_from_bone()];
+                let incoming = position(joints[i]) - position(joints[i - 1]);
+                let outgoing = position(joints[i + 1]) - position(joints[i]);
+                if incoming.length_squared() > f32::EPSILON
+                    && outgoing.length_squared() > f32::EPSILON
+                {
+                    bends[joints[i].get_index_from_bone()] = incoming.angle_between(outgoing);
+                }
+            }
+        }
+        Self {
+            hand,
+            positions,
+            bends,
+            tracked,
+        }
+    }
+    pub fn position(&self, bone: HandBone) -> Vec3 {
+        self.positions[bone.get_index_from_bone()]
+    }
+    pub fn bend(&self, bone: HandBone) -> f32 {
+        self.bends[bone.get_index_from_bone()]
+    }
+    /// How far the finger is curled, 0 when straight and 1 when fully curled.
+    pub fn curl(&self, finger: HandFinger) -> f32 {
+        let joints = finger.joints();
+        // the knuckles, the bend between the wrist and the metacarpal barely moves
+        let bend: f32 = joints[2..joints.len() - 1]
+            .iter()
+            .map(|bone| self.bend(*bone))
+            .sum();
+        (bend / finger.max_curl()).clamp(0.0, 1.0)
+    }
+    /// The direction the finger points in, from its first knuckle to its tip.
+    pub fn finger_direction(&self, finger: HandFinger) -> Vec3 {
+        let joints = finger.joints();
+        (self.position(joints[joints.len() - 1]) - self.position(joints[2])).normalize_or_zero()
+    }
+    /// The direction the inside of the hand faces.
+    pub fn palm_normal(&self) -> Vec3 {
+        let wrist = self.position(HandBone::Wrist);
+        let index = self.position(HandBone::IndexProximal) - wrist;
+        let little = self.position(HandBone::LittleProximal) - wrist;
+        match self.hand {
+            Hand::Left => little.cross(index).normalize_or_zero(),
+            Hand::Right => index.cross(little).normalize_or_zero(),
+        }
+    }
+    pub fn pinch_distance(&self) -> f32 {
+        self.position(HandBone::ThumbTip)
+            .distance(self.position(HandBone::IndexTip))
+    }
+    fn curled(&self, finger: HandFinger) -> f32 {
+        ramp(self.curl(finger), 0.4, 0.7)
+    }
+    fn straight(&self, finger: HandFinger) -> f32 {
+        ramp(self.curl(finger), 0.35, 0.15)
+    }
+    /// The mean of `f` over every finger except the thumb.
+    fn fingers(&self, f: impl Fn(&Self, HandFinger) -> f32) -> f32 {
+        let fingers = [
+            HandFinger::Index,
+            HandFinger::Middle,
+            HandFinger::Ring,
+            HandFinger::Little,
+        ];
+        fingers.iter().map(|finger| f(self, *finger)).sum::<f32>() / fingers.len() as f32
+    }
+    /// How confident the hand makes the gesture, between 0 and 1. Custom gestures are matched by
+    /// [`XrHandPoseTemplate::confidence`] instead and are always 0 here.
+    pub fn confidence(&self, gesture: &XrGesture) -> f32 {
+        match gesture {
+            XrGesture::Pinch => ramp(self.pinch_distance(), 0.035, 0.015),
+            XrGesture::Grab => self.fingers(Self::curled),
+            XrGesture::Point => self.straight(HandFinger::Index).min(
+                (self.curled(HandFinger::Middle)
+                    + self.curled(HandFinger::Ring)
+                    + self.curled(HandFinger::Little))
+                    / 3.0,
+            ),
+            XrGesture::ThumbsUp => self
+                .fingers(Self::curled)
+                .min(ramp(self.curl(HandFinger::Thumb), 0.5, 0.25))
+                .min(ramp(
+                    self.finger_direction(HandFinger::Thumb).dot(Vec3::Y),
+                    0.5,
+                    0.8,
+                )),
+            XrGesture::OpenPalm => self.open_palm(),
+            XrGesture::PalmUpMenu => {
+                self.open_palm()
+                    .min(ramp(self.palm_normal().dot(Vec3::Y), 0.5, 0.85))
+            }
+            XrGesture::Custom(_) => 0.0,
+        }
+    }
+    fn open_palm(&self) -> f32 {
+        self.fingers(Self::straight)
+            .min(ramp(self.curl(HandFinger::Thumb), 0.6, 0.35))
+    }
+}
+
+/// Maps `value` from `from..to` onto `0..1`, clamped, `from` may be larger than `to`.
+fn ramp(value: f32, from: f32, to: f32) -> f32 {
+    ((value - from) / (to - from)).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum XrGesture {
+    /// The thumb and index finger tips touch.
+    Pinch,
+    /// All fingers are curled into a fist.
+    Grab,
+    /// The index finger is stretched out while the others are curled.
+    Point,
+    ThumbsUp,
+    /// All fingers are stretched out.
+    OpenPalm,
+    /// An open palm facing up, to bring up a menu.
+    PalmUpMenu,
+    /// A match of the [`XrHandPoseTemplate`] with this name.
+    Custom(String),
+}
+
+impl XrGesture {
+    pub fn get_built_in_gestures() -> [XrGesture; 6] {
+        [
+            XrGesture::Pinch,
+            XrGesture::Grab,
+            XrGesture::Point,
+            XrGesture::ThumbsUp,
+            XrGesture::OpenPalm,
+            XrGesture::PalmUpMenu,
+        ]
+    }
+}
+
+/// A user defined gesture, matched by the bend of the joints. Bends don't tell the hands apart so
+/// a template matches both unless `hand` is set.
+#[derive(Clone, Debug)]
+pub struct XrHandPoseTemplate {
+    pub name: String,
+    /// The expected bend at joints, see [`XrHandPose::bends`].
+    pub bends: Vec<(HandBone, f32)>,
+    /// The mean difference to the expected bends in radians at which the confidence reaches 0.
+    pub tolerance: f32,
+    pub hand: Option<Hand>,
+}
+
+impl XrHandPoseTemplate {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            bends: Vec::new(),
+            tolerance: 0.35,
+            hand: None,
+        }
+    }
+    /// A template of every joint bend of a recorded pose, like one from [`XrHandGestures`].
+    pub fn from_pose(name: impl Into<String>, pose: &XrHandPose) -> Self {
+        let mut template = Self::new(name);
+        for finger in HandFinger::get_all_fingers() {
+            let joints = finger.joints();
+            for bone in &joints[1..joints.len() - 1] {
+                template.bends.push((*bone, pose.bend(*bone)));
+            }
+        }
+        template
+    }
+    pub fn with_bend(mut self, bone: HandBone, bend: f32) -> Self {
+        self.bends.push((bone, bend));
+        self
+    }
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+    pub fn with_hand(mut self, hand: Hand) -> Self {
+        self.hand = Some(hand);
+        self
+    }
+    pub fn confidence(&self, pose: &XrHandPose) -> f32 {
+        if self.bends.is_empty() || self.hand.is_some_and(|hand| hand != pose.hand) {
+            return 0.0;
+        }
+        let error = self
+            .bends
+            .iter()
+            .map(|(bone, bend)| (pose.bend(*bone) - bend).abs())
+            .sum::<f32>()
+            / self.bends.len() as f32;
+        ramp(error, self.tolerance, 0.0)
+    }
+}
+
+/// The user defined gestures recognized next to the built-in ones.
+#[derive(Resource, Clone, Debug, Default, Deref, DerefMut)]
+pub struct XrGestureTemplates(pub Vec<XrHandPoseTemplate>);
+
+/// A gesture starts once its confidence reaches `start_confidence` and ends when it drops below
+/// `end_confidence`, so it doesn't flicker around a single threshold.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct XrGestureSettings {
+    pub start_confidence: f32,
+    pub end_confidence: f32,
+}
+
+impl Default for XrGestureSettings {
+    fn default() -> Self {
+        Self {
+            start_confidence: 0.8,
+            end_confidence: 0.6,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct XrHandGestureState {
+    /// The pose of the hand this frame, `None` while the hand has no bones.
+    pub pose: Option<XrHandPose>,
+    /// The active gestures and their confidence.
+    pub active: HashMap<XrGesture, f32>,
+}
+
+/// The gestures each hand makes.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct XrHandGestures {
+    pub left: XrHandGestureState,
+    pub right: XrHandGestureState,
+}
+
+impl XrHandGestures {
+    pub fn get(&self, hand: Hand) -> &XrHandGestureState {
+        match hand {
+            Hand::Left => &self.left,
+            Hand::Right => &self.right,
+        }
+    }
+    pub fn get_mut(&mut self, hand: Hand) -> &mut XrHandGestureState {
+        match hand {
+            Hand::Left => &mut self.left,
+            Hand::Right => &mut self.right,
+        }
+    }
+    pub fn is_active(&self, hand: Hand, gesture: &XrGesture) -> bool {
+        self.get(hand).active.contains_key(gesture)
+    }
+    /// The confidence of an active gesture.
+    pub fn confidence(&self, hand: Hand, gesture: &XrGesture) -> Option<f32> {
+        self.get(hand).active.get(gesture).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrGesturePhase {
+    Started,
+    /// Sent every frame the gesture stays active after it started.
+    Held,
+    Ended,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct XrGestureEvent {
+    pub hand: Hand,
+    pub gesture: XrGesture,
+    pub phase: XrGesturePhase,
+    pub confidence: f32,
+}
+
+/// Recognizes the built-in [`XrGesture`]s and the [`XrGestureTemplates`] from the [`HandBone`]
+/// entities into [`XrHandGestures`] and [`XrGestureEvent`]s. Tracked and emulated bones are treated
+/// the same.
+pub struct XrGesturePlugin;
+
+impl Plugin for XrGesturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XrGestureTemplates>();
+        app.init_resource::<XrGestureSettings>();
+        app.init_resource::<XrHandGestures>();
+        app.add_event::<XrGestureEvent>();
+        app.add_systems(
+            PostUpdate,
+            recognize_gestures.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn recognize_gestures(
+    settings: Res<XrGestureSettings>,
+    templates: Res<XrGestureTemplates>,
+    mut gestures: ResMut<XrHandGestures>,
+    mut events: EventWriter<XrGestureEvent>,
+    bones: Query<(&Transform, &Hand, &HandBone, &BoneTrackingStatus)>,
+) {
+    for hand in [Hand::Left, Hand::Right] {
+        let mut positions = [None; 26];
+        let mut tracked = true;
+        for (transform, _, bone, status) in bones.iter().filter(|bone| *bone.1 == hand) {
+            positions[bone.get_index_from_bone()] = Some(transform.translation);
+            tracked &= *status == BoneTrackingStatus::Tracked;
+        }
+        let pose = positions
+            .iter()
+            .all(Option::is_some)
+            .then(|| XrHandPose::new(hand, positions.map(Option::unwrap_or_default), tracked));
+        let mut confidences = HashMap::new();
+        if let Some(pose) = &pose {
+            for gesture in XrGesture::get_built_in_gestures() {
+                let confidence = pose.confidence(&gesture);
+                confidences.insert(gesture, confidence);
+            }
+            for template in templates.iter() {
+                let gesture = XrGesture::Custom(template.name.clone());
+                let confidence = template.confidence(pose);
+                // templates sharing a name count as one gesture
+                let best = confidences.entry(gesture).or_insert(0.0);
+                *best = best.max(confidence);
+            }
+        }
+        let state = gestures.get_mut(hand);
+        state.active.retain(|gesture, active_confidence| {
+            let confidence = confidences.get(gesture).copied().unwrap_or(0.0);
+            let held = confidence >= settings.end_confidence;
+            events.send(XrGestureEvent {
+                hand,
+                gesture: gesture.clone(),
+                phase: match held {
+                    true => XrGesturePhase::Held,
+                    false => XrGesturePhase::Ended,
+                },
+                confidence,
+            });
+            *active_confidence = confidence;
+            held
+        });
+        for (gesture, confidence) in confidences {
+            if confidence < settings.start_confidence || state.active.contains_key(&gesture) {
+                continue;
+            }
+            events.send(XrGestureEvent {
+                hand,
+                gesture: gesture.clone(),
+                phase: XrGesturePhase::Started,
+                confidence,
+            });
+            state.active.insert(gesture, confidence);
+        }
+        state.pose = pose;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::{Events, ManualEventReader};
+
+    use super::*;
+
+    const FINGER_BEND: f32 = 1.4;
+    const THUMB_BEND: f32 = 0.9;
+
+    /// A right hand with the fingers along -Z, each finger knuckle bent by `finger_bend` and each
+    /// thumb knuckle by `thumb_bend`.
+    fn hand_positions(finger_bend: f32, thumb_bend: f32) -> [Vec3; 26] {
+        let mut positions = [Vec3::ZERO; 26];
+        let mut set =
+            |bone: HandBone, position: Vec3| positions[bone.get_index_from_bone()] = position;
+        set(HandBone::Palm, Vec3::new(0.0, 0.0, -0.05));
+        set(HandBone::Wrist, Vec3::ZERO);
+        let fingers = [
+            (HandFinger::Index, 0.02),
+            (HandFinger::Middle, 0.0),
+            (HandFinger::Ring, -0.02),
+            (HandFinger::Little, -0.04),
+        ];
+        for (finger, x) in fingers {
+            let joints = finger.joints();
+            set(joints[1], Vec3::new(x, 0.0, -0.02));
+            let mut position = Vec3::new(x, 0.0, -0.08);
+            set(joints[2], position);
+            let mut direction = Vec3::NEG_Z;
+            for bone in &joints[3..] {
+                direction = Quat::from_rotation_x(-finger_bend) * direction;
+                position += direction * 0.03;
+                set(*bone, position);
+            }
+        }
+        set(HandBone::ThumbMetacarpal, Vec3::new(0.03, 0.0, -0.02));
+        let mut position = Vec3::new(0.05, 0.0, -0.04);
+        set(HandBone::ThumbProximal, position);
+        let mut direction = Vec3::new(1.0, 0.0, -1.0).normalize();
+        for bone in [HandBone::ThumbDistal, HandBone::ThumbTip] {
+            direction = Quat::from_rotation_y(thumb_bend) * direction;
+            position += direction * 0.03;
+            set(bone, position);
+        }
+        positions
+    }
+
+    fn flat_hand() -> [Vec3; 26] {
+        hand_positions(0.0, 0.0)
+    }
+
+    fn fist() -> [Vec3; 26] {
+        hand_positions(FINGER_BEND, THUMB_BEND)
+    }
+
+    /// A flat hand with the thumb tip `distance` away from the index tip.
+    fn pinch(distance: f32) -> [Vec3; 26] {
+        let mut positions = flat_hand();
+        positions[HandBone::ThumbTip.get_index_from_bone()] =
+            positions[HandBone::IndexTip.get_index_from_bone()] + Vec3::X * distance;
+        positions
+    }
+
+    /// The thumb to index distance at which a pinch has `confidence`.
+    fn pinch_distance(confidence: f32) -> f32 {
+        0.035 - confidence * 0.02
+    }
+
+    #[test]
+    fn bends_at_the_knuckles() {
+        let pose = XrHandPose::new(Hand::Right, fist(), true);
+        for bone in [
+            HandBone::IndexProximal,
+            HandBone::IndexIntermediate,
+            HandBone::IndexDistal,
+            HandBone::LittleDistal,
+        ] {
+            assert!((pose.bend(bone) - FINGER_BEND).abs() < 0.01, "{bone:?}");
+        }
+        assert!((pose.bend(HandBone::ThumbDistal) - THUMB_BEND).abs() < 0.01);
+        for bone in [
+            HandBone::Palm,
+            HandBone::Wrist,
+            HandBone::IndexTip,
+            HandBone::ThumbTip,
+        ] {
+            assert_eq!(pose.bend(bone), 0.0, "{bone:?}");
+        }
+    }
+
+    #[test]
+    fn curl() {
+        let flat = XrHandPose::new(Hand::Right, flat_hand(), true);
+        let fist = XrHandPose::new(Hand::Right, fist(), true);
+        for finger in HandFinger::get_all_fingers() {
+            assert!(flat.curl(finger) < 0.01, "{finger:?}");
+        }
+        let finger_curl = FINGER_BEND * 3.0 / (PI * 1.5);
+        assert!((fist.curl(HandFinger::Index) - finger_curl).abs() < 0.01);
+        assert!((fist.curl(HandFinger::Little) - finger_curl).abs() < 0.01);
+        let thumb_curl = THUMB_BEND * 2.0 / (PI * 0.75);
+        assert!((fist.curl(HandFinger::Thumb) - thumb_curl).abs() < 0.01);
+    }
+
+    #[test]
+    fn built_in_confidence() {
+        for tracked in [true, false] {
+            let flat = XrHandPose::new(Hand::Right, flat_hand(), tracked);
+            assert!(flat.confidence(&XrGesture::OpenPalm) > 0.99);
+            assert_eq!(flat.confidence(&XrGesture::Grab), 0.0);
+            assert_eq!(flat.confidence(&XrGesture::Pinch), 0.0);
+
+            let fist = XrHandPose::new(Hand::Right, fist(), tracked);
+            assert!(fist.confidence(&XrGesture::Grab) > 0.99);
+            assert_eq!(fist.confidence(&XrGesture::OpenPalm), 0.0);
+            assert_eq!(fist.confidence(&XrGesture::Point), 0.0);
+
+            let pinch = XrHandPose::new(Hand::Right, pinch(0.005), tracked);
+            assert!(pinch.confidence(&XrGesture::Pinch) > 0.99);
+            assert_eq!(pinch.confidence(&XrGesture::Grab), 0.0);
+            let half = XrHandPose::new(Hand::Right, pinch(pinch_distance(0.5)), tracked);
+            assert!((half.confidence(&XrGesture::Pinch) - 0.5).abs() < 0.01);
+
+            assert_eq!(flat.confidence(&XrGesture::Custom("fist".into())), 0.0);
+        }
+    }
+
+    #[test]
+    fn template_confidence() {
+        let flat = XrHandPose::new(Hand::Right, flat_hand(), true);
+        let fist = XrHandPose::new(Hand::Right, fist(), true);
+        let template = XrHandPoseTemplate::from_pose("fist", &fist);
+        assert!(template.confidence(&fist) > 0.99);
+        assert_eq!(template.confidence(&flat), 0.0);
+        // halfway to the tolerance is half confident
+        let template = XrHandPoseTemplate::new("bent index")
+            .with_bend(HandBone::IndexProximal, FINGER_BEND)
+            .with_tolerance(0.2);
+        let bent = XrHandPose::new(Hand::Right, hand_positions(FINGER_BEND - 0.1, 0.0), true);
+        assert!((template.confidence(&bent) - 0.5).abs() < 0.05);
+        assert_eq!(
+            template.clone().with_hand(Hand::Left).confidence(&fist),
+            0.0
+        );
+        assert!(template.with_hand(Hand::Right).confidence(&fist) > 0.99);
+        assert_eq!(XrHandPoseTemplate::new("empty").confidence(&fist), 0.0);
+    }
+
+    fn gesture_app(tracked: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins(XrGesturePlugin);
+        let status = match tracked {
+            true => BoneTrackingStatus::Tracked,
+            false => BoneTrackingStatus::Emulated,
+        };
+        for bone in HandBone::get_all_bones() {
+            app.world
+                .spawn((Transform::default(), Hand::Right, bone, status));
+        }
+        app
+    }
+
+    /// Moves the bones of the hand to `positions` and runs a frame.
+    fn update(app: &mut App, positions: [Vec3; 26]) {
+        let mut bones = app.world.query::<(&mut Transform, &HandBone)>();
+        for (mut transform, bone) in bones.iter_mut(&mut app.world) {
+            transform.translation = positions[bone.get_index_from_bone()];
+        }
+        app.update();
+    }
+
+    /// The phases of the pinch events sent since the last call.
+    fn pinch_phases(
+        app: &App,
+        reader: &mut ManualEventReader<XrGestureEvent>,
+    ) -> Vec<XrGesturePhase> {
+        reader
+            .read(app.world.resource::<Events<XrGestureEvent>>())
+            .filter(|event| event.hand == Hand::Right && event.gesture == XrGesture::Pinch)
+            .map(|event| event.phase)
+            .collect()
+    }
+
+    #[test]
+    fn start_and_end_hysteresis() {
+        for tracked in [true, false] {
+            let mut app = gesture_app(tracked);
+            let mut reader = app.world.resource::<Events<XrGestureEvent>>().get_reader();
+            let is_active = |app: &App| {
+                app.world
+                    .resource::<XrHandGestures>()
+                    .is_active(Hand::Right, &XrGesture::Pinch)
+            };
+
+            // between the end and start confidence, not started yet
+            update(&mut app, pinch(pinch_distance(0.7)));
+            assert!(!is_active(&app));
+            assert!(pinch_phases(&app, &mut reader).is_empty());
+            let gestures = app.world.resource::<XrHandGestures>();
+            assert_eq!(gestures.right.pose.as_ref().unwrap().tracked, tracked);
+            assert!(gestures.left.pose.is_none());
+
+            update(&mut app, pinch(pinch_distance(0.9)));
+            assert!(is_active(&app));
+            assert_eq!(pinch_phases(&app, &mut reader), [XrGesturePhase::Started]);
+
+            // back between the thresholds, still held
+            update(&mut app, pinch(pinch_distance(0.7)));
+            assert!(is_active(&app));
+            assert_eq!(pinch_phases(&app, &mut reader), [XrGesturePhase::Held]);
+            let confidence = app
+                .world
+                .resource::<XrHandGestures>()
+                .confidence(Hand::Right, &XrGesture::Pinch)
+                .unwrap();
+            assert!((confidence - 0.7).abs() < 0.01);
+
+            update(&mut app, pinch(pinch_distance(0.5)));
+            assert!(!is_active(&app));
+            assert_eq!(pinch_phases(&app, &mut reader), [XrGesturePhase::Ended]);
+
+            // needs the start confidence again
+            update(&mut app, pinch(pinch_distance(0.7)));
+            assert!(!is_active(&app));
+            assert!(pinch_phases(&app, &mut reader).is_empty());
+        }
+    }
+}
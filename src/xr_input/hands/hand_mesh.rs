@@ -0,0 +1,583 @@
+use std::f32::consts::TAU;
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use bevy::render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes};
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::view::NoFrustumCulling;
+use openxr as xr;
+use xr::sys;
+
+use super::hand_tracking::HandTrackingData;
+use super::HandBone;
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionTeardown};
+use crate::xr_input::trackers::OpenXRTracker;
+use crate::xr_input::{Hand, QuatConv, Vec3Conv};
+
+/// Where the mesh of a hand comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XrHandMeshSource {
+    /// The runtime's skinned mesh from `XR_FB_hand_tracking_mesh`.
+    Fb,
+    /// The runtime's mesh from `XR_MSFT_hand_tracking_mesh`, deformed by the runtime every frame
+    /// instead of skinned.
+    Msft,
+    /// A simple skinned hand built from tubes, for runtimes without a hand mesh.
+    Procedural,
+}
+
+/// A rendered hand spawned by [`XrHandMeshPlugin`]. Skinned meshes follow the [`HandBone`]
+/// entities of their [`Hand`], tracked or emulated. Swap the `Handle<StandardMaterial>` of the
+/// entity to change the look.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct XrHandMesh {
+    pub source: XrHandMeshSource,
+}
+
+/// Renders a mesh for each hand, from the runtime's hand mesh extensions when enabled and a
+/// procedural hand otherwise. Not part of the default plugins, add it next to them.
+pub struct XrHandMeshPlugin;
+
+impl Plugin for XrHandMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MsftHandMeshes>();
+        app.add_systems(Update, spawn_hand_meshes.run_if(xr_only()));
+        app.add_systems(XrSessionTeardown, teardown_msft_hand_meshes);
+        app.add_systems(
+            PreUpdate,
+            update_msft_hand_meshes
+                .run_if(resource_exists::<HandTrackingData>)
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+struct MsftHandMeshes(Vec<MsftHandMesh>);
+
+struct MsftHandMesh {
+    entity: Entity,
+    hand: Hand,
+    mesh: Handle<Mesh>,
+    // the tracker the space was created for, the trackers are recreated with the session
+    tracker: sys::HandTrackerEXT,
+    space: Option<xr::Space>,
+    index_buffer_key: u32,
+    vertices: Vec<sys::HandMeshVertexMSFT>,
+    indices: Vec<u32>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_hand_meshes(
+    mut commands: Commands,
+    instance: Res<XrInstance>,
+    hand_tracking: Option<Res<HandTrackingData>>,
+    mut msft_meshes: ResMut<MsftHandMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material: Local<Option<Handle<StandardMaterial>>>,
+    hand_meshes: Query<&Hand, With<XrHandMesh>>,
+    bones: Query<(Entity, &Hand, &HandBone)>,
+) {
+    for hand in [Hand::Left, Hand::Right] {
+        if hand_meshes.iter().any(|other| *other == hand) {
+            continue;
+        }
+        let mut joints = [Entity::PLACEHOLDER; 26];
+        for (entity, _, bone) in bones.iter().filter(|(_, other, _)| **other == hand) {
+            joints[bone.get_index_from_bone()] = entity;
+        }
+        if joints.contains(&Entity::PLACEHOLDER) {
+            continue;
+        }
+        let material = material
+            .get_or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: Color::rgb(0.8, 0.7, 0.65),
+                    perceptual_roughness: 0.7,
+                    ..default()
+                })
+            })
+            .clone();
+        let tracker = hand_tracking
+            .as_ref()
+            .map(|tracking| tracking.tracker(hand));
+        let fb_mesh = match tracker {
+            Some(tracker) if instance.exts().fb_hand_tracking_mesh.is_some() => {
+                fb_hand_mesh(&instance, tracker)
+                    .map_err(|err| warn!("Failed to get the {hand:?} hand mesh: {err}"))
+                    .ok()
+            }
+            _ => None,
+        };
+        if tracker.is_some() && fb_mesh.is_none() {
+            match msft_hand_mesh_capacity(&instance) {
+                Ok(Some((vertex_capacity, index_capacity))) => {
+                    let mesh = meshes.add(
+                        Mesh::new(
+                            PrimitiveTopology::TriangleList,
+                            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+                        )
+                        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
+                        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, Vec::<[f32; 3]>::new())
+                        .with_inserted_indices(Indices::U32(Vec::new())),
+                    );
+                    let entity = commands
+                        .spawn((
+                            PbrBundle {
+                                mesh: mesh.clone(),
+                                material,
+                                visibility: Visibility::Hidden,
+                                ..default()
+                            },
+                            XrHandMesh {
+                                source: XrHandMeshSource::Msft,
+                            },
+                            hand,
+                            OpenXRTracker,
+                        ))
+                        .id();
+                    msft_meshes.0.push(MsftHandMesh {
+                        entity,
+                        hand,
+                        mesh,
+                        tracker: sys::HandTrackerEXT::NULL,
+                        space: None,
+                        index_buffer_key: 0,
+                        vertices: vec![unsafe { mem::zeroed() }; vertex_capacity as usize],
+                        indices: vec![0; index_capacity as usize],
+                    });
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Failed to query hand mesh support: {err}"),
+            }
+        }
+        let (source, (mesh, inverse_bindposes)) = match fb_mesh {
+            Some(fb_mesh) => (XrHandMeshSource::Fb, fb_mesh),
+            None => (XrHandMeshSource::Procedural, procedural_hand_mesh(hand)),
+        };
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh),
+                material,
+                ..default()
+            },
+            SkinnedMesh {
+                inverse_bindposes: bindposes.add(inverse_bindposes),
+                joints: joints.to_vec(),
+            },
+            // the bounds of a skinned mesh are those of its bind pose
+            NoFrustumCulling,
+            XrHandMesh { source },
+            hand,
+        ));
+    }
+}
+
+/// The hand mesh spaces keep their session alive, they are created again for the new trackers.
+fn teardown_msft_hand_meshes(mut msft_meshes: ResMut<MsftHandMeshes>) {
+    for hand_mesh in &mut msft_meshes.0 {
+        hand_mesh.space = None;
+        hand_mesh.tracker = sys::HandTrackerEXT::NULL;
+        hand_mesh.index_buffer_key = 0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_msft_hand_meshes(
+    mut msft_meshes: ResMut<MsftHandMeshes>,
+    instance: Res<XrInstance>,
+    session: Res<XrSession>,
+    hand_tracking: Res<HandTrackingData>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(&mut Transform, &mut Visibility), With<XrHandMesh>>,
+) {
+    let Some(fp) = instance.exts().msft_hand_tracking_mesh else {
+        return;
+    };
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for hand_mesh in &mut msft_meshes.0 {
+        let tracker = hand_tracking.tracker(hand_mesh.hand).as_raw();
+        if hand_mesh.tracker != tracker {
+            hand_mesh.space = None;
+            hand_mesh.index_buffer_key = 0;
+            let info = sys::HandMeshSpaceCreateInfoMSFT {
+                ty: sys::HandMeshSpaceCreateInfoMSFT::TYPE,
+                next: ptr::null(),
+                hand_pose_type: sys::HandPoseTypeMSFT::TRACKED,
+                pose_in_hand_mesh_space: xr::Posef::IDENTITY,
+            };
+            let mut space = sys::Space::NULL;
+            let result = unsafe { (fp.create_hand_mesh_space)(tracker, &info, &mut space) };
+            if result.into_raw() < 0 {
+                warn!(
+                    "Failed to create the {:?} hand mesh space: {result}",
+                    hand_mesh.hand
+                );
+                continue;
+            }
+            hand_mesh.tracker = tracker;
+            hand_mesh.space =
+                Some(unsafe { xr::Space::reference_from_raw((**session).clone(), space) });
+        }
+        let Some(space) = hand_mesh.space.as_ref() else {
+            continue;
+        };
+        let Ok((mut transform, mut visibility)) = query.get_mut(hand_mesh.entity) else {
+            continue;
+        };
+        let info = sys::HandMeshUpdateInfoMSFT {
+            ty: sys::HandMeshUpdateInfoMSFT::TYPE,
+            next: ptr::null(),
+            time,
+            hand_pose_type: sys::HandPoseTypeMSFT::TRACKED,
+        };
+        let mut update = sys::HandMeshMSFT {
+            ty: sys::HandMeshMSFT::TYPE,
+            next: ptr::null_mut(),
+            is_active: false.into(),
+            index_buffer_changed: false.into(),
+            vertex_buffer_changed: false.into(),
+            index_buffer: sys::HandMeshIndexBufferMSFT {
+                index_buffer_key: hand_mesh.index_buffer_key,
+                index_capacity_input: hand_mesh.indices.len() as u32,
+                index_count_output: 0,
+                indices: hand_mesh.indices.as_mut_ptr(),
+            },
+            vertex_buffer: sys::HandMeshVertexBufferMSFT {
+                vertex_update_time: xr::Time::from_nanos(0),
+                vertex_capacity_input: hand_mesh.vertices.len() as u32,
+                vertex_count_output: 0,
+                vertices: hand_mesh.vertices.as_mut_ptr(),
+            },
+        };
+        let result = unsafe { (fp.update_hand_mesh)(tracker, &info, &mut update) };
+        if result.into_raw() < 0 {
+            warn!(
+                "Failed to update the {:?} hand mesh: {result}",
+                hand_mesh.hand
+            );
+            continue;
+        }
+        let pose = match space.locate(&input.stage, time) {
+            Ok(location)
+                if bool::from(update.is_active)
+                    && location.location_flags.contains(
+                        xr::SpaceLocationFlags::POSITION_VALID
+                            | xr::SpaceLocationFlags::ORIENTATION_VALID,
+                    ) =>
+            {
+                location.pose
+            }
+            Ok(_) => {
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+            Err(err) => {
+                warn!("Failed to locate the {:?} hand mesh: {err}", hand_mesh.hand);
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+        };
+        *transform = Transform::from_translation(pose.position.to_vec3())
+            .with_rotation(pose.orientation.to_quat());
+        *visibility = Visibility::Inherited;
+        let Some(mesh) = meshes.get_mut(&hand_mesh.mesh) else {
+            continue;
+        };
+        if update.index_buffer_changed.into() {
+            hand_mesh.index_buffer_key = update.index_buffer.index_buffer_key;
+            let count = update.index_buffer.index_count_output as usize;
+            mesh.insert_indices(Indices::U32(hand_mesh.indices[..count].to_vec()));
+        }
+        if update.vertex_buffer_changed.into() {
+            let vertices = &hand_mesh.vertices[..update.vertex_buffer.vertex_count_output as usize];
+            let positions: Vec<_> = vertices
+                .iter()
+                .map(|v| v.position.to_vec3().to_array())
+                .collect();
+            let normals: Vec<_> = vertices
+                .iter()
+                .map(|v| v.normal.to_vec3().to_array())
+                .collect();
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        }
+    }
+}
+
+/// The vertex and index capacity of the `XR_MSFT_hand_tracking_mesh` buffers, `None` when the
+/// extension is not enabled or the system has no hand mesh.
+fn msft_hand_mesh_capacity(instance: &XrInstance) -> xr::Result<Option<(u32, u32)>> {
+    if instance.exts().msft_hand_tracking_mesh.is_none() {
+        return Ok(None);
+    }
+    let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
+    let mut properties = sys::SystemHandTrackingMeshPropertiesMSFT {
+        ty: sys::SystemHandTrackingMeshPropertiesMSFT::TYPE,
+        next: ptr::null_mut(),
+        supports_hand_tracking_mesh: false.into(),
+        max_hand_mesh_index_count: 0,
+        max_hand_mesh_vertex_count: 0,
+    };
+    let mut system_properties: sys::SystemProperties = unsafe { mem::zeroed() };
+    system_properties.ty = sys::SystemProperties::TYPE;
+    system_properties.next = &mut properties as *mut _ as *mut _;
+    let result = unsafe {
+        (instance.fp().get_system_properties)(instance.as_raw(), system, &mut system_properties)
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    if !bool::from(properties.supports_hand_tracking_mesh) {
+        return Ok(None);
+    }
+    Ok(Some((
+        properties.max_hand_mesh_vertex_count,
+        properties.max_hand_mesh_index_count,
+    )))
+}
+
+/// Fetches the skinned hand mesh of the runtime along with its inverse bind poses.
+fn fb_hand_mesh(
+    instance: &XrInstance,
+    tracker: &xr::HandTracker,
+) -> xr::Result<(Mesh, SkinnedMeshInverseBindposes)> {
+    let fp = instance
+        .exts()
+        .fb_hand_tracking_mesh
+        .ok_or(sys::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+    let mut hand_mesh: sys::HandTrackingMeshFB = unsafe { mem::zeroed() };
+    hand_mesh.ty = sys::HandTrackingMeshFB::TYPE;
+    let result = unsafe { (fp.get_hand_mesh)(tracker.as_raw(), &mut hand_mesh) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let joint_count = hand_mesh.joint_count_output as usize;
+    let vertex_count = hand_mesh.vertex_count_output as usize;
+    let index_count = hand_mesh.index_count_output as usize;
+    let mut bind_poses = vec![xr::Posef::IDENTITY; joint_count];
+    let mut radii = vec![0.0; joint_count];
+    let mut parents = vec![sys::HandJointEXT::PALM; joint_count];
+    let mut positions = vec![unsafe { mem::zeroed::<sys::Vector3f>() }; vertex_count];
+    let mut normals = vec![unsafe { mem::zeroed::<sys::Vector3f>() }; vertex_count];
+    let mut uvs = vec![unsafe { mem::zeroed::<sys::Vector2f>() }; vertex_count];
+    let mut blend_indices = vec![unsafe { mem::zeroed::<sys::Vector4sFB>() }; vertex_count];
+    let mut blend_weights = vec![unsafe { mem::zeroed::<sys::Vector4f>() }; vertex_count];
+    let mut indices = vec![0i16; index_count];
+    hand_mesh.joint_capacity_input = joint_count as u32;
+    hand_mesh.joint_bind_poses = bind_poses.as_mut_ptr();
+    hand_mesh.joint_radii = radii.as_mut_ptr();
+    hand_mesh.joint_parents = parents.as_mut_ptr();
+    hand_mesh.vertex_capacity_input = vertex_count as u32;
+    hand_mesh.vertex_positions = positions.as_mut_ptr();
+    hand_mesh.vertex_normals = normals.as_mut_ptr();
+    hand_mesh.vertex_u_vs = uvs.as_mut_ptr();
+    hand_mesh.vertex_blend_indices = blend_indices.as_mut_ptr();
+    hand_mesh.vertex_blend_weights = blend_weights.as_mut_ptr();
+    hand_mesh.index_capacity_input = index_count as u32;
+    hand_mesh.indices = indices.as_mut_ptr();
+    let result = unsafe { (fp.get_hand_mesh)(tracker.as_raw(), &mut hand_mesh) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        positions
+            .iter()
+            .map(|v| [v.x, v.y, v.z])
+            .collect::<Vec<_>>(),
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        normals.iter().map(|v| [v.x, v.y, v.z]).collect::<Vec<_>>(),
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        uvs.iter().map(|v| [v.x, v.y]).collect::<Vec<_>>(),
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_JOINT_INDEX,
+        VertexAttributeValues::Uint16x4(
+            blend_indices
+                .iter()
+                .map(|v| [v.x, v.y, v.z, v.w].map(|joint| joint.max(0) as u16))
+                .collect(),
+        ),
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_JOINT_WEIGHT,
+        blend_weights
+            .iter()
+            .map(|v| [v.x, v.y, v.z, v.w])
+            .collect::<Vec<_>>(),
+    )
+    .with_inserted_indices(Indices::U16(
+        indices.iter().map(|index| *index as u16).collect(),
+    ));
+    let inverse_bindposes = bind_poses
+        .iter()
+        .map(|pose| {
+            Mat4::from_rotation_translation(pose.orientation.to_quat(), pose.position.to_vec3())
+                .inverse()
+        })
+        .collect::<Vec<_>>();
+    Ok((mesh, inverse_bindposes.into()))
+}
+
+const TUBE_SIDES: u32 = 8;
+
+/// The tubes of the procedural hand as start joint, end joint and radius.
+const PROCEDURAL_TUBES: [(HandBone, HandBone, f32); 20] = [
+    (HandBone::Wrist, HandBone::Palm, 0.022),
+    (HandBone::ThumbMetacarpal, HandBone::ThumbProximal, 0.013),
+    (HandBone::ThumbProximal, HandBone::ThumbDistal, 0.011),
+    (HandBone::ThumbDistal, HandBone::ThumbTip, 0.01),
+    (HandBone::IndexMetacarpal, HandBone::IndexProximal, 0.012),
+    (HandBone::IndexProximal, HandBone::IndexIntermediate, 0.01),
+    (HandBone::IndexIntermediate, HandBone::IndexDistal, 0.009),
+    (HandBone::IndexDistal, HandBone::IndexTip, 0.008),
+    (HandBone::MiddleMetacarpal, HandBone::MiddleProximal, 0.012),
+    (HandBone::MiddleProximal, HandBone::MiddleIntermediate, 0.01),
+    (HandBone::MiddleIntermediate, HandBone::MiddleDistal, 0.009),
+    (HandBone::MiddleDistal, HandBone::MiddleTip, 0.008),
+    (HandBone::RingMetacarpal, HandBone::RingProximal, 0.012),
+    (HandBone::RingProximal, HandBone::RingIntermediate, 0.0095),
+    (HandBone::RingIntermediate, HandBone::RingDistal, 0.0085),
+    (HandBone::RingDistal, HandBone::RingTip, 0.0075),
+    (HandBone::LittleMetacarpal, HandBone::LittleProximal, 0.011),
+    (
+        HandBone::LittleProximal,
+        HandBone::LittleIntermediate,
+        0.0085,
+    ),
+    (HandBone::LittleIntermediate, HandBone::LittleDistal, 0.0075),
+    (HandBone::LittleDistal, HandBone::LittleTip, 0.007),
+];
+
+/// The bind pose of the procedural left hand: flat, palm down, fingers along -Z, so the joint
+/// axes match the OpenXR hand joint conventions. The right hand mirrors it.
+fn procedural_bind_pose(hand: Hand) -> [Transform; 26] {
+    let mirror = match hand {
+        Hand::Left => 1.0,
+        Hand::Right => -1.0,
+    };
+    let thumb_direction = Vec3::new(0.5 * mirror, 0.0, -1.0).normalize();
+    let thumb_rotation = Quat::from_rotation_arc(Vec3::NEG_Z, thumb_direction);
+    let thumb_base = Vec3::new(0.02 * mirror, -0.015, -0.015);
+    let finger = |x: f32, lengths: [f32; 4]| {
+        let mut z = -0.02;
+        let mut joints = [Vec3::ZERO; 5];
+        joints[0] = Vec3::new(x * 0.5 * mirror, 0.0, z);
+        for (joint, length) in joints[1..].iter_mut().zip(lengths) {
+            z -= length;
+            *joint = Vec3::new(x * mirror, 0.0, z);
+        }
+        joints
+    };
+    let mut joints = [Transform::IDENTITY; 26];
+    joints[HandBone::Palm.get_index_from_bone()].translation = Vec3::new(0.0, 0.0, -0.05);
+    for (index, distance) in [0.0, 0.045, 0.08, 0.11].into_iter().enumerate() {
+        joints[HandBone::ThumbMetacarpal.get_index_from_bone() + index] =
+            Transform::from_translation(thumb_base + thumb_direction * distance)
+                .with_rotation(thumb_rotation);
+    }
+    for (metacarpal, x, lengths) in [
+        (HandBone::IndexMetacarpal, 0.024, [0.07, 0.04, 0.025, 0.02]),
+        (
+            HandBone::MiddleMetacarpal,
+            0.004,
+            [0.07, 0.045, 0.028, 0.022],
+        ),
+        (HandBone::RingMetacarpal, -0.015, [0.065, 0.04, 0.027, 0.02]),
+        (
+            HandBone::LittleMetacarpal,
+            -0.032,
+            [0.055, 0.03, 0.02, 0.018],
+        ),
+    ] {
+        for (index, joint) in finger(x, lengths).into_iter().enumerate() {
+            joints[metacarpal.get_index_from_bone() + index].translation = joint;
+        }
+    }
+    joints
+}
+
+/// Builds the procedural hand out of a tube per bone, skinned so each end of a tube follows its
+/// joint.
+fn procedural_hand_mesh(hand: Hand) -> (Mesh, SkinnedMeshInverseBindposes) {
+    let bind_pose = procedural_bind_pose(hand);
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut joint_indices = Vec::new();
+    let mut indices = Vec::new();
+    for (start, end, radius) in PROCEDURAL_TUBES {
+        let (start, end) = (start.get_index_from_bone(), end.get_index_from_bone());
+        let (a, b) = (bind_pose[start].translation, bind_pose[end].translation);
+        let axis = (b - a).normalize();
+        let u = axis.any_orthonormal_vector();
+        let v = axis.cross(u);
+        let base = positions.len() as u32;
+        for (joint, center) in [(start, a), (end, b)] {
+            for side in 0..TUBE_SIDES {
+                let angle = side as f32 * TAU / TUBE_SIDES as f32;
+                let normal = u * angle.cos() + v * angle.sin();
+                positions.push((center + normal * radius).to_array());
+                normals.push(normal.to_array());
+                joint_indices.push([joint as u16, 0, 0, 0]);
+            }
+        }
+        for side in 0..TUBE_SIDES {
+            let next = (side + 1) % TUBE_SIDES;
+            let (a0, a1) = (base + side, base + next);
+            let (b0, b1) = (a0 + TUBE_SIDES, a1 + TUBE_SIDES);
+            indices.extend([a0, a1, b0, a1, b1, b0]);
+        }
+        // the caps bulge a little past the joints to round off the tips
+        let start_cap = positions.len() as u32;
+        positions.push((a - axis * radius * 0.5).to_array());
+        normals.push((-axis).to_array());
+        joint_indices.push([start as u16, 0, 0, 0]);
+        let end_cap = start_cap + 1;
+        positions.push((b + axis * radius * 0.5).to_array());
+        normals.push(axis.to_array());
+        joint_indices.push([end as u16, 0, 0, 0]);
+        for side in 0..TUBE_SIDES {
+            let next = (side + 1) % TUBE_SIDES;
+            indices.extend([start_cap, base + next, base + side]);
+            let ring = base + TUBE_SIDES;
+            indices.extend([end_cap, ring + side, ring + next]);
+        }
+    }
+    let weights = vec![[1.0f32, 0.0, 0.0, 0.0]; positions.len()];
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_JOINT_INDEX,
+        VertexAttributeValues::Uint16x4(joint_indices),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, weights)
+    .with_inserted_indices(Indices::U32(indices));
+    let inverse_bindposes = bind_pose
+        .iter()
+        .map(|joint| joint.compute_matrix().inverse())
+        .collect::<Vec<_>>();
+    (mesh, inverse_bindposes.into())
+}
@@ -1,12 +1,23 @@
+use std::ffi::c_void;
+use std::{mem, ptr};
+
 use bevy::prelude::*;
-use openxr::{HandTracker, Result, SpaceLocationFlags};
+use openxr::sys::HandTrackingAimFlagsFB;
+use openxr::{sys, HandTracker, Result, SpaceLocationFlags};
 
+use super::capsules::{HandCapsule, FB_CAPSULE_COUNT};
 use super::common::HandBoneRadius;
 use crate::{
     input::XrInput,
-    resources::{XrFrameState, XrSession},
-    xr_init::xr_only,
-    xr_input::{hands::HandBone, trackers::OpenXRTrackingRoot, Hand, QuatConv, Vec3Conv},
+    resources::{XrFrameState, XrInstance, XrSession},
+    xr_begin_frame,
+    xr_init::{xr_only, XrSessionSetup},
+    xr_input::{
+        hands::HandBone,
+        interactions::{XRInteractorState, XRRayInteractor},
+        trackers::{AimPose, OpenXRTracker, OpenXRTrackingRoot},
+        Hand, QuatConv, Vec3Conv,
+    },
 };
 
 use super::BoneTrackingStatus;
@@ -23,17 +34,30 @@ pub struct HandTrackingPlugin;
 pub struct HandTrackingData {
     left_hand: HandTracker,
     right_hand: HandTracker,
+    fp: Option<openxr::raw::HandTrackingEXT>,
+    aim: bool,
+    capsules: bool,
 }
 
 impl HandTrackingData {
     pub fn new(session: &XrSession) -> Result<HandTrackingData> {
         let left = session.create_hand_tracker(openxr::HandEXT::LEFT)?;
         let right = session.create_hand_tracker(openxr::HandEXT::RIGHT)?;
+        let exts = session.instance().exts();
         Ok(HandTrackingData {
             left_hand: left,
             right_hand: right,
+            fp: exts.ext_hand_tracking,
+            aim: exts.fb_hand_tracking_aim.is_some(),
+            capsules: exts.fb_hand_tracking_capsules.is_some(),
         })
     }
+    pub fn tracker(&self, hand: Hand) -> &HandTracker {
+        match hand {
+            Hand::Left => &self.left_hand,
+            Hand::Right => &self.right_hand,
+        }
+    }
     pub fn get_ref<'a>(
         &'a self,
         input: &'a XrInput,
@@ -78,7 +102,134 @@ impl HandJoints {
     }
 }
 
+/// The aim of a tracked hand through `XR_FB_hand_tracking_aim`. The entity is placed in the
+/// tracking root with its [`AimPose`] and is a [`XRRayInteractor`] that selects while the index
+/// finger pinches.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct XrHandAim {
+    /// The runtime computed the aim this frame, the other fields keep their last values otherwise.
+    pub valid: bool,
+    pub status: HandTrackingAimFlagsFB,
+    /// How far the finger pinches the thumb, between 0 and 1.
+    pub pinch_strength_index: f32,
+    pub pinch_strength_middle: f32,
+    pub pinch_strength_ring: f32,
+    pub pinch_strength_little: f32,
+}
+
+impl Default for XrHandAim {
+    fn default() -> Self {
+        Self {
+            valid: false,
+            status: HandTrackingAimFlagsFB::EMPTY,
+            pinch_strength_index: 0.0,
+            pinch_strength_middle: 0.0,
+            pinch_strength_ring: 0.0,
+            pinch_strength_little: 0.0,
+        }
+    }
+}
+
+impl XrHandAim {
+    pub fn index_pinching(&self) -> bool {
+        self.status.contains(HandTrackingAimFlagsFB::INDEX_PINCHING)
+    }
+    /// The user does the system gesture, the hand should not interact meanwhile.
+    pub fn system_gesture(&self) -> bool {
+        self.status.contains(HandTrackingAimFlagsFB::SYSTEM_GESTURE)
+    }
+    pub fn menu_pressed(&self) -> bool {
+        self.status.contains(HandTrackingAimFlagsFB::MENU_PRESSED)
+    }
+    pub fn dominant_hand(&self) -> bool {
+        self.status.contains(HandTrackingAimFlagsFB::DOMINANT_HAND)
+    }
+}
+
 impl<'a> HandTrackingRef<'a> {
+    /// The aim of the hand and its stage-relative pose, `None` when `XR_FB_hand_tracking_aim` is
+    /// not enabled or the hand is not tracked.
+    pub fn get_aim(&self, side: Hand) -> Option<(XrHandAim, Transform)> {
+        if !self.tracking.aim {
+            return None;
+        }
+        let mut aim_state = sys::HandTrackingAimStateFB {
+            ty: sys::HandTrackingAimStateFB::TYPE,
+            next: ptr::null_mut(),
+            status: HandTrackingAimFlagsFB::EMPTY,
+            aim_pose: openxr::Posef::IDENTITY,
+            pinch_strength_index: 0.0,
+            pinch_strength_middle: 0.0,
+            pinch_strength_ring: 0.0,
+            pinch_strength_little: 0.0,
+        };
+        let active = self.locate_chained(side, &mut aim_state as *mut _ as *mut _, "aim");
+        if !active || !aim_state.status.contains(HandTrackingAimFlagsFB::VALID) {
+            return None;
+        }
+        let aim = XrHandAim {
+            valid: true,
+            status: aim_state.status,
+            pinch_strength_index: aim_state.pinch_strength_index,
+            pinch_strength_middle: aim_state.pinch_strength_middle,
+            pinch_strength_ring: aim_state.pinch_strength_ring,
+            pinch_strength_little: aim_state.pinch_strength_little,
+        };
+        let pose = Transform::from_translation(aim_state.aim_pose.position.to_vec3())
+            .with_rotation(aim_state.aim_pose.orientation.to_quat());
+        Some((aim, pose))
+    }
+    /// The collision capsules of the hand in the stage, `None` when `XR_FB_hand_tracking_capsules`
+    /// is not enabled or the hand is not tracked.
+    pub fn get_capsules(&self, side: Hand) -> Option<[HandCapsule; FB_CAPSULE_COUNT]> {
+        if !self.tracking.capsules {
+            return None;
+        }
+        let mut capsules_state: sys::HandTrackingCapsulesStateFB = unsafe { mem::zeroed() };
+        capsules_state.ty = sys::HandTrackingCapsulesStateFB::TYPE;
+        if !self.locate_chained(side, &mut capsules_state as *mut _ as *mut _, "capsules") {
+            return None;
+        }
+        let bones = HandBone::get_all_bones();
+        Some(capsules_state.capsules.map(|capsule| HandCapsule {
+            bone: bones[capsule.joint.into_raw() as usize],
+            start: capsule.points[0].to_vec3(),
+            end: capsule.points[1].to_vec3(),
+            radius: capsule.radius,
+        }))
+    }
+    /// Locates the joints of the hand with `next` chained to the locations, returns whether the
+    /// hand is tracked.
+    fn locate_chained(&self, side: Hand, next: *mut c_void, what: &str) -> bool {
+        let Some(fp) = self.tracking.fp else {
+            return false;
+        };
+        let mut joints = [sys::HandJointLocationEXT {
+            location_flags: SpaceLocationFlags::EMPTY,
+            pose: openxr::Posef::IDENTITY,
+            radius: 0.0,
+        }; 26];
+        let mut locations = sys::HandJointLocationsEXT {
+            ty: sys::HandJointLocationsEXT::TYPE,
+            next,
+            is_active: false.into(),
+            joint_count: joints.len() as u32,
+            joint_locations: joints.as_mut_ptr(),
+        };
+        let info = sys::HandJointsLocateInfoEXT {
+            ty: sys::HandJointsLocateInfoEXT::TYPE,
+            next: ptr::null(),
+            base_space: self.input.stage.as_raw(),
+            time: self.frame_state.lock().unwrap().predicted_display_time,
+        };
+        let tracker = self.tracking.tracker(side);
+        let result = unsafe { (fp.locate_hand_joints)(tracker.as_raw(), &info, &mut locations) };
+        if result.into_raw() < 0 {
+            warn!("Failed to locate the {side:?} hand {what}: {result}");
+            return false;
+        }
+        locations.is_active.into()
+    }
     pub fn get_poses(&self, side: Hand) -> Option<HandJoints> {
         self.input
             .stage
@@ -131,6 +282,81 @@ impl Plugin for HandTrackingPlugin {
                 update_tracking_state_on_disable,
             ),
         );
+        app.add_systems(XrSessionSetup, spawn_hand_aims);
+        app.add_systems(
+            PreUpdate,
+            update_hand_aims
+                .run_if(resource_exists::<HandTrackingData>)
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+    }
+}
+
+fn spawn_hand_aims(
+    mut commands: Commands,
+    instance: Res<XrInstance>,
+    query: Query<(), With<XrHandAim>>,
+) {
+    if instance.exts().fb_hand_tracking_aim.is_none() || !query.is_empty() {
+        return;
+    }
+    for hand in [Hand::Left, Hand::Right] {
+        commands.spawn((
+            XrHandAim::default(),
+            hand,
+            AimPose(Transform::IDENTITY),
+            XRRayInteractor,
+            XRInteractorState::default(),
+            SpatialBundle::default(),
+            OpenXRTracker,
+        ));
+    }
+}
+
+fn update_hand_aims(
+    disabled_tracking: Option<Res<DisableHandTracking>>,
+    hand_tracking: Res<HandTrackingData>,
+    xr_input: Res<XrInput>,
+    xr_frame_state: Res<XrFrameState>,
+    mut query: Query<(
+        &mut XrHandAim,
+        &Hand,
+        &mut Transform,
+        Option<&mut AimPose>,
+        Option<&mut XRInteractorState>,
+    )>,
+) {
+    let hand_ref = hand_tracking.get_ref(&xr_input, &xr_frame_state);
+    for (mut aim, hand, mut transform, aim_pose, interactor_state) in &mut query {
+        let disabled = match (hand, disabled_tracking.as_deref()) {
+            (_, Some(DisableHandTracking::Both)) => true,
+            (Hand::Left, Some(DisableHandTracking::OnlyLeft)) => true,
+            (Hand::Right, Some(DisableHandTracking::OnlyRight)) => true,
+            _ => false,
+        };
+        let located = match disabled {
+            true => None,
+            false => hand_ref.get_aim(*hand),
+        };
+        let Some((new_aim, pose)) = located else {
+            aim.valid = false;
+            if let Some(mut state) = interactor_state {
+                *state = XRInteractorState::Idle;
+            }
+            continue;
+        };
+        *aim = new_aim;
+        *transform = pose;
+        if let Some(mut aim_pose) = aim_pose {
+            aim_pose.0 = pose;
+        }
+        if let Some(mut state) = interactor_state {
+            *state = match aim.index_pinching() && !aim.system_gesture() {
+                true => XRInteractorState::Selecting,
+                false => XRInteractorState::Idle,
+            };
+        }
     }
 }
 
@@ -1,9 +1,14 @@
 use bevy::{app::PluginGroupBuilder, prelude::*};
 
-use self::{emulated::HandEmulationPlugin, hand_tracking::HandTrackingPlugin};
+use self::{
+    emulated::HandEmulationPlugin, gestures::XrGesturePlugin, hand_tracking::HandTrackingPlugin,
+};
 
+pub mod capsules;
 pub mod common;
 pub mod emulated;
+pub mod gestures;
+pub mod hand_mesh;
 pub mod hand_tracking;
 
 pub struct XrHandPlugins;
@@ -13,6 +18,7 @@ impl PluginGroup for XrHandPlugins {
         PluginGroupBuilder::start::<Self>()
             .add(HandTrackingPlugin)
             .add(HandEmulationPlugin)
+            .add(XrGesturePlugin)
             .build()
     }
 }
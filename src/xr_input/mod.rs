@@ -1,20 +1,27 @@
 pub mod actions;
+pub mod body_tracking;
 pub mod controllers;
 pub mod debug_gizmos;
+pub mod eye_gaze;
+pub mod face_tracking;
 pub mod hand_poses;
 pub mod hands;
 pub mod interactions;
 pub mod oculus_touch;
 pub mod prototype_locomotion;
 pub mod trackers;
+pub mod vive_trackers;
 pub mod xr_camera;
 
-use crate::resources::{XrInstance, XrSession};
+use crate::resources::{XrInstance, XrSession, XrSwapchain, XrViewConfigurationType};
 use crate::xr_begin_frame;
-use crate::xr_init::{xr_only, XrPostSetup, XrSetup, XrPreSetup};
+use crate::xr_init::{xr_only, XrPreSetup, XrSessionSetup, XrSessionTeardown, XrSetup};
 use crate::xr_input::controllers::XrControllerType;
 use crate::xr_input::oculus_touch::setup_oculus_controller;
-use crate::xr_input::xr_camera::{xr_camera_head_sync, Eye, XRProjection, XrCameraBundle};
+use crate::xr_input::xr_camera::{
+    xr_camera_head_sync, xr_camera_should_render, xr_secondary_camera_sync, Eye, XRProjection,
+    XrCameraBundle,
+};
 use bevy::app::{App, PostUpdate, Startup};
 use bevy::ecs::entity::Entity;
 use bevy::ecs::query::With;
@@ -30,7 +37,9 @@ use bevy::utils::HashMap;
 use openxr::Binding;
 
 use self::actions::{setup_oxr_actions, OpenXrActionsPlugin};
-use self::oculus_touch::{post_action_setup_oculus_controller, ActionSets, init_subaction_path};
+use self::oculus_touch::{
+    init_subaction_path, post_action_setup_oculus_controller, teardown_oculus_controller, ActionSets,
+};
 use self::trackers::{
     adopt_open_xr_trackers, update_open_xr_controllers, OpenXRLeftEye, OpenXRRightEye,
     OpenXRTrackingRoot,
@@ -56,7 +65,8 @@ impl Plugin for OpenXrInput {
     fn build(&self, app: &mut App) {
         app.add_plugins(CameraProjectionPlugin::<XRProjection>::default());
         app.add_plugins(OpenXrActionsPlugin);
-        app.add_systems(XrPostSetup, post_action_setup_oculus_controller);
+        app.add_systems(XrSessionSetup, post_action_setup_oculus_controller);
+        app.add_systems(XrSessionTeardown, teardown_oculus_controller);
         match self.controller_type {
             XrControllerType::OculusTouch => {
                 app.add_systems(XrSetup, setup_oculus_controller);
@@ -69,6 +79,18 @@ impl Plugin for OpenXrInput {
             PreUpdate,
             xr_camera_head_sync.run_if(xr_only()).after(xr_begin_frame),
         );
+        app.add_systems(
+            PreUpdate,
+            xr_camera_should_render
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
+        app.add_systems(
+            PreUpdate,
+            xr_secondary_camera_sync
+                .run_if(xr_only())
+                .after(xr_begin_frame),
+        );
         //update controller trackers
         app.add_systems(Update, update_open_xr_controllers.run_if(xr_only()));
         app.add_systems(
@@ -96,6 +118,8 @@ fn setup_binding_recommendations(
 fn setup_xr_cameras(
     mut commands: Commands,
     tracking_root_query: Query<Entity, With<OpenXRTrackingRoot>>,
+    view_type: Res<XrViewConfigurationType>,
+    swapchain: Res<XrSwapchain>,
 ) {
     //this needs to do the whole xr tracking volume not just cameras
     //get the root?
@@ -106,13 +130,24 @@ fn setup_xr_cameras(
             .spawn((SpatialBundle::default(), OpenXRTrackingRoot))
             .id(),
     };
-    let right = commands
-        .spawn((XrCameraBundle::new(Eye::Right), OpenXRRightEye))
-        .id();
-    let left = commands
-        .spawn((XrCameraBundle::new(Eye::Left), OpenXRLeftEye))
-        .id();
-    commands.entity(tracking_root).push_children(&[right, left]);
+    let mut cameras = Vec::new();
+    for eye in Eye::from_view_count(view_type.view_count()) {
+        let mut camera = commands.spawn(XrCameraBundle::new(*eye));
+        match eye {
+            Eye::Left => {
+                camera.insert(OpenXRLeftEye);
+            }
+            Eye::Right => {
+                camera.insert(OpenXRRightEye);
+            }
+            Eye::LeftFocus | Eye::RightFocus => {}
+        }
+        cameras.push(camera.id());
+    }
+    if swapchain.secondary_view_info().is_some() {
+        cameras.push(commands.spawn(XrCameraBundle::secondary()).id());
+    }
+    commands.entity(tracking_root).push_children(&cameras);
 }
 
 pub fn action_set_system(action_sets: Res<ActionSets>, session: Res<XrSession>) {
@@ -44,6 +44,14 @@ pub fn post_action_setup_oculus_controller(
             .unwrap(),
     })
 }
+/// Drops the controller spaces of a lost session, [`post_action_setup_oculus_controller`]
+/// creates them again for the new one.
+pub fn teardown_oculus_controller(controller: Option<ResMut<OculusController>>) {
+    if let Some(mut controller) = controller {
+        controller.grip_space = None;
+        controller.aim_space = None;
+    }
+}
 pub fn setup_oculus_controller(
     mut commands: Commands,
     instance: Res<XrInstance>,
@@ -0,0 +1,435 @@
+use std::ffi::c_void;
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use openxr as xr;
+use xr::sys;
+
+use crate::graphics::extensions::load_function;
+use crate::input::XrInput;
+use crate::resources::{XrFrameState, XrInstance, XrSession};
+use crate::xr_begin_frame;
+use crate::xr_init::{xr_only, XrSessionSetup, XrSessionTeardown, XrSetup};
+
+use super::actions::{ActionHandednes, ActionType, SetupActionSets, XrActionSets, XrBinding};
+use super::trackers::OpenXRTracker;
+use super::{QuatConv, Vec3Conv};
+
+pub(crate) const HTCX_VIVE_TRACKER_EXTENSION: &str = "XR_HTCX_vive_tracker_interaction";
+
+const TRACKER_ACTION_SET: &str = "trackers";
+const VIVE_TRACKER_POSE_ACTION: &str = "vive_tracker_pose";
+const WRIST_TRACKER_POSE_ACTION: &str = "wrist_tracker_pose";
+
+const VIVE_TRACKER_PATHS: [&str; 13] = [
+    "/user/vive_tracker_htcx/role/handheld_object",
+    "/user/vive_tracker_htcx/role/left_foot",
+    "/user/vive_tracker_htcx/role/right_foot",
+    "/user/vive_tracker_htcx/role/left_shoulder",
+    "/user/vive_tracker_htcx/role/right_shoulder",
+    "/user/vive_tracker_htcx/role/left_elbow",
+    "/user/vive_tracker_htcx/role/right_elbow",
+    "/user/vive_tracker_htcx/role/left_knee",
+    "/user/vive_tracker_htcx/role/right_knee",
+    "/user/vive_tracker_htcx/role/waist",
+    "/user/vive_tracker_htcx/role/chest",
+    "/user/vive_tracker_htcx/role/camera",
+    "/user/vive_tracker_htcx/role/keyboard",
+];
+const VIVE_TRACKER_POSE_BINDINGS: [&str; 13] = [
+    "/user/vive_tracker_htcx/role/handheld_object/input/grip/pose",
+    "/user/vive_tracker_htcx/role/left_foot/input/grip/pose",
+    "/user/vive_tracker_htcx/role/right_foot/input/grip/pose",
+    "/user/vive_tracker_htcx/role/left_shoulder/input/grip/pose",
+    "/user/vive_tracker_htcx/role/right_shoulder/input/grip/pose",
+    "/user/vive_tracker_htcx/role/left_elbow/input/grip/pose",
+    "/user/vive_tracker_htcx/role/right_elbow/input/grip/pose",
+    "/user/vive_tracker_htcx/role/left_knee/input/grip/pose",
+    "/user/vive_tracker_htcx/role/right_knee/input/grip/pose",
+    "/user/vive_tracker_htcx/role/waist/input/grip/pose",
+    "/user/vive_tracker_htcx/role/chest/input/grip/pose",
+    "/user/vive_tracker_htcx/role/camera/input/grip/pose",
+    "/user/vive_tracker_htcx/role/keyboard/input/grip/pose",
+];
+const WRIST_TRACKER_PATHS: [&str; 2] = ["/user/wrist_htc/left", "/user/wrist_htc/right"];
+const WRIST_TRACKER_POSE_BINDINGS: [&str; 2] = [
+    "/user/wrist_htc/left/input/entity_htc/pose",
+    "/user/wrist_htc/right/input/entity_htc/pose",
+];
+
+/// What a tracker is attached to. Vive trackers get their role assigned in the runtime's
+/// settings, wrist trackers are always on a wrist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XrTrackerRole {
+    HandheldObject,
+    LeftFoot,
+    RightFoot,
+    LeftShoulder,
+    RightShoulder,
+    LeftElbow,
+    RightElbow,
+    LeftKnee,
+    RightKnee,
+    Waist,
+    Chest,
+    Camera,
+    Keyboard,
+    LeftWrist,
+    RightWrist,
+}
+
+impl XrTrackerRole {
+    pub const fn get_all_roles() -> [XrTrackerRole; 15] {
+        [
+            XrTrackerRole::HandheldObject,
+            XrTrackerRole::LeftFoot,
+            XrTrackerRole::RightFoot,
+            XrTrackerRole::LeftShoulder,
+            XrTrackerRole::RightShoulder,
+            XrTrackerRole::LeftElbow,
+            XrTrackerRole::RightElbow,
+            XrTrackerRole::LeftKnee,
+            XrTrackerRole::RightKnee,
+            XrTrackerRole::Waist,
+            XrTrackerRole::Chest,
+            XrTrackerRole::Camera,
+            XrTrackerRole::Keyboard,
+            XrTrackerRole::LeftWrist,
+            XrTrackerRole::RightWrist,
+        ]
+    }
+    /// The top level user path of the role.
+    pub fn path(&self) -> &'static str {
+        let index = *self as usize;
+        match VIVE_TRACKER_PATHS.get(index) {
+            Some(path) => path,
+            None => WRIST_TRACKER_PATHS[index - VIVE_TRACKER_PATHS.len()],
+        }
+    }
+    pub fn is_wrist(&self) -> bool {
+        matches!(self, XrTrackerRole::LeftWrist | XrTrackerRole::RightWrist)
+    }
+}
+
+/// A connected tracker, placed in the tracking root. The entity is spawned when the tracker
+/// connects and despawned when it disconnects.
+#[derive(Component, Clone, Debug)]
+pub struct XrTracker {
+    pub role: XrTrackerRole,
+    /// The runtime's path of the device itself, stable across sessions. `None` for wrist trackers.
+    pub persistent_path: Option<String>,
+    /// The runtime located the tracker this frame, the transform keeps the last pose otherwise.
+    pub tracked: bool,
+}
+
+#[derive(Event, Clone, Debug)]
+pub enum XrTrackerEvent {
+    Connected {
+        entity: Entity,
+        role: XrTrackerRole,
+    },
+    /// The entity is despawned along with this event.
+    Disconnected {
+        entity: Entity,
+        role: XrTrackerRole,
+    },
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ViveTrackerPathsHTCX {
+    ty: sys::StructureType,
+    next: *mut c_void,
+    persistent_path: sys::Path,
+    role_path: sys::Path,
+}
+
+const TYPE_VIVE_TRACKER_PATHS_HTCX: sys::StructureType = sys::StructureType::from_raw(1000103000);
+
+type EnumerateViveTrackerPathsHTCX = unsafe extern "system" fn(
+    sys::Instance,
+    u32,
+    *mut u32,
+    *mut ViveTrackerPathsHTCX,
+) -> sys::Result;
+
+fn load_enumerate_vive_tracker_paths(
+    instance: &xr::Instance,
+) -> xr::Result<EnumerateViveTrackerPathsHTCX> {
+    unsafe {
+        load_function(instance, b"xrEnumerateViveTrackerPathsHTCX\0")
+            .map(|function| mem::transmute(function))
+    }
+}
+
+struct TrackerSpace {
+    role: XrTrackerRole,
+    subaction_path: xr::Path,
+    space: xr::Space,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct TrackerSpaces {
+    spaces: Vec<TrackerSpace>,
+    enumerate_vive_tracker_paths: Option<EnumerateViveTrackerPathsHTCX>,
+    persistent_paths: HashMap<sys::Path, String>,
+}
+
+/// Tracks Vive trackers through `XR_HTCX_vive_tracker_interaction` and wrist trackers through
+/// `XR_HTC_vive_wrist_tracker_interaction`, spawning an [`XrTracker`] per connected tracker.
+/// Vive trackers without an assigned role can't be tracked.
+pub struct XrTrackerPlugin;
+
+impl Plugin for XrTrackerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<XrTrackerEvent>();
+        app.init_resource::<TrackerSpaces>();
+        app.add_systems(XrSetup, setup_tracker_actions);
+        app.add_systems(XrSessionSetup, setup_tracker_spaces);
+        app.add_systems(XrSessionTeardown, teardown_tracker_spaces);
+        app.add_systems(
+            PreUpdate,
+            update_trackers.run_if(xr_only()).after(xr_begin_frame),
+        );
+    }
+}
+
+fn setup_tracker_actions(instance: Res<XrInstance>, mut action_sets: ResMut<SetupActionSets>) {
+    let vive_trackers = load_enumerate_vive_tracker_paths(&instance).is_ok();
+    let wrist_trackers = instance.exts().htc_vive_wrist_tracker_interaction.is_some();
+    if !vive_trackers && !wrist_trackers {
+        return;
+    }
+    let action_set = action_sets.add_action_set(TRACKER_ACTION_SET, "Trackers".into(), 0);
+    if vive_trackers {
+        action_set.new_action(
+            VIVE_TRACKER_POSE_ACTION,
+            "Vive Tracker Pose".into(),
+            ActionType::PoseF,
+            ActionHandednes::Custom(&VIVE_TRACKER_PATHS),
+        );
+        let bindings =
+            VIVE_TRACKER_POSE_BINDINGS.map(|path| XrBinding::new(VIVE_TRACKER_POSE_ACTION, path));
+        action_set.suggest_binding("/interaction_profiles/htc/vive_tracker_htcx", &bindings);
+    }
+    if wrist_trackers {
+        action_set.new_action(
+            WRIST_TRACKER_POSE_ACTION,
+            "Wrist Tracker Pose".into(),
+            ActionType::PoseF,
+            ActionHandednes::Custom(&WRIST_TRACKER_PATHS),
+        );
+        let bindings =
+            WRIST_TRACKER_POSE_BINDINGS.map(|path| XrBinding::new(WRIST_TRACKER_POSE_ACTION, path));
+        action_set.suggest_binding("/interaction_profiles/htc/vive_wrist_tracker", &bindings);
+    }
+}
+
+fn setup_tracker_spaces(
+    mut tracker_spaces: ResMut<TrackerSpaces>,
+    instance: Res<XrInstance>,
+    action_sets: Res<XrActionSets>,
+    session: Res<XrSession>,
+) {
+    *tracker_spaces = TrackerSpaces {
+        enumerate_vive_tracker_paths: load_enumerate_vive_tracker_paths(&instance).ok(),
+        ..default()
+    };
+    for role in XrTrackerRole::get_all_roles() {
+        let action_name = match role.is_wrist() {
+            true => WRIST_TRACKER_POSE_ACTION,
+            false => VIVE_TRACKER_POSE_ACTION,
+        };
+        let Ok(action) = action_sets.get_action_posef(TRACKER_ACTION_SET, action_name) else {
+            continue;
+        };
+        let subaction_path = instance.string_to_path(role.path()).unwrap();
+        match action.create_space((**session).clone(), subaction_path, xr::Posef::IDENTITY) {
+            Ok(space) => tracker_spaces.spaces.push(TrackerSpace {
+                role,
+                subaction_path,
+                space,
+            }),
+            Err(err) => warn!("Failed to create the {role:?} tracker space: {err}"),
+        }
+    }
+}
+
+/// The tracker spaces keep their session alive, so they are dropped with a lost session.
+fn teardown_tracker_spaces(mut tracker_spaces: ResMut<TrackerSpaces>) {
+    tracker_spaces.spaces.clear();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_trackers(
+    mut commands: Commands,
+    mut tracker_spaces: ResMut<TrackerSpaces>,
+    instance: Res<XrInstance>,
+    action_sets: Res<XrActionSets>,
+    session: Res<XrSession>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut events: EventWriter<XrTrackerEvent>,
+    mut query: Query<(Entity, &mut XrTracker, &mut Transform)>,
+) {
+    if tracker_spaces.spaces.is_empty() {
+        return;
+    }
+    let vive_trackers = match tracker_spaces.enumerate_vive_tracker_paths {
+        Some(enumerate) => match vive_tracker_paths(&instance, enumerate) {
+            Ok(paths) => paths,
+            Err(err) => {
+                warn!("Failed to enumerate the Vive trackers: {err}");
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    let tracker_spaces = &mut *tracker_spaces;
+    let mut connected = HashMap::new();
+    for tracker in &tracker_spaces.spaces {
+        let persistent_path = if tracker.role.is_wrist() {
+            let active = action_sets
+                .get_action_posef(TRACKER_ACTION_SET, WRIST_TRACKER_POSE_ACTION)
+                .map_or(false, |action| {
+                    action
+                        .is_active(&session, tracker.subaction_path)
+                        .unwrap_or(false)
+                });
+            if !active {
+                continue;
+            }
+            None
+        } else {
+            let Some(paths) = vive_trackers
+                .iter()
+                .find(|paths| paths.role_path == tracker.subaction_path)
+            else {
+                continue;
+            };
+            let persistent_path = tracker_spaces
+                .persistent_paths
+                .entry(paths.persistent_path)
+                .or_insert_with(|| {
+                    instance
+                        .path_to_string(paths.persistent_path)
+                        .unwrap_or_default()
+                });
+            Some(persistent_path.clone())
+        };
+        let pose = match tracker.space.locate(&input.stage, time) {
+            Ok(location)
+                if location.location_flags.contains(
+                    xr::SpaceLocationFlags::POSITION_VALID
+                        | xr::SpaceLocationFlags::ORIENTATION_VALID,
+                ) =>
+            {
+                Some(
+                    Transform::from_translation(location.pose.position.to_vec3())
+                        .with_rotation(location.pose.orientation.to_quat()),
+                )
+            }
+            Ok(_) => None,
+            Err(err) => {
+                warn!("Failed to locate the {:?} tracker: {err}", tracker.role);
+                None
+            }
+        };
+        connected.insert(tracker.role, (persistent_path, pose));
+    }
+    for (entity, mut tracker, mut transform) in &mut query {
+        let Some((persistent_path, pose)) = connected.remove(&tracker.role) else {
+            events.send(XrTrackerEvent::Disconnected {
+                entity,
+                role: tracker.role,
+            });
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        tracker.persistent_path = persistent_path;
+        tracker.tracked = pose.is_some();
+        if let Some(pose) = pose {
+            *transform = pose;
+        }
+    }
+    for (role, (persistent_path, pose)) in connected {
+        let entity = commands
+            .spawn((
+                SpatialBundle::from_transform(pose.unwrap_or_default()),
+                XrTracker {
+                    role,
+                    persistent_path,
+                    tracked: pose.is_some(),
+                },
+                OpenXRTracker,
+            ))
+            .id();
+        events.send(XrTrackerEvent::Connected { entity, role });
+    }
+}
+
+/// Locates the connected trackers again, for [`XrLateLatchingPlugin`].
+///
+/// [`XrLateLatchingPlugin`]: crate::late_latching::XrLateLatchingPlugin
+pub(crate) fn late_latch_trackers(
+    tracker_spaces: Res<TrackerSpaces>,
+    input: Res<XrInput>,
+    frame_state: Res<XrFrameState>,
+    mut query: Query<(&XrTracker, &mut Transform)>,
+) {
+    let time = frame_state.lock().unwrap().predicted_display_time;
+    for (tracker, mut transform) in &mut query {
+        if !tracker.tracked {
+            continue;
+        }
+        let Some(space) = tracker_spaces
+            .spaces
+            .iter()
+            .find(|space| space.role == tracker.role)
+        else {
+            continue;
+        };
+        let Ok(location) = space.space.locate(&input.stage, time) else {
+            continue;
+        };
+        if location.location_flags.contains(
+            xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID,
+        ) {
+            *transform = Transform::from_translation(location.pose.position.to_vec3())
+                .with_rotation(location.pose.orientation.to_quat());
+        }
+    }
+}
+
+fn vive_tracker_paths(
+    instance: &xr::Instance,
+    enumerate: EnumerateViveTrackerPathsHTCX,
+) -> xr::Result<Vec<ViveTrackerPathsHTCX>> {
+    let empty = ViveTrackerPathsHTCX {
+        ty: TYPE_VIVE_TRACKER_PATHS_HTCX,
+        next: ptr::null_mut(),
+        persistent_path: sys::Path::NULL,
+        role_path: sys::Path::NULL,
+    };
+    let mut count = 0;
+    let result = unsafe { enumerate(instance.as_raw(), 0, &mut count, ptr::null_mut()) };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    let mut paths = vec![empty; count as usize];
+    let result = unsafe {
+        enumerate(
+            instance.as_raw(),
+            paths.len() as u32,
+            &mut count,
+            paths.as_mut_ptr(),
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(result);
+    }
+    paths.truncate(count as usize);
+    Ok(paths)
+}
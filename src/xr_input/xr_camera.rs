@@ -1,10 +1,12 @@
+use crate::resources::{XrFrameState, XrSecondaryViewState};
 use crate::xr_input::{QuatConv, Vec3Conv};
-use crate::{LEFT_XR_TEXTURE_HANDLE, RIGHT_XR_TEXTURE_HANDLE};
+use crate::{SECONDARY_XR_TEXTURE_HANDLE, XR_TEXTURE_HANDLES};
 use bevy::core_pipeline::tonemapping::{DebandDither, Tonemapping};
 use bevy::math::Vec3A;
 use bevy::prelude::*;
 use bevy::render::camera::{
-    CameraMainTextureUsages, CameraProjection, CameraRenderGraph, RenderTarget,
+    CameraMainTextureUsages, CameraProjection, CameraRenderGraph, ManualTextureViewHandle,
+    RenderTarget,
 };
 use bevy::render::primitives::Frustum;
 use bevy::render::view::{ColorGrading, VisibleEntities};
@@ -49,24 +51,47 @@ pub struct XrCameraBundle {
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Component)]
 pub enum XrCameraType {
     Xr(Eye),
+    /// Renders the secondary view configuration, like the first person observer of mixed
+    /// reality capture, while the runtime has it active.
+    Secondary,
     Flatscreen,
 }
 
+/// The primary view a camera renders, the discriminant is the index of the view. A mono view
+/// configuration only has [`Eye::Left`], the focus views only exist with Varjo quad views.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub enum Eye {
     Left = 0,
     Right = 1,
+    LeftFocus = 2,
+    RightFocus = 3,
+}
+
+impl Eye {
+    /// The eyes of a view configuration with `view_count` views.
+    pub fn from_view_count(view_count: usize) -> &'static [Eye] {
+        static EYES: [Eye; 4] = [Eye::Left, Eye::Right, Eye::LeftFocus, Eye::RightFocus];
+        &EYES[..view_count.min(EYES.len())]
+    }
 }
 
 impl XrCameraBundle {
     pub fn new(eye: Eye) -> Self {
+        Self::with_target(XR_TEXTURE_HANDLES[eye as usize], XrCameraType::Xr(eye))
+    }
+
+    /// A camera for the secondary view, it stays inactive until the runtime activates the view.
+    pub fn secondary() -> Self {
+        let mut bundle = Self::with_target(SECONDARY_XR_TEXTURE_HANDLE, XrCameraType::Secondary);
+        bundle.camera.is_active = false;
+        bundle
+    }
+
+    fn with_target(target: ManualTextureViewHandle, xr_camera_type: XrCameraType) -> Self {
         Self {
             camera: Camera {
                 order: -1,
-                target: RenderTarget::TextureView(match eye {
-                    Eye::Left => LEFT_XR_TEXTURE_HANDLE,
-                    Eye::Right => RIGHT_XR_TEXTURE_HANDLE,
-                }),
+                target: RenderTarget::TextureView(target),
                 viewport: None,
                 ..default()
             },
@@ -87,7 +112,7 @@ impl XrCameraBundle {
                     | TextureUsages::TEXTURE_BINDING
                     | TextureUsages::COPY_SRC,
             ),
-            xr_camera_type: XrCameraType::Xr(eye),
+            xr_camera_type,
         }
     }
 }
@@ -259,7 +284,7 @@ pub fn xr_camera_head_sync(
         for (mut transform, camera_type, mut xr_projection) in query.iter_mut() {
             let view_idx = match camera_type {
                 XrCameraType::Xr(eye) => *eye as usize,
-                XrCameraType::Flatscreen => return None,
+                XrCameraType::Secondary | XrCameraType::Flatscreen => continue,
             };
             let v = views.lock().unwrap();
             let view = v.get(view_idx)?;
@@ -271,3 +296,64 @@ pub fn xr_camera_head_sync(
     };
     let _ = f();
 }
+
+/// Added to the eye cameras [`xr_camera_should_render`] turned off while the compositor doesn't
+/// need a frame. Only these are turned back on, cameras the app deactivated itself stay off.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrRenderPaused;
+
+/// Deactivates the eye cameras while the compositor doesn't need a frame, so nothing is rendered
+/// into a swapchain image that was never acquired, and turns them back on afterwards.
+pub fn xr_camera_should_render(
+    mut commands: Commands,
+    frame_state: Res<XrFrameState>,
+    mut query: Query<(Entity, &mut Camera, &XrCameraType, Has<XrRenderPaused>)>,
+) {
+    let should_render = frame_state.lock().unwrap().should_render;
+    for (entity, mut camera, camera_type, paused) in &mut query {
+        if !matches!(camera_type, XrCameraType::Xr(_)) {
+            continue;
+        }
+        // Checked every frame, cameras spawned or activated while not rendering are paused too
+        if !should_render && camera.is_active {
+            camera.is_active = false;
+            commands.entity(entity).insert(XrRenderPaused);
+        } else if should_render && paused {
+            camera.is_active = true;
+            commands.entity(entity).remove::<XrRenderPaused>();
+        }
+    }
+}
+
+/// Moves the secondary view camera to the located secondary view and only keeps it active while
+/// the runtime wants that view rendered.
+pub fn xr_secondary_camera_sync(
+    frame_state: Res<XrFrameState>,
+    secondary_view: Res<XrSecondaryViewState>,
+    mut query: Query<(
+        &mut Camera,
+        &mut Transform,
+        &mut XRProjection,
+        &XrCameraType,
+    )>,
+) {
+    let should_render = frame_state.lock().unwrap().should_render;
+    for (mut camera, mut transform, mut xr_projection, camera_type) in &mut query {
+        if *camera_type != XrCameraType::Secondary {
+            continue;
+        }
+        let view = secondary_view
+            .view
+            .as_ref()
+            .filter(|_| secondary_view.active);
+        let is_active = should_render && view.is_some();
+        if camera.is_active != is_active {
+            camera.is_active = is_active;
+        }
+        if let Some(view) = view {
+            xr_projection.fov = view.fov;
+            transform.rotation = view.pose.orientation.to_quat();
+            transform.translation = view.pose.position.to_vec3();
+        }
+    }
+}